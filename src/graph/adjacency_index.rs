@@ -0,0 +1,403 @@
+use petgraph::Direction;
+use std::collections::{BTreeMap, HashMap};
+use std::iter;
+use std::ops::Bound;
+
+/// Parallel edges of the same type between the same two nodes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) struct EdgeBundle {
+    pub from: usize,
+    pub edge_type: usize,
+    pub to: usize,
+    /// How many edges there are. Adding an edge does not check for existing ones, so there may be
+    /// more than one.
+    pub count: usize,
+    /// When the first of these edges was added, relative to all other edges in the index.
+    pub age: u64,
+}
+
+/// Nodes on the other end of a set of edges, kept sorted by ID. Each one maps to how many edges
+/// lead there.
+type Neighbors = BTreeMap<usize, usize>;
+
+/// Edges touching a single node.
+#[derive(Default)]
+struct NodeEdges {
+    /// Neighbors keyed by direction and edge type.
+    by_type: HashMap<(Direction, usize), Neighbors>,
+    /// Neighbors across all edge types, keyed by direction.
+    all: HashMap<Direction, Neighbors>,
+}
+
+impl NodeEdges {
+    fn neighbors(&self, direction: Direction, edge_type: Option<usize>) -> Option<&Neighbors> {
+        match edge_type {
+            Some(t) => self.by_type.get(&(direction, t)),
+            None => self.all.get(&direction),
+        }
+    }
+
+    fn add(&mut self, direction: Direction, edge_type: usize, other: usize, count: usize) {
+        *self
+            .by_type
+            .entry((direction, edge_type))
+            .or_default()
+            .entry(other)
+            .or_default() += count;
+        *self
+            .all
+            .entry(direction)
+            .or_default()
+            .entry(other)
+            .or_default() += count;
+    }
+
+    fn remove(&mut self, direction: Direction, edge_type: usize, other: usize, count: usize) {
+        decrement(&mut self.by_type, (direction, edge_type), other, count);
+        decrement(&mut self.all, direction, other, count);
+    }
+}
+
+/// Remove `count` edges leading to `other` from a set of neighbors, cleaning up after any
+/// neighbors or sets that end up empty.
+fn decrement<K: Eq + std::hash::Hash>(
+    sets: &mut HashMap<K, Neighbors>,
+    key: K,
+    other: usize,
+    count: usize,
+) {
+    if let Some(neighbors) = sets.get_mut(&key) {
+        if let Some(existing) = neighbors.get_mut(&other) {
+            *existing -= count;
+            if *existing == 0 {
+                neighbors.remove(&other);
+            }
+        }
+        if neighbors.is_empty() {
+            sets.remove(&key);
+        }
+    }
+}
+
+/// Index of all edges in a graph, keyed by node, direction, and edge type, so that neighbors of a
+/// node never have to be filtered or sorted when they're retrieved.
+#[derive(Default)]
+pub(super) struct AdjacencyIndex {
+    nodes: HashMap<usize, NodeEdges>,
+    /// Number of edges for every (from, edge type, to) triple, so that edges can be checked for
+    /// in constant time.
+    counts: HashMap<(usize, usize, usize), usize>,
+    /// When each (from, edge type, to) triple first got an edge, so that edges can be listed in
+    /// the order they were added.
+    ages: HashMap<(usize, usize, usize), u64>,
+    next_age: u64,
+}
+
+impl AdjacencyIndex {
+    /// Add `count` edges of the same type between two nodes.
+    pub fn add(&mut self, from: usize, edge_type: usize, to: usize, count: usize) {
+        let age = self.next_age;
+        self.insert(from, edge_type, to, count, age);
+    }
+
+    /// Add removed edges back in, in the same place in the order of edges as before.
+    pub fn restore(&mut self, edges: EdgeBundle) {
+        self.insert(
+            edges.from,
+            edges.edge_type,
+            edges.to,
+            edges.count,
+            edges.age,
+        );
+    }
+
+    fn insert(&mut self, from: usize, edge_type: usize, to: usize, count: usize, age: u64) {
+        if count == 0 {
+            return;
+        }
+        let key = (from, edge_type, to);
+        let existing = self.counts.entry(key).or_default();
+        if *existing == 0 {
+            self.ages.insert(key, age);
+            self.next_age = self.next_age.max(age + 1);
+        }
+        *existing += count;
+        let from_edges = self.nodes.entry(from).or_default();
+        from_edges.add(Direction::Outgoing, edge_type, to, count);
+        let to_edges = self.nodes.entry(to).or_default();
+        to_edges.add(Direction::Incoming, edge_type, from, count);
+    }
+
+    /// Remove a single edge, if one exists.
+    pub fn remove_one(&mut self, from: usize, edge_type: usize, to: usize) {
+        if self.count(from, edge_type, to) > 0 {
+            self.remove(from, edge_type, to, 1);
+        }
+    }
+
+    /// Remove all edges of the given type between two nodes, returning what was removed.
+    pub fn remove_all(&mut self, from: usize, edge_type: usize, to: usize) -> Option<EdgeBundle> {
+        let count = self.count(from, edge_type, to);
+        if count == 0 {
+            return None;
+        }
+        let removed = self.bundle((from, edge_type, to), count);
+        self.remove(from, edge_type, to, count);
+        Some(removed)
+    }
+
+    /// Remove all edges going into or out of a node, as well as all edges that have the node as
+    /// their type, returning what was removed.
+    pub fn remove_node(&mut self, id: usize) -> Vec<EdgeBundle> {
+        let mut edges: Vec<EdgeBundle> = self
+            .counts
+            .iter()
+            .filter(|((from, edge_type, to), _)| *edge_type == id && *from != id && *to != id)
+            .map(|(key, count)| self.bundle(*key, *count))
+            .collect();
+        if let Some(node_edges) = self.nodes.get(&id) {
+            for ((direction, edge_type), neighbors) in &node_edges.by_type {
+                for (other, count) in neighbors {
+                    let (from, to) = match direction {
+                        Direction::Outgoing => (id, *other),
+                        // self-loops were already picked up as outgoing edges
+                        Direction::Incoming if *other == id => continue,
+                        Direction::Incoming => (*other, id),
+                    };
+                    edges.push(self.bundle((from, *edge_type, to), *count));
+                }
+            }
+        }
+        edges.sort_unstable();
+        for edge in &edges {
+            self.remove(edge.from, edge.edge_type, edge.to, edge.count);
+        }
+        self.nodes.remove(&id);
+        edges
+    }
+
+    /// Describe the edges between two nodes of a certain type.
+    fn bundle(&self, key: (usize, usize, usize), count: usize) -> EdgeBundle {
+        let (from, edge_type, to) = key;
+        EdgeBundle {
+            from,
+            edge_type,
+            to,
+            count,
+            age: self.ages[&key],
+        }
+    }
+
+    /// Number of edges of the given type between two nodes.
+    pub fn count(&self, from: usize, edge_type: usize, to: usize) -> usize {
+        self.counts
+            .get(&(from, edge_type, to))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// The other ends of all edges in a certain direction, optionally filtered by type, in
+    /// ascending order. Nodes connected by several edges come up once for each edge.
+    pub fn neighbors(
+        &self,
+        id: usize,
+        direction: Direction,
+        edge_type: Option<usize>,
+    ) -> impl Iterator<Item = usize> + '_ {
+        self.nodes
+            .get(&id)
+            .and_then(|node_edges| node_edges.neighbors(direction, edge_type))
+            .into_iter()
+            .flatten()
+            .flat_map(|(other, count)| iter::repeat(*other).take(*count))
+    }
+
+    /// The nodes that a node links to via outgoing edges of a certain type, in the order that the
+    /// first edge to each of them was added.
+    pub fn outgoing_in_order(&self, from: usize, edge_type: usize) -> Vec<usize> {
+        let mut nodes: Vec<usize> = self
+            .nodes
+            .get(&from)
+            .and_then(|node_edges| node_edges.neighbors(Direction::Outgoing, Some(edge_type)))
+            .into_iter()
+            .flat_map(|neighbors| neighbors.keys().copied())
+            .collect();
+        nodes.sort_by_key(|to| self.ages[&(from, edge_type, *to)]);
+        nodes
+    }
+
+    /// Like `neighbors`, but starting after the node with ID `after`, and with each node only
+    /// coming up once.
+    pub fn distinct_neighbors_after(
+        &self,
+        id: usize,
+        direction: Direction,
+        edge_type: Option<usize>,
+        after: Option<usize>,
+    ) -> impl Iterator<Item = usize> + '_ {
+        let start = match after {
+            Some(a) => Bound::Excluded(a),
+            None => Bound::Unbounded,
+        };
+        self.nodes
+            .get(&id)
+            .and_then(|node_edges| node_edges.neighbors(direction, edge_type))
+            .into_iter()
+            .flat_map(move |neighbors| neighbors.range((start, Bound::Unbounded)))
+            .map(|(other, _)| *other)
+    }
+
+    /// All edges in the graph, sorted by source, type, and then target.
+    pub fn edges(&self) -> Vec<EdgeBundle> {
+        let mut edges: Vec<EdgeBundle> = self
+            .counts
+            .iter()
+            .map(|(key, count)| self.bundle(*key, *count))
+            .collect();
+        edges.sort_unstable();
+        edges
+    }
+
+    fn remove(&mut self, from: usize, edge_type: usize, to: usize, count: usize) {
+        let key = (from, edge_type, to);
+        if let Some(existing) = self.counts.get_mut(&key) {
+            *existing -= count;
+            if *existing == 0 {
+                self.counts.remove(&key);
+                self.ages.remove(&key);
+            }
+        }
+        if let Some(from_edges) = self.nodes.get_mut(&from) {
+            from_edges.remove(Direction::Outgoing, edge_type, to, count);
+        }
+        if let Some(to_edges) = self.nodes.get_mut(&to) {
+            to_edges.remove(Direction::Incoming, edge_type, from, count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sorted_neighbors() {
+        let mut index = AdjacencyIndex::default();
+        index.add(0, 1, 5, 1);
+        index.add(0, 1, 3, 2);
+        index.add(0, 2, 4, 1);
+        assert_eq!(
+            index
+                .neighbors(0, Direction::Outgoing, Some(1))
+                .collect::<Vec<_>>(),
+            vec![3, 3, 5]
+        );
+        assert_eq!(
+            index
+                .neighbors(0, Direction::Outgoing, None)
+                .collect::<Vec<_>>(),
+            vec![3, 3, 4, 5]
+        );
+        assert_eq!(
+            index
+                .distinct_neighbors_after(0, Direction::Outgoing, None, Some(3))
+                .collect::<Vec<_>>(),
+            vec![4, 5]
+        );
+        assert_eq!(
+            index
+                .neighbors(3, Direction::Incoming, Some(1))
+                .collect::<Vec<_>>(),
+            vec![0, 0]
+        );
+    }
+
+    #[test]
+    fn test_remove_node_with_self_loop() {
+        let mut index = AdjacencyIndex::default();
+        index.add(0, 1, 0, 1);
+        index.add(0, 1, 2, 1);
+        index.add(2, 1, 0, 1);
+        assert_eq!(
+            index.remove_node(0),
+            vec![
+                EdgeBundle {
+                    from: 0,
+                    edge_type: 1,
+                    to: 0,
+                    count: 1,
+                    age: 0
+                },
+                EdgeBundle {
+                    from: 0,
+                    edge_type: 1,
+                    to: 2,
+                    count: 1,
+                    age: 1
+                },
+                EdgeBundle {
+                    from: 2,
+                    edge_type: 1,
+                    to: 0,
+                    count: 1,
+                    age: 2
+                },
+            ]
+        );
+        assert_eq!(index.edges(), vec![]);
+        assert_eq!(index.neighbors(2, Direction::Incoming, None).count(), 0);
+    }
+
+    #[test]
+    fn test_remove_edge_type_node() {
+        let mut index = AdjacencyIndex::default();
+        index.add(0, 1, 2, 1);
+        index.add(0, 3, 2, 1);
+        assert_eq!(
+            index.remove_node(1),
+            vec![EdgeBundle {
+                from: 0,
+                edge_type: 1,
+                to: 2,
+                count: 1,
+                age: 0
+            }]
+        );
+        assert_eq!(index.count(0, 1, 2), 0);
+        assert_eq!(
+            index
+                .neighbors(0, Direction::Outgoing, None)
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn test_outgoing_in_order() {
+        let mut index = AdjacencyIndex::default();
+        index.add(0, 1, 5, 1);
+        index.add(0, 1, 3, 1);
+        index.add(0, 1, 5, 1);
+        index.add(0, 2, 4, 1);
+        assert_eq!(index.outgoing_in_order(0, 1), vec![5, 3]);
+
+        // restored edges go back to where they were
+        let removed = index.remove_all(0, 1, 5).unwrap();
+        index.add(0, 1, 6, 1);
+        index.restore(removed);
+        assert_eq!(index.outgoing_in_order(0, 1), vec![5, 3, 6]);
+        assert_eq!(index.count(0, 1, 5), 2);
+    }
+
+    #[test]
+    fn test_remove_one() {
+        let mut index = AdjacencyIndex::default();
+        index.add(0, 1, 2, 2);
+        index.remove_one(0, 1, 2);
+        assert_eq!(index.count(0, 1, 2), 1);
+        index.remove_one(0, 1, 2);
+        index.remove_one(0, 1, 2);
+        assert_eq!(index.count(0, 1, 2), 0);
+        assert_eq!(index.remove_all(0, 1, 2), None);
+    }
+}
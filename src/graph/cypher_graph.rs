@@ -1,137 +1,260 @@
-use super::value_wrappers::{unwrap_value, KBValue, StrongValue, WeakValue};
-use super::Graph;
+use super::value_wrappers::{unwrap_value, KBRc, KBValue, StrongValue, WeakValue};
+#[cfg(feature = "snapshot")]
+use super::GraphSnapshot;
+use super::{Graph, GraphError, GraphResult};
+use rusted_cypher::cypher::result::CypherResult;
+use rusted_cypher::cypher::transaction::{Started, Transaction};
+use rusted_cypher::cypher::Statement;
 use rusted_cypher::cypher_stmt;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt::Display;
 use std::rc::Rc;
 
+/// Report a failure from the Neo4j client as a backend error.
+fn backend_error<E: Display>(e: E) -> GraphError {
+    GraphError::Backend(e.to_string())
+}
+
+/// Execute a query on the graph, returning early from the surrounding function if the query fails.
+/// When a column is specified, the values of that column for all returned rows are collected into
+/// a vec.
 macro_rules! exec_db {
     ($db:expr, $query:expr) => {
-        $db.exec($query).unwrap()
+        $db.exec($query).map_err(backend_error)?
     };
     ($db:expr, $query:expr, $bindings:tt) => {
-        $db.exec(cypher_stmt!($query, $bindings).unwrap()).unwrap()
+        $db.exec(cypher_stmt!($query, $bindings).map_err(backend_error)?)
+            .map_err(backend_error)?
+    };
+    ( $db:expr, $query:expr, $bindings:tt, { $k:expr => $v:ty } ) => {
+        exec_db!($db, $query, $bindings)
+            .rows()
+            .map(|r| r.get::<$v>($k).map_err(backend_error))
+            .collect::<GraphResult<Vec<$v>>>()?
     };
-    ( $db:expr, $query:expr, $bindings:tt, { $( $k:expr => $v:ty ),+ } ) => {
-        $db.exec(cypher_stmt!($query, $bindings).unwrap()).unwrap().rows()
-        $(
-            .map(|r| r.get::<$v>($k).unwrap())
-        )*
-    }
 }
 
 /// Graph that is backed by a Neo4j graph database.
+///
+/// Transactions are native Neo4j transactions. Neo4j has no notion of nested transactions, so
+/// inner transactions only get folded into the outermost one, and cannot be rolled back on their
+/// own. Trying to do so marks the outermost transaction as rollback-only.
 pub struct CypherGraph {
-    db: rusted_cypher::GraphClient,
+    /// The client is leaked so that an open transaction can borrow it for as long as needed. This
+    /// is fine because graphs are generally bound once and kept around for the whole program.
+    db: &'static rusted_cypher::GraphClient,
+    transaction: RefCell<Option<Transaction<'static, Started>>>,
+    /// Number of transactions currently open, including inner ones.
+    transaction_depth: usize,
+    /// Whether an inner transaction failed to roll back, so that the outermost one must not be
+    /// committed.
+    rollback_only: bool,
 }
 
 impl CypherGraph {
     /// Constructs an empty new in-memory graph
     pub fn new(uri: &str) -> Self {
         match rusted_cypher::GraphClient::connect(uri) {
-            Ok(client) => CypherGraph { db: client },
+            Ok(client) => CypherGraph {
+                db: Box::leak(Box::new(client)),
+                transaction: RefCell::new(None),
+                transaction_depth: 0,
+                rollback_only: false,
+            },
             Err(e) => panic!(
                 "Couldn't connect to Neo4j database at {}. Error: {}",
                 uri, e
             ),
         }
     }
+
+    /// Execute a statement, as part of the open transaction if there is one.
+    fn exec<S: Into<Statement>>(
+        &self,
+        statement: S,
+    ) -> Result<CypherResult, rusted_cypher::GraphError> {
+        match self.transaction.borrow_mut().as_mut() {
+            Some(transaction) => transaction.exec(statement),
+            None => self.db.exec(statement),
+        }
+    }
+
+    /// Return an error if the node with the given ID does not exist.
+    fn ensure_node(&self, id: usize) -> GraphResult<()> {
+        let count = exec_db!(self, "MATCH (n) WHERE ID(n) = {id} RETURN COUNT(n)", {
+            "id" => id
+        }, {
+            "COUNT(n)" => usize
+        });
+        match count.first() {
+            Some(c) if *c > 0 => Ok(()),
+            _ => Err(GraphError::UnknownNode(id)),
+        }
+    }
+
+    /// Delete a node along with everything that refers to it: its own edges, its flag on other
+    /// nodes, and edges that use it as their type.
+    fn purge_node(&self, id: usize) -> GraphResult<()> {
+        exec_db!(
+            self,
+            format!("MATCH (n) WHERE n.f_{0} IS NOT NULL REMOVE n.f_{0}", id).as_str()
+        );
+        exec_db!(self, "MATCH ()-[r:R { id: {id} }]->() DELETE r", {
+            "id" => id
+        });
+        exec_db!(self, "MATCH (n) WHERE ID(n) = {id} DETACH DELETE n", {
+            "id" => id
+        });
+        Ok(())
+    }
+
+    /// Get the only value returned by a query that matches a single node by ID.
+    fn single_node_result<T>(id: usize, results: Vec<T>) -> GraphResult<T> {
+        results
+            .into_iter()
+            .next()
+            .ok_or(GraphError::UnknownNode(id))
+    }
 }
 
 impl Graph for CypherGraph {
-    fn size(&self) -> usize {
-        exec_db!(self.db, "MATCH (n) RETURN COUNT(*)")
+    fn try_size(&self) -> GraphResult<usize> {
+        exec_db!(self, "MATCH (n) RETURN COUNT(*)")
             .rows()
             .next()
-            .unwrap()
+            .ok_or_else(|| backend_error("no node count returned"))?
             .get::<usize>("COUNT(*)")
-            .unwrap()
+            .map_err(backend_error)
     }
 
-    fn add_node(&mut self) -> usize {
-        exec_db!(self.db, "CREATE (n) RETURN ID(n)")
+    fn try_add_node(&mut self) -> GraphResult<usize> {
+        exec_db!(self, "CREATE (n) RETURN ID(n)")
             .rows()
             .next()
-            .unwrap()
+            .ok_or_else(|| backend_error("no ID returned for new node"))?
             .get::<usize>("ID(n)")
-            .unwrap()
+            .map_err(backend_error)
+    }
+
+    fn try_remove_node(&mut self, id: usize) -> GraphResult<()> {
+        self.ensure_node(id)?;
+        // Neo4j reuses the IDs of deleted nodes, so nothing may keep referring to this one
+        self.try_begin_transaction()?;
+        match self.purge_node(id) {
+            Ok(()) => self.try_commit(),
+            Err(e) => {
+                let _ = self.try_rollback();
+                Err(e)
+            }
+        }
     }
 
-    fn set_node_value(&mut self, id: usize, value: Rc<dyn KBValue>) {
+    fn try_set_node_value(&mut self, id: usize, value: KBRc<dyn KBValue>) -> GraphResult<()> {
         // todo: see if lifetime ugliness can be cleaned up without cloning
         let unwrapped_value = match value.as_any().downcast_ref::<WeakValue<String>>() {
-            Some(ww) => ww.value().unwrap(),
-            None => unwrap_value::<String>(Some(value)).unwrap(),
-        };
-        exec_db!(self.db, "MATCH (n) WHERE ID(n) = {id} SET n.value = {value}", {
+            Some(ww) => ww.value(),
+            None => unwrap_value::<String>(Some(value)),
+        }
+        .ok_or_else(|| backend_error("only string values can be stored in Neo4j"))?;
+        let ids = exec_db!(self, "MATCH (n) WHERE ID(n) = {id} SET n.value = {value} RETURN ID(n)", {
             "id" => id,
             "value" => unwrapped_value.as_str()
+        }, {
+            "ID(n)" => usize
         });
+        Self::single_node_result(id, ids).map(|_| ())
     }
 
-    fn set_node_name(&mut self, id: usize, name: &str) {
-        exec_db!(self.db, "MATCH (n) WHERE ID(n) = {id} SET n.name = {name}", {
+    fn try_set_node_name(&mut self, id: usize, name: &str) -> GraphResult<()> {
+        let ids = exec_db!(self, "MATCH (n) WHERE ID(n) = {id} SET n.name = {name} RETURN ID(n)", {
             "id" => id,
             "name" => name
+        }, {
+            "ID(n)" => usize
         });
+        Self::single_node_result(id, ids).map(|_| ())
     }
 
-    fn node_name(&self, id: usize) -> Option<Rc<str>> {
-        exec_db!(self.db, "MATCH (n) WHERE ID(n) = {id} RETURN n.name", {
+    fn try_node_name(&self, id: usize) -> GraphResult<Option<Rc<str>>> {
+        let names = exec_db!(self, "MATCH (n) WHERE ID(n) = {id} RETURN n.name", {
             "id" => id
         }, {
             "n.name" => Option<String>
-        })
-        .next()
-        .unwrap()
-        .map(|s| Rc::from(s.as_str()))
+        });
+        Ok(Self::single_node_result(id, names)?.map(|s| Rc::from(s.as_str())))
     }
 
-    fn node_value(&self, id: usize) -> Option<Rc<dyn KBValue>> {
-        exec_db!(self.db, "MATCH (n) WHERE ID(n) = {id} RETURN n.value", {
+    fn try_node_value(&self, id: usize) -> GraphResult<Option<KBRc<dyn KBValue>>> {
+        let values = exec_db!(self, "MATCH (n) WHERE ID(n) = {id} RETURN n.value", {
             "id" => id
         }, {
             "n.value" => Option<String>
-        })
-        .next()
-        .unwrap()
-        .map(|s| {
+        });
+        Ok(Self::single_node_result(id, values)?.map(|s| {
             // define rc before returning to help compiler with typing here
-            let rc: Rc<dyn KBValue> = Rc::new(StrongValue::new(s));
+            let rc: KBRc<dyn KBValue> = KBRc::new(StrongValue::new(s));
             rc
-        })
+        }))
     }
 
-    fn lookup(&self, name: &str) -> Vec<usize> {
-        exec_db!(self.db, "MATCH (n) WHERE n.name = {name} RETURN ID(n) ORDER BY ID(n)", {
-            "name" => name
-        }, {
-            "ID(n)" => usize
-        })
-        .collect()
+    fn try_lookup(&self, name: &str) -> GraphResult<Vec<usize>> {
+        Ok(
+            exec_db!(self, "MATCH (n) WHERE n.name = {name} RETURN ID(n) ORDER BY ID(n)", {
+                "name" => name
+            }, {
+                "ID(n)" => usize
+            }),
+        )
     }
 
-    fn add_flag(&mut self, id: usize, flag: usize) {
-        exec_db!(
-        self.db,
-            &format!("MATCH (n) WHERE ID(n) = {{id}} SET n.f_{} = true", flag), {
+    fn try_add_flag(&mut self, id: usize, flag: usize) -> GraphResult<()> {
+        let ids = exec_db!(
+        self,
+            &format!("MATCH (n) WHERE ID(n) = {{id}} SET n.f_{} = true RETURN ID(n)", flag), {
+                "id" => id
+            }, {
+                "ID(n)" => usize
+            });
+        Self::single_node_result(id, ids).map(|_| ())
+    }
+
+    fn try_remove_flag(&mut self, id: usize, flag: usize) -> GraphResult<()> {
+        let ids = exec_db!(
+        self,
+            &format!("MATCH (n) WHERE ID(n) = {{id}} REMOVE n.f_{} RETURN ID(n)", flag), {
                 "id" => id
+            }, {
+                "ID(n)" => usize
             });
+        Self::single_node_result(id, ids).map(|_| ())
     }
 
-    fn has_flag(&self, id: usize, flag: usize) -> bool {
-        exec_db!(self.db, &format!("MATCH (n) WHERE ID(n) = {{id}} RETURN n.f_{}", flag), {
+    fn try_has_flag(&self, id: usize, flag: usize) -> GraphResult<bool> {
+        let flags = exec_db!(self, &format!("MATCH (n) WHERE ID(n) = {{id}} RETURN n.f_{}", flag), {
             "id" => id
-        })
-        .rows()
-        .next()
-        .unwrap()
-        .get::<bool>(&format!("n.f_{}", flag))
-        .unwrap_or(false)
+        }, {
+            &format!("n.f_{}", flag) => Option<bool>
+        });
+        Ok(Self::single_node_result(id, flags)?.unwrap_or(false))
     }
 
-    fn add_edge(&mut self, from: usize, edge_type: usize, to: usize) {
+    fn try_nodes_with_flag(&self, flag: usize) -> GraphResult<Vec<usize>> {
+        let query = format!(
+            "MATCH (n) WHERE n.f_{} = true RETURN ID(n) ORDER BY ID(n)",
+            flag
+        );
+        exec_db!(self, query.as_str())
+            .rows()
+            .map(|r| r.get::<usize>("ID(n)").map_err(backend_error))
+            .collect()
+    }
+
+    fn try_add_edge(&mut self, from: usize, edge_type: usize, to: usize) -> GraphResult<()> {
+        self.ensure_node(from)?;
+        self.ensure_node(to)?;
         exec_db!(
-        self.db,
+        self,
             "MATCH (a), (b) \
             WHERE ID(a) = {from} AND ID(b) = {to} \
             CREATE (a)-[r:R { id: {edge} }]->(b)", {
@@ -139,11 +262,29 @@ impl Graph for CypherGraph {
                 "to" => to,
                 "edge" => edge_type
             });
+        Ok(())
     }
 
-    fn has_edge(&self, from: usize, edge_type: usize, to: usize) -> bool {
+    fn try_remove_edge(&mut self, from: usize, edge_type: usize, to: usize) -> GraphResult<()> {
+        self.ensure_node(from)?;
+        self.ensure_node(to)?;
         exec_db!(
-        self.db,
+        self,
+            "MATCH (a)-[r:R { id: {edge} }]->(b) \
+            WHERE ID(a) = {from} AND ID(b) = {to} \
+            DELETE r", {
+                "from" => from,
+                "edge" => edge_type,
+                "to" => to
+            });
+        Ok(())
+    }
+
+    fn try_has_edge(&self, from: usize, edge_type: usize, to: usize) -> GraphResult<bool> {
+        self.ensure_node(from)?;
+        self.ensure_node(to)?;
+        let counts = exec_db!(
+        self,
             "MATCH (a)-[r:R { id: {edge} }]->(b) \
             WHERE ID(a) = {from} AND ID(b) = {to} \
             RETURN COUNT(r)", {
@@ -152,15 +293,14 @@ impl Graph for CypherGraph {
                 "to" => to
             }, {
                 "COUNT(r)" => usize
-            })
-        .next()
-        .unwrap()
-            > 0
+            });
+        Ok(counts.first().copied().unwrap_or(0) > 0)
     }
 
-    fn outgoing_nodes(&self, from: usize, edge_type: usize) -> Vec<usize> {
-        exec_db!(
-        self.db,
+    fn try_outgoing_nodes(&self, from: usize, edge_type: usize) -> GraphResult<Vec<usize>> {
+        self.ensure_node(from)?;
+        Ok(exec_db!(
+        self,
             "MATCH (a)-[r:R { id: {edge} }]->(b) \
             WHERE ID(a) = {from} \
             RETURN ID(b) ORDER BY ID(b)", {
@@ -168,13 +308,13 @@ impl Graph for CypherGraph {
                 "edge" => edge_type
             }, {
                 "ID(b)" => usize
-            })
-        .collect()
+            }))
     }
 
-    fn incoming_nodes(&self, to: usize, edge_type: usize) -> Vec<usize> {
-        exec_db!(
-        self.db,
+    fn try_incoming_nodes(&self, to: usize, edge_type: usize) -> GraphResult<Vec<usize>> {
+        self.ensure_node(to)?;
+        Ok(exec_db!(
+        self,
             "MATCH (a)<-[r:R { id: {edge} }]-(b) \
             WHERE ID(a) = {to} \
             RETURN ID(b) ORDER BY ID(b)", {
@@ -182,60 +322,60 @@ impl Graph for CypherGraph {
                 "edge" => edge_type
             }, {
                 "ID(b)" => usize
-            })
-        .collect()
+            }))
     }
 
-    fn all_outgoing_nodes(&self, from: usize) -> Vec<usize> {
-        exec_db!(self.db, "MATCH (a)-->(b) WHERE ID(a) = {from} RETURN ID(b) ORDER BY ID(b)", {
-            "from" => from
-        }, {
-            "ID(b)" => usize
-        })
-        .collect()
+    fn try_all_outgoing_nodes(&self, from: usize) -> GraphResult<Vec<usize>> {
+        self.ensure_node(from)?;
+        Ok(
+            exec_db!(self, "MATCH (a)-->(b) WHERE ID(a) = {from} RETURN ID(b) ORDER BY ID(b)", {
+                "from" => from
+            }, {
+                "ID(b)" => usize
+            }),
+        )
     }
 
-    fn all_incoming_nodes(&self, to: usize) -> Vec<usize> {
-        exec_db!(self.db, "MATCH (a)<--(b) WHERE ID(a) = {to} RETURN ID(b) ORDER BY ID(b)", {
-            "to" => to
-        }, {
-            "ID(b)" => usize
-        })
-        .collect()
+    fn try_all_incoming_nodes(&self, to: usize) -> GraphResult<Vec<usize>> {
+        self.ensure_node(to)?;
+        Ok(
+            exec_db!(self, "MATCH (a)<--(b) WHERE ID(a) = {to} RETURN ID(b) ORDER BY ID(b)", {
+                "to" => to
+            }, {
+                "ID(b)" => usize
+            }),
+        )
     }
 
-    fn into_dot(&self) -> String {
+    fn try_into_dot(&self) -> GraphResult<String> {
         let mut node_names = HashMap::new();
-        let nodes: Vec<String> = exec_db!(self.db, "MATCH (n) RETURN ID(n), n.name ORDER BY ID(n)")
-            .rows()
-            .map(|r| {
-                let id = r.get::<usize>("ID(n)").unwrap();
-                let name = r
-                    .get::<Option<String>>("n.name")
-                    .unwrap()
-                    .unwrap_or_else(|| id.to_string());
-                let row_str = format!("    {} [ label = \"{}\" ]\n", id, name);
-                node_names.insert(id, name);
-                row_str
-            })
-            .collect();
-        let relations: Vec<String> = exec_db!(
-            self.db,
+        let mut nodes = Vec::<String>::new();
+        for r in exec_db!(self, "MATCH (n) RETURN ID(n), n.name ORDER BY ID(n)").rows() {
+            let id = r.get::<usize>("ID(n)").map_err(backend_error)?;
+            let name = r
+                .get::<Option<String>>("n.name")
+                .map_err(backend_error)?
+                .unwrap_or_else(|| id.to_string());
+            nodes.push(format!("    {} [ label = \"{}\" ]\n", id, name));
+            node_names.insert(id, name);
+        }
+        let mut relations = Vec::<String>::new();
+        for r in exec_db!(
+            self,
             "MATCH (a)-[r]->(b) RETURN ID(a), r.id, ID(b) ORDER BY ID(a)"
         )
         .rows()
-        .map(|r| {
-            let from = r.get::<usize>("ID(a)").unwrap();
-            let edge_type = r.get::<usize>("r.id").unwrap();
-            let to = r.get::<usize>("ID(b)").unwrap();
-            format!(
+        {
+            let from = r.get::<usize>("ID(a)").map_err(backend_error)?;
+            let edge_type = r.get::<usize>("r.id").map_err(backend_error)?;
+            let to = r.get::<usize>("ID(b)").map_err(backend_error)?;
+            relations.push(format!(
                 "    {} -> {} [ label = \"{}\" ]\n",
                 from,
                 to,
                 node_names.get(&edge_type).unwrap_or(&edge_type.to_string())
-            )
-        })
-        .collect();
+            ));
+        }
         let mut dot: String = "digraph {\n".to_owned();
         for node in nodes {
             dot.push_str(node.as_str())
@@ -244,7 +384,59 @@ impl Graph for CypherGraph {
             dot.push_str(relation.as_str())
         }
         dot.push('}');
-        dot
+        Ok(dot)
+    }
+
+    fn try_begin_transaction(&mut self) -> GraphResult<()> {
+        if self.transaction_depth == 0 {
+            let (transaction, _) = self.db.transaction().begin().map_err(backend_error)?;
+            *self.transaction.borrow_mut() = Some(transaction);
+        }
+        self.transaction_depth += 1;
+        Ok(())
+    }
+
+    fn try_commit(&mut self) -> GraphResult<()> {
+        match self.transaction_depth {
+            0 => return Err(GraphError::NoTransaction),
+            1 if self.rollback_only => {
+                self.try_rollback()?;
+                return Err(GraphError::RollbackOnly);
+            }
+            1 => {
+                let transaction = self.transaction.borrow_mut().take().unwrap();
+                transaction.commit().map_err(backend_error)?;
+            }
+            _ => (), // changes will be committed along with the outermost transaction
+        }
+        self.transaction_depth -= 1;
+        Ok(())
+    }
+
+    fn try_rollback(&mut self) -> GraphResult<()> {
+        match self.transaction_depth {
+            0 => Err(GraphError::NoTransaction),
+            1 => {
+                self.transaction_depth = 0;
+                self.rollback_only = false;
+                let transaction = self.transaction.borrow_mut().take().unwrap();
+                transaction.rollback().map_err(backend_error)
+            }
+            _ => {
+                self.transaction_depth -= 1;
+                self.rollback_only = true;
+                Err(backend_error(
+                    "Neo4j cannot roll back an inner transaction on its own",
+                ))
+            }
+        }
+    }
+
+    #[cfg(feature = "snapshot")]
+    fn try_snapshot(&self) -> GraphResult<GraphSnapshot> {
+        Err(GraphError::Backend(
+            "Snapshots are not supported for Neo4j graphs".to_owned(),
+        ))
     }
 }
 
@@ -308,14 +500,100 @@ mod tests {
         assert!(g.size() > initial_size);
     }
 
+    #[test]
+    #[ignore]
+    fn test_remove_node() {
+        bind_cypher_graph(TEST_DB_URI);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let edge_type = g.add_node();
+        g.set_node_name(a_id, "Removed A");
+        g.add_edge(a_id, edge_type, b_id);
+        g.remove_node(a_id);
+        assert!(!g.lookup("Removed A").contains(&a_id));
+        assert_eq!(g.all_incoming_nodes(b_id), Vec::<usize>::new());
+    }
+
+    #[test]
+    #[ignore]
+    fn test_remove_node_purges_references() {
+        bind_cypher_graph(TEST_DB_URI);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let removed = g.add_node();
+        g.add_flag(removed, a_id);
+        g.add_flag(a_id, removed);
+        g.add_edge(a_id, removed, b_id);
+        g.remove_node(removed);
+
+        // a new node that gets the same ID starts out with a clean slate
+        assert!(!g.has_flag(a_id, removed));
+        assert_eq!(g.nodes_with_flag(removed), Vec::<usize>::new());
+        assert_eq!(g.all_outgoing_nodes(a_id), Vec::<usize>::new());
+        assert_eq!(g.all_incoming_nodes(b_id), Vec::<usize>::new());
+    }
+
+    #[test]
+    #[ignore]
+    fn test_rollback() {
+        bind_cypher_graph(TEST_DB_URI);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        g.begin_transaction();
+        g.set_node_name(a_id, "Rolled back A");
+        let b_id = g.add_node();
+        g.add_edge(a_id, a_id, b_id);
+        g.rollback();
+        assert_eq!(g.node_name(a_id), None);
+        assert_eq!(g.all_outgoing_nodes(a_id), Vec::<usize>::new());
+    }
+
+    #[test]
+    #[ignore]
+    fn test_nested_commit() {
+        bind_cypher_graph(TEST_DB_URI);
+        let mut g = InjectionGraph::new();
+        g.begin_transaction();
+        let a_id = g.add_node();
+        g.begin_transaction();
+        g.set_node_name(a_id, "Nested A");
+        g.commit();
+        assert!(g.try_rollback().is_ok());
+        assert_eq!(g.try_commit(), Err(GraphError::NoTransaction));
+    }
+
+    #[test]
+    #[ignore]
+    fn test_nested_rollback() {
+        bind_cypher_graph(TEST_DB_URI);
+        let mut g = InjectionGraph::new();
+        g.begin_transaction();
+        let a_id = g.add_node();
+        g.begin_transaction();
+        g.set_node_name(a_id, "Nested A");
+        assert!(g.try_rollback().is_err());
+        // the outer transaction can no longer be committed as if nothing happened
+        assert_eq!(g.try_commit(), Err(GraphError::RollbackOnly));
+        assert_eq!(g.try_node_name(a_id), Err(GraphError::UnknownNode(a_id)));
+        assert_eq!(g.try_commit(), Err(GraphError::NoTransaction));
+
+        // and the next transaction starts afresh
+        g.begin_transaction();
+        let b_id = g.add_node();
+        g.commit();
+        assert_eq!(g.node_name(b_id), None);
+    }
+
     #[test]
     #[ignore]
     fn test_set_node_value() {
         bind_cypher_graph(TEST_DB_URI);
         let mut g = InjectionGraph::new();
         let a_id = g.add_node();
-        let v = Rc::new("5".to_string());
-        g.set_node_value(a_id, Rc::new(WeakValue::new(&v)));
+        let v = KBRc::new("5".to_string());
+        g.set_node_value(a_id, KBRc::new(WeakValue::new(&v)));
         assert_eq!(unwrap_value(g.node_value(a_id)), Some(v));
         assert_eq!(g.node_name(a_id), None);
     }
@@ -336,9 +614,9 @@ mod tests {
         bind_cypher_graph(TEST_DB_URI);
         let mut g = InjectionGraph::new();
         let a_id = g.add_node();
-        let v = Rc::new("5".to_string());
+        let v = KBRc::new("5".to_string());
         g.set_node_name(a_id, "A");
-        g.set_node_value(a_id, Rc::new(WeakValue::new(&v)));
+        g.set_node_value(a_id, KBRc::new(WeakValue::new(&v)));
         assert_eq!(g.node_name(a_id), Some(Rc::from("A")));
         assert_eq!(unwrap_value(g.node_value(a_id)), Some(v));
     }
@@ -379,6 +657,33 @@ mod tests {
         assert!(g.has_flag(a_id, b_id));
     }
 
+    #[test]
+    #[ignore]
+    fn test_remove_flag() {
+        bind_cypher_graph(TEST_DB_URI);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        g.add_flag(a_id, b_id);
+        g.remove_flag(a_id, b_id);
+        assert!(!g.has_flag(a_id, b_id));
+    }
+
+    #[test]
+    #[ignore]
+    fn test_nodes_with_flag() {
+        bind_cypher_graph(TEST_DB_URI);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let flag = g.add_node();
+        g.add_flag(b_id, flag);
+        g.add_flag(a_id, flag);
+        assert_eq!(g.nodes_with_flag(flag), vec![a_id, b_id]);
+        g.remove_flag(a_id, flag);
+        assert_eq!(g.nodes_with_flag(flag), vec![b_id]);
+    }
+
     #[test]
     #[ignore]
     fn test_no_outgoing_node() {
@@ -467,6 +772,22 @@ mod tests {
         assert!(!g.has_edge(b_id, edge_type2, a_id));
     }
 
+    #[test]
+    #[ignore]
+    fn test_remove_edge() {
+        bind_cypher_graph(TEST_DB_URI);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let edge_type1 = g.add_node();
+        let edge_type2 = g.add_node();
+        g.add_edge(a_id, edge_type1, b_id);
+        g.add_edge(a_id, edge_type2, b_id);
+        g.remove_edge(a_id, edge_type1, b_id);
+        assert!(!g.has_edge(a_id, edge_type1, b_id));
+        assert!(g.has_edge(a_id, edge_type2, b_id));
+    }
+
     #[test]
     #[ignore]
     fn test_no_incoming_node() {
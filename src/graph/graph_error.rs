@@ -0,0 +1,86 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result};
+
+/// Result of a fallible graph operation.
+pub type GraphResult<T> = std::result::Result<T, GraphError>;
+
+/// Reasons why a graph operation can fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphError {
+    /// No node with this ID exists in the graph. The node may have been removed, or the ID may
+    /// have come from a different graph.
+    UnknownNode(usize),
+    /// The graph has not been bound to an actual implementation yet.
+    Unbound,
+    /// The graph implementation itself failed, for example because a database query did not go
+    /// through.
+    Backend(String),
+    /// A graph snapshot could not be read or written, or its contents are malformed.
+    Snapshot(String),
+    /// A transaction was committed or rolled back without one having been started.
+    NoTransaction,
+    /// A transaction was committed after one of its inner transactions could not be rolled back on
+    /// its own, so the whole transaction got rolled back instead.
+    RollbackOnly,
+    /// The ancestors of this node cannot be put in an order of precedence, so there is no telling
+    /// which of them should win out when they disagree.
+    InconsistentLinearization(usize),
+}
+
+impl Display for GraphError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            GraphError::UnknownNode(id) => write!(f, "No node with ID {} exists", id),
+            GraphError::Unbound => write!(f, "Initialize graph binding before use"),
+            GraphError::Backend(msg) => write!(f, "Graph backend failure: {}", msg),
+            GraphError::Snapshot(msg) => write!(f, "Invalid graph snapshot: {}", msg),
+            GraphError::NoTransaction => write!(f, "No transaction in progress"),
+            GraphError::RollbackOnly => write!(
+                f,
+                "Transaction rolled back because an inner transaction could not be rolled back on \
+                its own"
+            ),
+            GraphError::InconsistentLinearization(id) => write!(
+                f,
+                "The ancestors of node {} have no consistent order of precedence",
+                id
+            ),
+        }
+    }
+}
+
+impl Error for GraphError {}
+
+/// Unwrap the result of a graph operation for the infallible versions of the graph API, panicking
+/// with a readable message if the operation failed.
+pub(crate) fn expect_graph<T>(result: GraphResult<T>) -> T {
+    result.unwrap_or_else(|e| panic!("{}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            format!("{}", GraphError::UnknownNode(5)),
+            "No node with ID 5 exists"
+        );
+        assert_eq!(
+            format!("{}", GraphError::Unbound),
+            "Initialize graph binding before use"
+        );
+    }
+
+    #[test]
+    fn test_expect_ok() {
+        assert_eq!(expect_graph(Ok(5)), 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "No node with ID 5 exists")]
+    fn test_expect_err() {
+        expect_graph::<usize>(Err(GraphError::UnknownNode(5)));
+    }
+}
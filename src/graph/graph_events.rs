@@ -0,0 +1,217 @@
+use super::KnowledgeBase;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A change that was made to the graph of a KB.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GraphEvent {
+    /// A new node was added.
+    NodeAdded {
+        /// ID of the new node.
+        id: usize,
+    },
+    /// A node was removed, along with all of its edges.
+    NodeRemoved {
+        /// ID of the removed node.
+        id: usize,
+    },
+    /// A node was given a name.
+    NodeNamed {
+        /// ID of the named node.
+        id: usize,
+        /// The node's new name.
+        name: Rc<str>,
+    },
+    /// A node was given a value.
+    ValueSet {
+        /// ID of the node whose value was set.
+        id: usize,
+    },
+    /// A flag was added to a node.
+    FlagAdded {
+        /// ID of the flagged node.
+        id: usize,
+        /// The flag that was added.
+        flag: usize,
+    },
+    /// A flag was removed from a node.
+    FlagRemoved {
+        /// ID of the node that is no longer flagged.
+        id: usize,
+        /// The flag that was removed.
+        flag: usize,
+    },
+    /// An edge was added between two nodes.
+    EdgeAdded {
+        /// ID of the node the edge starts from.
+        from: usize,
+        /// The type of the edge.
+        edge_type: usize,
+        /// ID of the node the edge leads to.
+        to: usize,
+    },
+    /// An edge between two nodes was removed.
+    EdgeRemoved {
+        /// ID of the node the edge started from.
+        from: usize,
+        /// The type of the edge.
+        edge_type: usize,
+        /// ID of the node the edge led to.
+        to: usize,
+    },
+    /// A transaction was rolled back, undoing changes that were already reported. Listeners that
+    /// mirror the graph should refresh their view of it.
+    RolledBack,
+}
+
+/// Which events a listener wants to hear about.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EventFilter {
+    /// Every change to the graph.
+    All,
+    /// Only edges of this type being added or removed.
+    EdgeType(usize),
+    /// Only this flag being added or removed.
+    Flag(usize),
+}
+
+impl EventFilter {
+    /// Whether or not the event passes this filter. Rollbacks pass every filter, since they may
+    /// undo any kind of change.
+    pub fn matches(&self, event: &GraphEvent) -> bool {
+        match (self, event) {
+            (EventFilter::All, _) | (_, GraphEvent::RolledBack) => true,
+            (EventFilter::EdgeType(t), GraphEvent::EdgeAdded { edge_type, .. })
+            | (EventFilter::EdgeType(t), GraphEvent::EdgeRemoved { edge_type, .. }) => {
+                t == edge_type
+            }
+            (EventFilter::Flag(f), GraphEvent::FlagAdded { flag, .. })
+            | (EventFilter::Flag(f), GraphEvent::FlagRemoved { flag, .. }) => f == flag,
+            _ => false,
+        }
+    }
+}
+
+/// Handle for a registered listener, used to unsubscribe it later.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Subscription {
+    kb: KnowledgeBase,
+    id: usize,
+}
+
+/// Callback that gets notified of graph changes.
+type Listener = Rc<RefCell<Box<dyn FnMut(&GraphEvent)>>>;
+
+/// All listeners registered on this thread.
+#[derive(Default)]
+struct Listeners {
+    next_id: usize,
+    by_kb: HashMap<KnowledgeBase, Vec<(usize, EventFilter, Listener)>>,
+}
+
+thread_local! {
+    static LISTENERS: RefCell<Listeners> = RefCell::new(Listeners::default());
+}
+
+/// Register a listener for changes to the given KB.
+pub(super) fn subscribe(
+    kb: KnowledgeBase,
+    filter: EventFilter,
+    callback: Box<dyn FnMut(&GraphEvent)>,
+) -> Subscription {
+    LISTENERS.with(|listeners| {
+        let mut listeners = listeners.borrow_mut();
+        let id = listeners.next_id;
+        listeners.next_id += 1;
+        listeners
+            .by_kb
+            .entry(kb)
+            .or_default()
+            .push((id, filter, Rc::new(RefCell::new(callback))));
+        Subscription { kb, id }
+    })
+}
+
+/// Remove a listener. Returns false if the listener was already removed.
+pub(super) fn unsubscribe(subscription: Subscription) -> bool {
+    LISTENERS.with(|listeners| {
+        let mut listeners = listeners.borrow_mut();
+        match listeners.by_kb.get_mut(&subscription.kb) {
+            Some(kb_listeners) => {
+                let count = kb_listeners.len();
+                kb_listeners.retain(|(id, _, _)| *id != subscription.id);
+                kb_listeners.len() < count
+            }
+            None => false,
+        }
+    })
+}
+
+/// Notify all interested listeners of a change to the given KB.
+pub(super) fn emit(kb: KnowledgeBase, event: GraphEvent) {
+    // collect the listeners first, so that they are free to (un)subscribe or query the graph
+    let interested: Vec<Listener> = LISTENERS.with(|listeners| {
+        listeners
+            .borrow()
+            .by_kb
+            .get(&kb)
+            .map(|kb_listeners| {
+                kb_listeners
+                    .iter()
+                    .filter(|(_, filter, _)| filter.matches(&event))
+                    .map(|(_, _, listener)| listener.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    });
+    for listener in interested {
+        // a listener that changes the graph itself doesn't get told about its own changes
+        if let Ok(mut callback) = listener.try_borrow_mut() {
+            callback(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_all() {
+        let filter = EventFilter::All;
+        assert!(filter.matches(&GraphEvent::NodeAdded { id: 1 }));
+        assert!(filter.matches(&GraphEvent::FlagAdded { id: 1, flag: 2 }));
+    }
+
+    #[test]
+    fn test_filter_edge_type() {
+        let filter = EventFilter::EdgeType(2);
+        assert!(filter.matches(&GraphEvent::EdgeAdded {
+            from: 1,
+            edge_type: 2,
+            to: 3
+        }));
+        assert!(filter.matches(&GraphEvent::EdgeRemoved {
+            from: 1,
+            edge_type: 2,
+            to: 3
+        }));
+        assert!(!filter.matches(&GraphEvent::EdgeAdded {
+            from: 2,
+            edge_type: 3,
+            to: 2
+        }));
+        assert!(!filter.matches(&GraphEvent::FlagAdded { id: 1, flag: 2 }));
+        assert!(filter.matches(&GraphEvent::RolledBack));
+    }
+
+    #[test]
+    fn test_filter_flag() {
+        let filter = EventFilter::Flag(2);
+        assert!(filter.matches(&GraphEvent::FlagAdded { id: 1, flag: 2 }));
+        assert!(filter.matches(&GraphEvent::FlagRemoved { id: 1, flag: 2 }));
+        assert!(!filter.matches(&GraphEvent::FlagAdded { id: 2, flag: 1 }));
+        assert!(!filter.matches(&GraphEvent::NodeAdded { id: 2 }));
+    }
+}
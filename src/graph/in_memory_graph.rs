@@ -1,208 +1,600 @@
-use super::{Graph, KBValue};
+use super::adjacency_index::{AdjacencyIndex, EdgeBundle};
+use super::value_wrappers::KBRc;
+#[cfg(feature = "snapshot")]
+use super::{EdgeSnapshot, GraphSnapshot, NodeSnapshot, SnapshotValue};
+use super::{Graph, GraphError, GraphResult, KBValue, NodeQuery};
 use petgraph::dot::Dot;
 use petgraph::graph::NodeIndex;
-use petgraph::visit::EdgeRef;
+use petgraph::stable_graph::StableGraph;
 use petgraph::Direction;
-use std::cell::RefCell;
-use std::collections::HashMap;
+#[cfg(feature = "snapshot")]
+use std::collections::HashSet;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::{Display, Formatter, Result};
+use std::iter;
+use std::ops::Bound;
 use std::rc::Rc;
 
-/// Wrapper around node name so that we can have multiple references to this in both the node and
-/// the edge weights, and also implement a custom Display for both of them.
-#[derive(Default)]
-struct NodeName {
-    name: Option<Rc<str>>,
-}
-
 #[derive(Default)]
 struct NodeInfo {
     /// Store ID here as well in order to allow printing the ID as a label when no internal name is
     /// assigned.
     id: usize,
-    name: Rc<RefCell<NodeName>>,
-    value: Option<Rc<dyn KBValue>>,
+    name: Option<KBRc<str>>,
+    value: Option<KBRc<dyn KBValue>>,
     flags: HashMap<usize, bool>,
 }
 
 impl<'a> Display for NodeInfo {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        match &self.name.borrow().name {
+        match &self.name {
             Some(name) => write!(f, "{}", name),
             None => write!(f, "{}", self.id),
         }
     }
 }
 
-/// Wrapper for edge's type ID so that it can have its own Display when it comes time for printing
-/// out its label.
-struct EdgeInfo {
-    type_id: usize,
-    type_name: Rc<RefCell<NodeName>>,
+/// Hand out a stored name in the form that the Graph API returns it in.
+#[cfg(not(feature = "thread-safe"))]
+fn shared_name(name: &KBRc<str>) -> Rc<str> {
+    name.clone()
 }
 
-impl Display for EdgeInfo {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        match &self.type_name.borrow().name {
-            Some(name) => write!(f, "{}", name),
-            None => write!(f, "{}", self.type_id),
-        }
-    }
+/// Hand out a stored name in the form that the Graph API returns it in.
+#[cfg(feature = "thread-safe")]
+fn shared_name(name: &KBRc<str>) -> Rc<str> {
+    Rc::from(&**name)
+}
+
+/// How many removed node IDs a snapshot may skip over regardless of how few nodes it has. Beyond
+/// this, snapshots may only have as many gaps as they have nodes.
+#[cfg(feature = "snapshot")]
+const MIN_SNAPSHOT_GAPS: usize = 1024;
+
+/// How to reverse a single change made during a transaction.
+enum UndoEntry {
+    AddNode(usize),
+    RemoveNode {
+        info: NodeInfo,
+        edges: Vec<EdgeBundle>,
+        /// Nodes that had the removed node set as a flag.
+        flagged: Vec<usize>,
+    },
+    SetName {
+        id: usize,
+        old: Option<KBRc<str>>,
+    },
+    SetValue {
+        id: usize,
+        old: Option<KBRc<dyn KBValue>>,
+    },
+    SetFlag {
+        id: usize,
+        flag: usize,
+        old: bool,
+    },
+    AddEdge {
+        from: usize,
+        edge_type: usize,
+        to: usize,
+    },
+    RemoveEdges(EdgeBundle),
 }
 
 /// Graph that resides entirely in-memory, based on PetGraph.
+///
+/// A stable graph is used so that removing a node does not shift the IDs of all nodes created
+/// after it. The IDs of removed nodes may be reused by nodes added later, the same as with Neo4j.
+///
+/// Edges are kept in a separate index keyed by node, direction, and edge type, with the
+/// neighbors under each key kept in sorted order. This way, retrieving the nodes linked to a hub
+/// node by one type of edge doesn't involve looking at any of its other edges, and checking for an
+/// edge takes constant time.
+///
+/// Transactions are implemented with an undo journal. Every change made while a transaction is
+/// open gets recorded alongside the information needed to reverse it, and rolling back replays
+/// the journal backwards. Node IDs are restored exactly, because the stable graph reuses vacant
+/// IDs in last-in, first-out order.
 pub struct InMemoryGraph {
-    graph: petgraph::graph::Graph<NodeInfo, EdgeInfo>,
-    names: HashMap<Rc<str>, Vec<usize>>,
+    graph: StableGraph<NodeInfo, ()>,
+    edges: AdjacencyIndex,
+    names: HashMap<KBRc<str>, Vec<usize>>,
+    /// All nodes that have each flag set.
+    flagged: HashMap<usize, BTreeSet<usize>>,
+    journal: Vec<UndoEntry>,
+    /// Length of the journal when each open transaction began, with the innermost one last.
+    savepoints: Vec<usize>,
 }
 
 impl InMemoryGraph {
     /// Constructs an empty new in-memory graph
     pub fn new() -> Self {
         InMemoryGraph {
-            graph: petgraph::graph::Graph::new(),
+            graph: StableGraph::new(),
+            edges: AdjacencyIndex::default(),
             names: HashMap::new(),
+            flagged: HashMap::new(),
+            journal: Vec::new(),
+            savepoints: Vec::new(),
+        }
+    }
+
+    /// Restores a graph from a snapshot, keeping all node IDs the same as they were before.
+    #[cfg(feature = "snapshot")]
+    pub fn from_snapshot(snapshot: &GraphSnapshot) -> GraphResult<Self> {
+        let mut g = InMemoryGraph::new();
+        let mut ids = HashSet::new();
+        for node in &snapshot.nodes {
+            if !ids.insert(node.id) {
+                return Err(GraphError::Snapshot(format!(
+                    "Node {} is defined more than once",
+                    node.id
+                )));
+            }
+        }
+        if let Some(max_id) = ids.iter().max() {
+            // every ID up to the highest one gets allocated, so don't let a tiny snapshot with a
+            // huge ID make us allocate a huge graph
+            let gaps = max_id + 1 - ids.len();
+            if gaps > ids.len().max(MIN_SNAPSHOT_GAPS) {
+                return Err(GraphError::Snapshot(format!(
+                    "Node {} is too far past the {} nodes in the snapshot",
+                    max_id,
+                    ids.len()
+                )));
+            }
+            for _ in 0..=*max_id {
+                g.try_add_node()?;
+            }
+            // remove the gaps left behind by nodes that had been removed before the snapshot
+            for id in (0..=*max_id).rev() {
+                if !ids.contains(&id) {
+                    g.try_remove_node(id)?;
+                }
+            }
+        }
+        for node in &snapshot.nodes {
+            if let Some(name) = &node.name {
+                g.try_set_node_name(node.id, name)?;
+            }
+            if let Some(value) = &node.value {
+                g.try_set_node_value(node.id, value.to_kb_value())?;
+            }
+            for flag in &node.flags {
+                g.try_add_flag(node.id, *flag)?;
+            }
+        }
+        for edge in &snapshot.edges {
+            g.try_add_edge(edge.from, edge.edge_type, edge.to)?;
+        }
+        Ok(g)
+    }
+
+    /// Retrieve the info for an existing node.
+    fn node_info(&self, id: usize) -> GraphResult<&NodeInfo> {
+        self.graph
+            .node_weight(NodeIndex::new(id))
+            .ok_or(GraphError::UnknownNode(id))
+    }
+
+    /// Retrieve the info for an existing node, for modification.
+    fn node_info_mut(&mut self, id: usize) -> GraphResult<&mut NodeInfo> {
+        self.graph
+            .node_weight_mut(NodeIndex::new(id))
+            .ok_or(GraphError::UnknownNode(id))
+    }
+
+    /// Remember how to undo a change, if there is a transaction to undo it for.
+    fn record(&mut self, entry: UndoEntry) {
+        if !self.savepoints.is_empty() {
+            self.journal.push(entry);
+        }
+    }
+
+    /// Replace the name of a node, keeping the name lookup index in sync. Returns the old name.
+    fn replace_name(
+        &mut self,
+        id: usize,
+        name: Option<KBRc<str>>,
+    ) -> GraphResult<Option<KBRc<str>>> {
+        let old = std::mem::replace(&mut self.node_info_mut(id)?.name, name.clone());
+        if let Some(old_name) = &old {
+            self.unindex_name(id, old_name);
+        }
+        if let Some(new_name) = name {
+            self.names.entry(new_name).or_default().push(id);
+        }
+        Ok(old)
+    }
+
+    /// Reverse a single change.
+    fn undo(&mut self, entry: UndoEntry) {
+        match entry {
+            UndoEntry::AddNode(id) => {
+                self.edges.remove_node(id);
+                let info = self.graph.remove_node(NodeIndex::new(id)).unwrap();
+                self.unindex_node(&info);
+            }
+            UndoEntry::RemoveNode {
+                info,
+                edges,
+                flagged,
+            } => {
+                let id = info.id;
+                let name = info.name.clone();
+                let flags: Vec<usize> = info.flags.keys().copied().collect();
+                let restored = self.graph.add_node(info);
+                debug_assert_eq!(restored.index(), id);
+                if let Some(name) = name {
+                    self.names.entry(name).or_default().push(id);
+                }
+                for flag in flags {
+                    self.index_flag(id, flag, true);
+                }
+                for flagged_id in flagged {
+                    self.node_info_mut(flagged_id)
+                        .unwrap()
+                        .flags
+                        .insert(id, true);
+                    self.index_flag(flagged_id, id, true);
+                }
+                self.restore_edges(edges);
+            }
+            UndoEntry::SetName { id, old } => {
+                self.replace_name(id, old).unwrap();
+            }
+            UndoEntry::SetValue { id, old } => {
+                self.node_info_mut(id).unwrap().value = old;
+            }
+            UndoEntry::SetFlag { id, flag, old } => {
+                let flags = &mut self.node_info_mut(id).unwrap().flags;
+                if old {
+                    flags.insert(flag, true);
+                } else {
+                    flags.remove(&flag);
+                }
+                self.index_flag(id, flag, old);
+            }
+            UndoEntry::AddEdge {
+                from,
+                edge_type,
+                to,
+            } => self.edges.remove_one(from, edge_type, to),
+            UndoEntry::RemoveEdges(edges) => self.restore_edges(vec![edges]),
+        }
+    }
+
+    /// Add removed edges back in.
+    fn restore_edges(&mut self, edges: Vec<EdgeBundle>) {
+        for edge in edges {
+            self.edges.restore(edge);
+        }
+    }
+
+    /// Keep the flag lookup index in sync with whether or not a node has a flag set.
+    fn index_flag(&mut self, id: usize, flag: usize, set: bool) {
+        if set {
+            self.flagged.entry(flag).or_default().insert(id);
+        } else if let Some(ids) = self.flagged.get_mut(&flag) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.flagged.remove(&flag);
+            }
+        }
+    }
+
+    /// Remove a node that's no longer in the graph from the name and flag lookup indices.
+    fn unindex_node(&mut self, info: &NodeInfo) {
+        if let Some(name) = &info.name {
+            self.unindex_name(info.id, name);
+        }
+        for flag in info.flags.keys() {
+            self.index_flag(info.id, *flag, false);
         }
     }
+
+    /// Remove a node ID from the name lookup index.
+    fn unindex_name(&mut self, id: usize, name: &KBRc<str>) {
+        if let Some(ids) = self.names.get_mut(name) {
+            ids.retain(|existing| *existing != id);
+            if ids.is_empty() {
+                self.names.remove(name);
+            }
+        }
+    }
+
+    /// Retrieve the other ends of all edges in a certain direction, optionally filtered by type.
+    fn neighbors(
+        &self,
+        id: usize,
+        direction: Direction,
+        edge_type: Option<usize>,
+    ) -> GraphResult<Vec<usize>> {
+        self.node_info(id)?;
+        Ok(self.edges.neighbors(id, direction, edge_type).collect())
+    }
 }
 
 impl Graph for InMemoryGraph {
-    fn size(&self) -> usize {
-        self.graph.node_count()
+    fn is_private(&self) -> bool {
+        true
     }
 
-    fn add_node(&mut self) -> usize {
+    fn try_size(&self) -> GraphResult<usize> {
+        Ok(self.graph.node_count())
+    }
+
+    fn try_add_node(&mut self) -> GraphResult<usize> {
         let new_id = self.graph.add_node(Default::default());
         self.graph.node_weight_mut(new_id).unwrap().id = new_id.index();
-        new_id.index()
+        self.record(UndoEntry::AddNode(new_id.index()));
+        Ok(new_id.index())
     }
 
-    fn set_node_value(&mut self, id: usize, value: Rc<dyn KBValue>) {
-        self.graph
-            .node_weight_mut(NodeIndex::new(id))
-            .unwrap()
-            .value = Some(value);
+    fn try_remove_node(&mut self, id: usize) -> GraphResult<()> {
+        self.node_info(id)?;
+        let edges = self.edges.remove_node(id);
+        let info = self.graph.remove_node(NodeIndex::new(id)).unwrap();
+        self.unindex_node(&info);
+        // the ID may get reused, and the new node shouldn't start out as a flag on other nodes
+        let flagged: Vec<usize> = self
+            .flagged
+            .remove(&id)
+            .map(|ids| ids.into_iter().collect())
+            .unwrap_or_default();
+        for flagged_id in &flagged {
+            if let Some(flagged_info) = self.graph.node_weight_mut(NodeIndex::new(*flagged_id)) {
+                flagged_info.flags.remove(&id);
+            }
+        }
+        self.record(UndoEntry::RemoveNode {
+            info,
+            edges,
+            flagged,
+        });
+        Ok(())
     }
 
-    fn set_node_name(&mut self, id: usize, name: &str) {
-        let name_rc: Rc<str> = Rc::from(name);
-        match self.names.get_mut(&name_rc) {
-            Some(existing_vec) => existing_vec.push(id),
-            None => {
-                self.names.insert(name_rc.clone(), vec![id]);
-            }
-        };
-        self.graph
-            .node_weight_mut(NodeIndex::new(id))
-            .unwrap()
-            .name
-            .borrow_mut()
-            .name = Some(name_rc);
+    fn try_set_node_value(&mut self, id: usize, value: KBRc<dyn KBValue>) -> GraphResult<()> {
+        let old = self.node_info_mut(id)?.value.replace(value);
+        self.record(UndoEntry::SetValue { id, old });
+        Ok(())
     }
 
-    fn node_name(&self, id: usize) -> Option<Rc<str>> {
-        self.graph
-            .node_weight(NodeIndex::new(id))
-            .map(|info| info.name.borrow().name.as_ref().cloned())
-            .flatten()
+    fn try_set_node_name(&mut self, id: usize, name: &str) -> GraphResult<()> {
+        let old = self.replace_name(id, Some(KBRc::from(name)))?;
+        self.record(UndoEntry::SetName { id, old });
+        Ok(())
     }
 
-    fn node_value(&self, id: usize) -> Option<Rc<dyn KBValue>> {
-        self.graph
-            .node_weight(NodeIndex::new(id))
-            .map(|info| info.value.as_ref().cloned())
-            .flatten()
+    fn try_node_name(&self, id: usize) -> GraphResult<Option<Rc<str>>> {
+        Ok(self.node_info(id)?.name.as_ref().map(shared_name))
+    }
+
+    fn try_node_value(&self, id: usize) -> GraphResult<Option<KBRc<dyn KBValue>>> {
+        Ok(self.node_info(id)?.value.as_ref().cloned())
     }
 
-    fn lookup(&self, name: &str) -> Vec<usize> {
-        let mut ids = self.names.get(&Rc::from(name)).cloned().unwrap_or_default();
+    fn try_lookup(&self, name: &str) -> GraphResult<Vec<usize>> {
+        let mut ids = self.names.get(name).cloned().unwrap_or_default();
         ids.sort_unstable();
-        ids
+        Ok(ids)
     }
 
-    fn add_flag(&mut self, id: usize, flag: usize) {
-        self.graph
-            .node_weight_mut(NodeIndex::new(id))
-            .unwrap()
-            .flags
-            .insert(flag, true);
+    fn try_add_flag(&mut self, id: usize, flag: usize) -> GraphResult<()> {
+        let old = self.node_info_mut(id)?.flags.insert(flag, true);
+        self.index_flag(id, flag, true);
+        self.record(UndoEntry::SetFlag {
+            id,
+            flag,
+            old: old.unwrap_or(false),
+        });
+        Ok(())
     }
 
-    fn has_flag(&self, id: usize, flag: usize) -> bool {
-        *self
-            .graph
-            .node_weight(NodeIndex::new(id))
-            .unwrap()
-            .flags
+    fn try_remove_flag(&mut self, id: usize, flag: usize) -> GraphResult<()> {
+        let old = self.node_info_mut(id)?.flags.remove(&flag);
+        self.index_flag(id, flag, false);
+        self.record(UndoEntry::SetFlag {
+            id,
+            flag,
+            old: old.unwrap_or(false),
+        });
+        Ok(())
+    }
+
+    fn try_has_flag(&self, id: usize, flag: usize) -> GraphResult<bool> {
+        Ok(*self.node_info(id)?.flags.get(&flag).unwrap_or(&false))
+    }
+
+    fn try_nodes_with_flag(&self, flag: usize) -> GraphResult<Vec<usize>> {
+        Ok(self
+            .flagged
             .get(&flag)
-            .unwrap_or(&false)
-    }
-
-    fn add_edge(&mut self, from: usize, edge_type: usize, to: usize) {
-        let edge_info = EdgeInfo {
-            type_id: edge_type,
-            type_name: self
-                .graph
-                .node_weight(NodeIndex::new(edge_type))
-                .unwrap()
-                .name
-                .clone(),
-        };
-        self.graph
-            .add_edge(NodeIndex::new(from), NodeIndex::new(to), edge_info);
+            .map(|ids| ids.iter().copied().collect())
+            .unwrap_or_default())
     }
 
-    fn has_edge(&self, from: usize, edge_type: usize, to: usize) -> bool {
-        // can't use petgraph's find_edge because it doesn't take into account the edge label
-        self.graph
-            .edges_connecting(NodeIndex::new(from), NodeIndex::new(to))
-            .any(|e| e.weight().type_id == edge_type)
+    fn try_add_edge(&mut self, from: usize, edge_type: usize, to: usize) -> GraphResult<()> {
+        self.node_info(from)?;
+        self.node_info(to)?;
+        self.node_info(edge_type)?;
+        self.edges.add(from, edge_type, to, 1);
+        self.record(UndoEntry::AddEdge {
+            from,
+            edge_type,
+            to,
+        });
+        Ok(())
     }
 
-    fn outgoing_nodes(&self, from: usize, edge_type: usize) -> Vec<usize> {
-        let mut result: Vec<usize> = self
-            .graph
-            .edges_directed(NodeIndex::new(from), Direction::Outgoing)
-            .filter(|e| e.weight().type_id == edge_type)
-            .map(|e| e.target().index())
-            .collect();
-        result.sort_unstable(); // sort for determinism
-        result
+    fn try_remove_edge(&mut self, from: usize, edge_type: usize, to: usize) -> GraphResult<()> {
+        self.node_info(from)?;
+        self.node_info(to)?;
+        // there may be duplicate edges, since adding an edge does not check for existing ones
+        if let Some(removed) = self.edges.remove_all(from, edge_type, to) {
+            self.record(UndoEntry::RemoveEdges(removed));
+        }
+        Ok(())
     }
 
-    fn incoming_nodes(&self, to: usize, edge_type: usize) -> Vec<usize> {
-        let mut result: Vec<usize> = self
-            .graph
-            .edges_directed(NodeIndex::new(to), Direction::Incoming)
-            .filter(|e| e.weight().type_id == edge_type)
-            .map(|e| e.source().index())
-            .collect();
-        result.sort_unstable(); // sort for determinism
-        result
+    fn try_has_edge(&self, from: usize, edge_type: usize, to: usize) -> GraphResult<bool> {
+        self.node_info(from)?;
+        self.node_info(to)?;
+        Ok(self.edges.count(from, edge_type, to) > 0)
     }
 
-    fn all_outgoing_nodes(&self, from: usize) -> Vec<usize> {
-        let mut result: Vec<usize> = self
-            .graph
-            .edges_directed(NodeIndex::new(from), Direction::Outgoing)
-            .map(|e| e.target().index())
-            .collect();
-        result.sort_unstable(); // sort for determinism
-        result
+    fn try_outgoing_nodes(&self, from: usize, edge_type: usize) -> GraphResult<Vec<usize>> {
+        self.neighbors(from, Direction::Outgoing, Some(edge_type))
+    }
+
+    fn try_outgoing_nodes_in_order(
+        &self,
+        from: usize,
+        edge_type: usize,
+    ) -> GraphResult<Vec<usize>> {
+        self.node_info(from)?;
+        Ok(self.edges.outgoing_in_order(from, edge_type))
     }
 
-    fn all_incoming_nodes(&self, to: usize) -> Vec<usize> {
-        let mut result: Vec<usize> = self
+    fn try_incoming_nodes(&self, to: usize, edge_type: usize) -> GraphResult<Vec<usize>> {
+        self.neighbors(to, Direction::Incoming, Some(edge_type))
+    }
+
+    fn try_all_outgoing_nodes(&self, from: usize) -> GraphResult<Vec<usize>> {
+        self.neighbors(from, Direction::Outgoing, None)
+    }
+
+    fn try_all_incoming_nodes(&self, to: usize) -> GraphResult<Vec<usize>> {
+        self.neighbors(to, Direction::Incoming, None)
+    }
+
+    fn try_node_page(
+        &self,
+        query: &NodeQuery,
+        after: Option<usize>,
+        limit: usize,
+    ) -> GraphResult<Vec<usize>> {
+        let (id, direction, edge_type) = match query {
+            NodeQuery::Outgoing { from, edge_type } => {
+                (*from, Direction::Outgoing, Some(*edge_type))
+            }
+            NodeQuery::Incoming { to, edge_type } => (*to, Direction::Incoming, Some(*edge_type)),
+            NodeQuery::AllOutgoing { from } => (*from, Direction::Outgoing, None),
+            NodeQuery::AllIncoming { to } => (*to, Direction::Incoming, None),
+            NodeQuery::Lookup { name } => {
+                let mut ids = self.try_lookup(name)?;
+                ids.retain(|id| Some(*id) > after);
+                ids.truncate(limit);
+                return Ok(ids);
+            }
+            NodeQuery::Flagged { flag } => {
+                let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+                return Ok(self
+                    .flagged
+                    .get(flag)
+                    .into_iter()
+                    .flat_map(|ids| ids.range((start, Bound::Unbounded)))
+                    .copied()
+                    .take(limit)
+                    .collect());
+            }
+        };
+        self.node_info(id)?;
+        Ok(self
+            .edges
+            .distinct_neighbors_after(id, direction, edge_type, after)
+            .take(limit)
+            .collect())
+    }
+
+    fn try_into_dot(&self) -> GraphResult<String> {
+        let mut labeled = self
             .graph
-            .edges_directed(NodeIndex::new(to), Direction::Incoming)
-            .map(|e| e.source().index())
-            .collect();
-        result.sort_unstable(); // sort for determinism
-        result
+            .map(|_, node| node.to_string(), |_, _| String::new());
+        for edge in self.edges.edges() {
+            // label edges with the name of their type, which may change after the edge was added
+            let label = match self.graph.node_weight(NodeIndex::new(edge.edge_type)) {
+                Some(type_node) => type_node.to_string(),
+                None => edge.edge_type.to_string(),
+            };
+            for _ in 0..edge.count {
+                labeled.add_edge(
+                    NodeIndex::new(edge.from),
+                    NodeIndex::new(edge.to),
+                    label.clone(),
+                );
+            }
+        }
+        Ok(format!("{}", Dot::new(&labeled)))
+    }
+
+    fn try_begin_transaction(&mut self) -> GraphResult<()> {
+        self.savepoints.push(self.journal.len());
+        Ok(())
+    }
+
+    fn try_commit(&mut self) -> GraphResult<()> {
+        self.savepoints.pop().ok_or(GraphError::NoTransaction)?;
+        if self.savepoints.is_empty() {
+            // nothing left that could still be rolled back
+            self.journal.clear();
+        }
+        Ok(())
     }
 
-    fn into_dot(&self) -> String {
-        format!("{}", Dot::new(&self.graph))
+    fn try_rollback(&mut self) -> GraphResult<()> {
+        let savepoint = self.savepoints.pop().ok_or(GraphError::NoTransaction)?;
+        while self.journal.len() > savepoint {
+            let entry = self.journal.pop().unwrap();
+            self.undo(entry);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "snapshot")]
+    fn try_snapshot(&self) -> GraphResult<GraphSnapshot> {
+        let mut nodes: Vec<NodeSnapshot> = self
+            .graph
+            .node_indices()
+            .map(|i| {
+                let info = &self.graph[i];
+                let mut flags: Vec<usize> = info
+                    .flags
+                    .iter()
+                    .filter(|(_, set)| **set)
+                    .map(|(flag, _)| *flag)
+                    .collect();
+                flags.sort_unstable();
+                NodeSnapshot {
+                    id: i.index(),
+                    name: info.name.as_ref().map(|n| n.to_string()),
+                    value: info
+                        .value
+                        .as_ref()
+                        .and_then(|v| SnapshotValue::from_kb_value(v.as_ref())),
+                    flags,
+                }
+            })
+            .collect();
+        nodes.sort_by_key(|n| n.id);
+        let mut edges = self.edges.edges();
+        // loading edges back in the same order keeps track of which ones were added first
+        edges.sort_by_key(|edge| edge.age);
+        let edges = edges
+            .into_iter()
+            // edges whose type no longer exists would make the snapshot impossible to load
+            .filter(|edge| self.graph.contains_node(NodeIndex::new(edge.edge_type)))
+            .flat_map(|edge| {
+                let snapshot = EdgeSnapshot {
+                    from: edge.from,
+                    edge_type: edge.edge_type,
+                    to: edge.to,
+                };
+                iter::repeat(snapshot).take(edge.count)
+            })
+            .collect();
+        Ok(GraphSnapshot::new(nodes, edges))
     }
 }
 
@@ -210,7 +602,7 @@ impl Graph for InMemoryGraph {
 mod tests {
     use super::super::*;
     use super::*;
-    use crate::graph::value_wrappers::{unwrap_value, WeakValue};
+    use crate::graph::value_wrappers::{unwrap_value, StrongValue, WeakValue};
 
     #[test]
     fn test_create() {
@@ -240,19 +632,80 @@ mod tests {
         bind_in_memory_graph();
         let mut g = InjectionGraph::new();
         let a_id = g.add_node();
-        let v = Rc::new(5);
-        g.set_node_value(a_id, Rc::new(WeakValue::new(&v)));
+        let v = KBRc::new(5);
+        g.set_node_value(a_id, KBRc::new(WeakValue::new(&v)));
         assert_eq!(unwrap_value::<i32>(g.node_value(a_id)), Some(v));
         assert_eq!(g.node_name(a_id), None);
     }
 
+    #[test]
+    fn test_remove_node() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let initial_size = g.size();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        g.remove_node(a_id);
+        assert_eq!(g.size(), initial_size + 1);
+        assert_eq!(g.node_name(a_id), None);
+        assert_eq!(g.node_name(b_id), None);
+    }
+
+    #[test]
+    fn test_remove_node_keeps_other_ids() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        g.set_node_name(b_id, "B");
+        g.remove_node(a_id);
+        assert_eq!(g.node_name(b_id), Some(Rc::from("B")));
+        assert_eq!(g.lookup("B"), vec![b_id]);
+    }
+
+    #[test]
+    fn test_remove_node_edges() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let c_id = g.add_node();
+        let edge_type = g.add_node();
+        g.add_edge(a_id, edge_type, b_id);
+        g.add_edge(c_id, edge_type, a_id);
+        g.remove_node(a_id);
+        assert_eq!(g.all_incoming_nodes(b_id), Vec::<usize>::new());
+        assert_eq!(g.all_outgoing_nodes(c_id), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_removed_id_reused_cleanly() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let removed = g.add_node();
+        g.add_flag(removed, a_id);
+        g.add_flag(a_id, removed);
+        g.add_edge(a_id, removed, b_id);
+        g.remove_node(removed);
+
+        let reused = g.add_node();
+        assert_eq!(reused, removed);
+        assert!(!g.has_flag(reused, a_id));
+        assert!(!g.has_flag(a_id, reused));
+        assert_eq!(g.nodes_with_flag(reused), Vec::<usize>::new());
+        assert!(!g.has_edge(a_id, reused, b_id));
+        assert_eq!(g.all_outgoing_nodes(a_id), Vec::<usize>::new());
+    }
+
     #[test]
     fn test_retrieve_node_string_value() {
         bind_in_memory_graph();
         let mut g = InjectionGraph::new();
         let a_id = g.add_node();
-        let v = Rc::new("5");
-        g.set_node_value(a_id, Rc::new(WeakValue::new(&v)));
+        let v = KBRc::new("5");
+        g.set_node_value(a_id, KBRc::new(WeakValue::new(&v)));
         assert_eq!(unwrap_value::<&str>(g.node_value(a_id)), Some(v));
         assert_eq!(g.node_name(a_id), None);
     }
@@ -271,9 +724,9 @@ mod tests {
         bind_in_memory_graph();
         let mut g = InjectionGraph::new();
         let a_id = g.add_node();
-        let v = Rc::new(5);
+        let v = KBRc::new(5);
         g.set_node_name(a_id, "A");
-        g.set_node_value(a_id, Rc::new(WeakValue::new(&v)));
+        g.set_node_value(a_id, KBRc::new(WeakValue::new(&v)));
         assert_eq!(g.node_name(a_id), Some(Rc::from("A")));
         assert_eq!(unwrap_value::<i32>(g.node_value(a_id)), Some(v));
     }
@@ -320,6 +773,77 @@ mod tests {
         assert!(g.has_flag(a_id, b_id));
     }
 
+    #[test]
+    fn test_remove_node_name_lookup() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        g.set_node_name(a_id, "A");
+        g.set_node_name(b_id, "A");
+        g.remove_node(a_id);
+        assert_eq!(g.lookup("A"), vec![b_id]);
+
+        g.remove_node(b_id);
+        assert_eq!(g.lookup("A"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_remove_flag() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let c_id = g.add_node();
+        g.add_flag(a_id, b_id);
+        g.add_flag(a_id, c_id);
+        g.remove_flag(a_id, b_id);
+        assert!(!g.has_flag(a_id, b_id));
+        assert!(g.has_flag(a_id, c_id));
+
+        // removing a nonexistent flag should be a no-op
+        g.remove_flag(a_id, b_id);
+        assert!(!g.has_flag(a_id, b_id));
+    }
+
+    #[test]
+    fn test_nodes_with_flag() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let c_id = g.add_node();
+        let flag = g.add_node();
+        assert_eq!(g.nodes_with_flag(flag), Vec::<usize>::new());
+        g.add_flag(c_id, flag);
+        g.add_flag(a_id, flag);
+        g.add_flag(b_id, a_id);
+        assert_eq!(g.nodes_with_flag(flag), vec![a_id, c_id]);
+        g.remove_flag(a_id, flag);
+        assert_eq!(g.nodes_with_flag(flag), vec![c_id]);
+        g.remove_node(c_id);
+        assert_eq!(g.nodes_with_flag(flag), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_nodes_with_flag_rollback() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let flag = g.add_node();
+        g.add_flag(a_id, flag);
+
+        g.begin_transaction();
+        g.add_flag(b_id, flag);
+        g.remove_node(a_id);
+        assert_eq!(g.nodes_with_flag(flag), vec![b_id]);
+        g.rollback();
+
+        assert_eq!(g.nodes_with_flag(flag), vec![a_id]);
+        assert_eq!(g.iter_nodes_with_flag(flag).collect::<Vec<_>>(), vec![a_id]);
+    }
+
     #[test]
     fn test_no_outgoing_node() {
         bind_in_memory_graph();
@@ -355,6 +879,21 @@ mod tests {
         assert_eq!(g.outgoing_nodes(a_id, edge_type), vec![b_id, c_id]);
     }
 
+    #[test]
+    fn test_outgoing_nodes_in_order() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let c_id = g.add_node();
+        let edge_type = g.add_node();
+        g.add_edge(a_id, edge_type, c_id);
+        g.add_edge(a_id, edge_type, b_id);
+        g.add_edge(a_id, edge_type, c_id);
+        assert_eq!(g.outgoing_nodes(a_id, edge_type), vec![b_id, c_id, c_id]);
+        assert_eq!(g.outgoing_nodes_in_order(a_id, edge_type), vec![c_id, b_id]);
+    }
+
     #[test]
     fn test_outgoing_ignores_incoming_nodes() {
         bind_in_memory_graph();
@@ -402,6 +941,87 @@ mod tests {
         assert!(!g.has_edge(b_id, edge_type2, a_id));
     }
 
+    #[test]
+    fn test_remove_edge() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let c_id = g.add_node();
+        let edge_type1 = g.add_node();
+        let edge_type2 = g.add_node();
+        g.add_edge(a_id, edge_type1, b_id);
+        g.add_edge(a_id, edge_type2, b_id);
+        g.add_edge(a_id, edge_type1, c_id);
+        g.remove_edge(a_id, edge_type1, b_id);
+        assert!(!g.has_edge(a_id, edge_type1, b_id));
+        assert!(g.has_edge(a_id, edge_type2, b_id));
+        assert_eq!(g.outgoing_nodes(a_id, edge_type1), vec![c_id]);
+        assert_eq!(g.incoming_nodes(b_id, edge_type2), vec![a_id]);
+    }
+
+    #[test]
+    fn test_remove_duplicate_edges() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let edge_type = g.add_node();
+        g.add_edge(a_id, edge_type, b_id);
+        g.add_edge(a_id, edge_type, b_id);
+        g.remove_edge(a_id, edge_type, b_id);
+        assert!(!g.has_edge(a_id, edge_type, b_id));
+        assert_eq!(g.all_outgoing_nodes(a_id), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_neighbors_sorted_by_id() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let hub = g.add_node();
+        let edge_type1 = g.add_node();
+        let edge_type2 = g.add_node();
+        let ids: Vec<usize> = (0..5).map(|_| g.add_node()).collect();
+        for id in ids.iter().rev() {
+            g.add_edge(*id, edge_type1, hub);
+            g.add_edge(*id, edge_type2, hub);
+        }
+        g.add_edge(ids[2], edge_type1, hub);
+        assert_eq!(
+            g.incoming_nodes(hub, edge_type1),
+            vec![ids[0], ids[1], ids[2], ids[2], ids[3], ids[4]]
+        );
+        assert_eq!(g.all_incoming_nodes(hub).len(), 11);
+        g.remove_edge(ids[2], edge_type1, hub);
+        assert_eq!(
+            g.incoming_nodes(hub, edge_type1),
+            vec![ids[0], ids[1], ids[3], ids[4]]
+        );
+        assert!(g.has_edge(ids[2], edge_type2, hub));
+        assert!(!g.has_edge(hub, edge_type2, ids[2]));
+    }
+
+    #[test]
+    fn test_rollback_restores_duplicate_edges() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let edge_type = g.add_node();
+        g.add_edge(a_id, edge_type, b_id);
+        g.add_edge(a_id, edge_type, b_id);
+        g.add_edge(b_id, edge_type, b_id);
+
+        g.begin_transaction();
+        g.remove_edge(a_id, edge_type, b_id);
+        g.remove_node(b_id);
+        g.rollback();
+
+        assert_eq!(g.outgoing_nodes(a_id, edge_type), vec![b_id, b_id]);
+        assert_eq!(g.incoming_nodes(b_id, edge_type), vec![a_id, a_id, b_id]);
+        assert!(g.has_edge(b_id, edge_type, b_id));
+    }
+
     #[test]
     fn test_no_incoming_node() {
         bind_in_memory_graph();
@@ -470,6 +1090,239 @@ mod tests {
         assert_eq!(g.incoming_nodes(a_id, edge_type1), vec![b_id, d_id]);
     }
 
+    #[test]
+    fn test_rollback() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let edge_type = g.add_node();
+        g.set_node_name(a_id, "A");
+        g.add_flag(a_id, edge_type);
+        g.add_edge(a_id, edge_type, b_id);
+
+        g.begin_transaction();
+        let c_id = g.add_node();
+        g.set_node_name(a_id, "Renamed");
+        g.set_node_value(c_id, KBRc::new(StrongValue::new(5)));
+        g.remove_flag(a_id, edge_type);
+        g.add_flag(b_id, edge_type);
+        g.add_edge(c_id, edge_type, a_id);
+        g.remove_edge(a_id, edge_type, b_id);
+        g.remove_node(edge_type);
+        g.rollback();
+
+        assert_eq!(g.size(), 3);
+        assert_eq!(g.node_name(a_id), Some(Rc::from("A")));
+        assert_eq!(g.lookup("A"), vec![a_id]);
+        assert_eq!(g.lookup("Renamed"), Vec::<usize>::new());
+        assert!(g.has_flag(a_id, edge_type));
+        assert!(!g.has_flag(b_id, edge_type));
+        assert_eq!(g.outgoing_nodes(a_id, edge_type), vec![b_id]);
+        assert_eq!(g.all_incoming_nodes(a_id), Vec::<usize>::new());
+        assert_eq!(g.try_node_name(c_id), Err(GraphError::UnknownNode(c_id)));
+        assert_eq!(g.add_node(), c_id);
+    }
+
+    #[test]
+    fn test_rollback_restores_removed_ids() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        g.set_node_name(b_id, "B");
+        g.add_edge(b_id, b_id, b_id);
+        g.begin_transaction();
+        g.remove_node(a_id);
+        g.remove_node(b_id);
+        let reused_id = g.add_node();
+        g.rollback();
+        assert!(reused_id == a_id || reused_id == b_id);
+        assert_eq!(g.size(), 2);
+        assert_eq!(g.node_name(b_id), Some(Rc::from("B")));
+        assert_eq!(g.outgoing_nodes(b_id, b_id), vec![b_id]);
+        assert_eq!(g.node_name(a_id), None);
+    }
+
+    #[test]
+    fn test_rollback_restores_flags_and_typed_edges() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let removed = g.add_node();
+        g.add_flag(a_id, removed);
+        g.add_edge(a_id, removed, b_id);
+        g.begin_transaction();
+        g.remove_node(removed);
+        g.rollback();
+        assert!(g.has_flag(a_id, removed));
+        assert_eq!(g.nodes_with_flag(removed), vec![a_id]);
+        assert!(g.has_edge(a_id, removed, b_id));
+    }
+
+    #[test]
+    fn test_commit() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        g.begin_transaction();
+        let a_id = g.add_node();
+        g.commit();
+        assert_eq!(g.try_rollback(), Err(GraphError::NoTransaction));
+        assert_eq!(g.size(), 1);
+        assert_eq!(g.try_node_name(a_id), Ok(None));
+    }
+
+    #[test]
+    fn test_nested_transactions() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        g.begin_transaction();
+        let a_id = g.add_node();
+        g.begin_transaction();
+        g.set_node_name(a_id, "A");
+        g.rollback();
+        g.begin_transaction();
+        g.add_flag(a_id, a_id);
+        g.commit();
+        assert_eq!(g.node_name(a_id), None);
+        assert!(g.has_flag(a_id, a_id));
+        g.rollback();
+        assert_eq!(g.size(), 0);
+    }
+
+    #[test]
+    fn test_transaction_panic() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let result = std::panic::catch_unwind(|| {
+            InjectionGraph::new().transaction(|| {
+                let mut inner = InjectionGraph::new();
+                inner.add_node();
+                inner.set_node_name(5, "nonexistent");
+            })
+        });
+        assert!(result.is_err());
+        assert_eq!(g.size(), 0);
+        assert_eq!(g.try_commit(), Err(GraphError::NoTransaction));
+        g.transaction(|| InjectionGraph::new().add_node());
+        assert_eq!(g.size(), 1);
+    }
+
+    #[test]
+    fn test_try_transaction_error() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let result = g.try_transaction(|| {
+            let mut inner = InjectionGraph::new();
+            let a_id = inner.try_add_node()?;
+            inner.try_add_edge(a_id, a_id, 5)
+        });
+        assert_eq!(result, Err(GraphError::UnknownNode(5)));
+        assert_eq!(g.size(), 0);
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn test_snapshot_round_trip() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let removed_id = g.add_node();
+        let b_id = g.add_node();
+        let edge_type = g.add_node();
+        g.set_node_name(a_id, "A");
+        g.set_node_value(b_id, KBRc::new(StrongValue::new("b value".to_string())));
+        g.add_flag(a_id, edge_type);
+        g.add_edge(a_id, edge_type, b_id);
+        g.remove_node(removed_id);
+        let snapshot = g.snapshot();
+
+        let restored = InMemoryGraph::from_snapshot(&snapshot).unwrap();
+        assert_eq!(restored.size(), 3);
+        assert_eq!(restored.node_name(a_id), Some(Rc::from("A")));
+        assert_eq!(restored.lookup("A"), vec![a_id]);
+        assert_eq!(
+            unwrap_value::<String>(restored.node_value(b_id)),
+            Some(KBRc::new("b value".to_string()))
+        );
+        assert!(restored.has_flag(a_id, edge_type));
+        assert_eq!(restored.outgoing_nodes(a_id, edge_type), vec![b_id]);
+        assert_eq!(
+            restored.try_node_name(removed_id),
+            Err(GraphError::UnknownNode(removed_id))
+        );
+        assert_eq!(restored.snapshot(), snapshot);
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn test_snapshot_skips_unsupported_values() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let v = KBRc::new(vec![1, 2, 3]);
+        g.set_node_value(a_id, KBRc::new(WeakValue::new(&v)));
+        assert_eq!(g.snapshot().nodes[0].value, None);
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn test_snapshot_after_removing_edge_type() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let edge_type = g.add_node();
+        g.add_edge(a_id, edge_type, b_id);
+        g.remove_node(edge_type);
+        let snapshot = g.snapshot();
+        assert_eq!(snapshot.edges, vec![]);
+        let restored = InMemoryGraph::from_snapshot(&snapshot).unwrap();
+        assert_eq!(restored.size(), 2);
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn test_snapshot_too_sparse() {
+        let node = |id| NodeSnapshot {
+            id,
+            name: None,
+            value: None,
+            flags: vec![],
+        };
+        let sparse = GraphSnapshot::new(vec![node(0), node(usize::MAX / 2)], vec![]);
+        assert!(matches!(
+            InMemoryGraph::from_snapshot(&sparse),
+            Err(GraphError::Snapshot(_))
+        ));
+        // gaps left by a reasonable number of removed nodes are still fine
+        let restored = InMemoryGraph::from_snapshot(&GraphSnapshot::new(vec![node(5)], vec![]));
+        assert_eq!(restored.map(|g| g.size()).ok(), Some(1));
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn test_snapshot_invalid_edge() {
+        let snapshot = GraphSnapshot::new(
+            vec![NodeSnapshot {
+                id: 0,
+                name: None,
+                value: None,
+                flags: vec![],
+            }],
+            vec![EdgeSnapshot {
+                from: 0,
+                edge_type: 0,
+                to: 1,
+            }],
+        );
+        assert_eq!(
+            InMemoryGraph::from_snapshot(&snapshot).err(),
+            Some(GraphError::UnknownNode(1))
+        );
+    }
+
     #[test]
     fn test_into_dot() {
         bind_in_memory_graph();
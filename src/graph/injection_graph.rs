@@ -1,18 +1,92 @@
 #[cfg(feature = "cypher")]
 use super::cypher_graph::CypherGraph;
+use super::graph_events::{self, EventFilter, GraphEvent, Subscription};
 use super::in_memory_graph::InMemoryGraph;
 use super::invalid_graph::InvalidGraph;
-use super::{Graph, KBValue};
+use super::knowledge_base::KnowledgeBase;
+#[cfg(feature = "thread-safe")]
+use super::shared_graph::SharedGraph;
+#[cfg(feature = "sqlite")]
+use super::sqlite_graph::SqliteGraph;
+#[cfg(feature = "snapshot")]
+use super::GraphSnapshot;
+use super::{Graph, GraphResult, KBRc, KBValue, NodeQuery};
+use crate::node_wrappers::ancestor_cache;
 use std::cell::RefCell;
 use std::rc::Rc;
+#[cfg(feature = "thread-safe")]
+use std::sync::Mutex;
+
+/// Shared, mutable reference to a graph.
+pub(super) type GraphCell = Rc<RefCell<Box<dyn Graph>>>;
 
 thread_local! {
-    static GRAPH: RefCell<Box<dyn Graph>> = RefCell::new(Box::new(InvalidGraph{}));
+    static GRAPH: GraphCell = Rc::new(RefCell::new(default_graph()));
+}
+
+/// The graph backing the global KB.
+pub(super) fn global_graph() -> GraphCell {
+    GRAPH.with(|g| g.clone())
+}
+
+/// Graph that new threads get bound to by default.
+#[cfg(feature = "thread-safe")]
+static SHARED_GRAPH: Mutex<Option<SharedGraph>> = Mutex::new(None);
+
+/// The graph that each thread starts out bound to.
+#[cfg(not(feature = "thread-safe"))]
+fn default_graph() -> Box<dyn Graph> {
+    Box::new(InvalidGraph {})
+}
+
+/// The graph that each thread starts out bound to. This is the shared graph if one has been set
+/// up with `bind_shared_in_memory_graph`.
+#[cfg(feature = "thread-safe")]
+fn default_graph() -> Box<dyn Graph> {
+    match SHARED_GRAPH.lock().ok().and_then(|shared| shared.clone()) {
+        Some(shared) => Box::new(shared),
+        None => Box::new(InvalidGraph {}),
+    }
+}
+
+/// Replace the graph backing the global KB, forgetting anything cached about the old one.
+pub(super) fn rebind_global_graph(graph: Box<dyn Graph>) {
+    GRAPH.with(|g| *g.borrow_mut() = graph);
+    ancestor_cache::forget(KnowledgeBase::GLOBAL);
 }
 
 /// Bind GRAPH to a new graph that sits entirely in memory.
 pub fn bind_in_memory_graph() {
-    GRAPH.with(|g| *g.borrow_mut() = Box::new(InMemoryGraph::new()));
+    rebind_global_graph(Box::new(InMemoryGraph::new()));
+}
+
+/// Bind GRAPH to a new in-memory graph that can be shared between threads. The current thread gets
+/// bound to it right away, and so does every thread that starts using the KB afterwards without
+/// binding a graph of its own. Threads that have already used the KB can join in with
+/// `bind_shared_graph`.
+#[cfg(feature = "thread-safe")]
+pub fn bind_shared_in_memory_graph() -> SharedGraph {
+    let shared = SharedGraph::new();
+    *SHARED_GRAPH
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(shared.clone());
+    bind_shared_graph(&shared);
+    shared
+}
+
+/// Bind GRAPH for the current thread to an existing shared graph.
+#[cfg(feature = "thread-safe")]
+pub fn bind_shared_graph(graph: &SharedGraph) {
+    rebind_global_graph(Box::new(graph.clone()));
+}
+
+/// Bind GRAPH to a new in-memory graph that is restored from a snapshot. The current binding is
+/// left untouched if the snapshot turns out to be invalid.
+#[cfg(feature = "snapshot")]
+pub fn bind_in_memory_snapshot(snapshot: &GraphSnapshot) -> GraphResult<()> {
+    let restored = InMemoryGraph::from_snapshot(snapshot)?;
+    rebind_global_graph(Box::new(restored));
+    Ok(())
 }
 
 /// Bind GRAPH to an external Neo4j database.
@@ -25,83 +99,311 @@ pub fn bind_in_memory_graph() {
 ///  * Only string values can be attached to nodes.
 #[cfg(feature = "cypher")]
 pub fn bind_cypher_graph(uri: &str) {
-    GRAPH.with(|g| *g.borrow_mut() = Box::new(CypherGraph::new(uri)));
+    rebind_global_graph(Box::new(CypherGraph::new(uri)));
+}
+
+/// Bind GRAPH to an embedded SQLite database file, which will be created if it doesn't exist yet.
+/// Unlike with the in-memory graph, everything added to the graph persists across process
+/// restarts. Pass in `:memory:` to use a temporary database instead.
+///
+/// Current limitations:
+///
+///  * Only string and number values can be attached to nodes.
+#[cfg(feature = "sqlite")]
+pub fn bind_sqlite_graph(path: &str) {
+    rebind_global_graph(Box::new(SqliteGraph::new(path)));
 }
 
-/// Graph usable with dependency injection.
-#[derive(Copy, Clone, Default)]
-pub struct InjectionGraph {}
+/// Graph usable with dependency injection. Every InjectionGraph refers to the graph of a specific
+/// KB.
+#[derive(Copy, Clone)]
+pub struct InjectionGraph {
+    kb: KnowledgeBase,
+}
 
 impl InjectionGraph {
-    /// Creates a new reference to
+    /// Creates a new reference to the graph of the current KB.
     pub fn new() -> Self {
-        Self {}
+        Self {
+            kb: KnowledgeBase::current(),
+        }
+    }
+
+    /// The KB whose graph this refers to.
+    pub fn kb(&self) -> KnowledgeBase {
+        self.kb
+    }
+
+    /// Register a callback that gets notified of every change made to this KB's graph that passes
+    /// the filter. Changes are reported right after they are made. Rollbacks are reported as a
+    /// single `GraphEvent::RolledBack` event, because the changes being undone were already
+    /// reported.
+    ///
+    /// Only changes made through an `InjectionGraph` on the current thread are reported.
+    pub fn subscribe<F: FnMut(&GraphEvent) + 'static>(
+        &self,
+        filter: EventFilter,
+        callback: F,
+    ) -> Subscription {
+        graph_events::subscribe(self.kb, filter, Box::new(callback))
+    }
+
+    /// Stop notifying a previously registered callback. Returns false if it was already
+    /// unsubscribed.
+    pub fn unsubscribe(&self, subscription: Subscription) -> bool {
+        graph_events::unsubscribe(subscription)
+    }
+
+    /// Report a successful change to all interested listeners.
+    fn notify<T>(&self, result: GraphResult<T>, event: GraphEvent) -> GraphResult<T> {
+        if result.is_ok() {
+            // caches get updated before any listener gets the chance to query the graph
+            ancestor_cache::invalidate(self.kb, &event);
+            graph_events::emit(self.kb, event);
+        }
+        result
+    }
+
+    /// Runs the given closure inside a transaction. The transaction gets committed if the closure
+    /// returns, and rolled back if it panics.
+    pub fn transaction<T, F: FnOnce() -> T>(&mut self, f: F) -> T {
+        self.begin_transaction();
+        let mut guard = RollbackGuard {
+            graph: *self,
+            armed: true,
+        };
+        let result = f();
+        guard.armed = false;
+        self.commit();
+        result
+    }
+
+    /// Runs the given closure inside a transaction. The transaction gets committed if the closure
+    /// succeeds, and rolled back if it returns an error or panics.
+    pub fn try_transaction<T, F: FnOnce() -> GraphResult<T>>(&mut self, f: F) -> GraphResult<T> {
+        self.try_begin_transaction()?;
+        let mut guard = RollbackGuard {
+            graph: *self,
+            armed: true,
+        };
+        let result = f();
+        guard.armed = false;
+        match result {
+            Ok(value) => {
+                self.try_commit()?;
+                Ok(value)
+            }
+            Err(e) => {
+                self.try_rollback()?;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Rolls back the current transaction when dropped while still armed, which happens when the code
+/// running inside the transaction panics.
+struct RollbackGuard {
+    graph: InjectionGraph,
+    armed: bool,
+}
+
+impl Drop for RollbackGuard {
+    fn drop(&mut self) {
+        if self.armed {
+            // already unwinding from a panic, so there's no one left to report this error to
+            let _ = self.graph.try_rollback();
+        }
+    }
+}
+
+impl Default for InjectionGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<KnowledgeBase> for InjectionGraph {
+    fn from(kb: KnowledgeBase) -> Self {
+        Self { kb }
     }
 }
 
 impl Graph for InjectionGraph {
-    fn size(&self) -> usize {
-        GRAPH.with(|g| g.borrow().size())
+    fn try_size(&self) -> GraphResult<usize> {
+        self.kb.graph_cell()?.borrow().try_size()
+    }
+
+    fn try_add_node(&mut self) -> GraphResult<usize> {
+        let id = self.kb.graph_cell()?.borrow_mut().try_add_node()?;
+        self.notify(Ok(id), GraphEvent::NodeAdded { id })
+    }
+
+    fn try_remove_node(&mut self, id: usize) -> GraphResult<()> {
+        let result = self.kb.graph_cell()?.borrow_mut().try_remove_node(id);
+        self.notify(result, GraphEvent::NodeRemoved { id })
+    }
+
+    fn try_set_node_value(&mut self, id: usize, value: KBRc<dyn KBValue>) -> GraphResult<()> {
+        let result = self
+            .kb
+            .graph_cell()?
+            .borrow_mut()
+            .try_set_node_value(id, value);
+        self.notify(result, GraphEvent::ValueSet { id })
+    }
+
+    fn try_set_node_name(&mut self, id: usize, name: &str) -> GraphResult<()> {
+        let result = self
+            .kb
+            .graph_cell()?
+            .borrow_mut()
+            .try_set_node_name(id, name);
+        let name = Rc::from(name);
+        self.notify(result, GraphEvent::NodeNamed { id, name })
+    }
+
+    fn try_node_name(&self, id: usize) -> GraphResult<Option<Rc<str>>> {
+        self.kb.graph_cell()?.borrow().try_node_name(id)
+    }
+
+    fn try_node_value(&self, id: usize) -> GraphResult<Option<KBRc<dyn KBValue>>> {
+        self.kb.graph_cell()?.borrow().try_node_value(id)
+    }
+
+    fn try_lookup(&self, name: &str) -> GraphResult<Vec<usize>> {
+        self.kb.graph_cell()?.borrow().try_lookup(name)
+    }
+
+    fn try_add_flag(&mut self, id: usize, flag: usize) -> GraphResult<()> {
+        let result = self.kb.graph_cell()?.borrow_mut().try_add_flag(id, flag);
+        self.notify(result, GraphEvent::FlagAdded { id, flag })
+    }
+
+    fn try_remove_flag(&mut self, id: usize, flag: usize) -> GraphResult<()> {
+        let result = self.kb.graph_cell()?.borrow_mut().try_remove_flag(id, flag);
+        self.notify(result, GraphEvent::FlagRemoved { id, flag })
+    }
+
+    fn try_has_flag(&self, id: usize, flag: usize) -> GraphResult<bool> {
+        self.kb.graph_cell()?.borrow().try_has_flag(id, flag)
     }
 
-    fn add_node(&mut self) -> usize {
-        GRAPH.with(|g| g.borrow_mut().add_node())
+    fn try_nodes_with_flag(&self, flag: usize) -> GraphResult<Vec<usize>> {
+        self.kb.graph_cell()?.borrow().try_nodes_with_flag(flag)
     }
 
-    fn set_node_value(&mut self, id: usize, value: Rc<dyn KBValue>) {
-        GRAPH.with(|g| g.borrow_mut().set_node_value(id, value));
+    fn try_add_edge(&mut self, from: usize, edge_type: usize, to: usize) -> GraphResult<()> {
+        let result = self
+            .kb
+            .graph_cell()?
+            .borrow_mut()
+            .try_add_edge(from, edge_type, to);
+        self.notify(
+            result,
+            GraphEvent::EdgeAdded {
+                from,
+                edge_type,
+                to,
+            },
+        )
     }
 
-    fn set_node_name(&mut self, id: usize, name: &str) {
-        GRAPH.with(|g| g.borrow_mut().set_node_name(id, name));
+    fn try_remove_edge(&mut self, from: usize, edge_type: usize, to: usize) -> GraphResult<()> {
+        let result = self
+            .kb
+            .graph_cell()?
+            .borrow_mut()
+            .try_remove_edge(from, edge_type, to);
+        self.notify(
+            result,
+            GraphEvent::EdgeRemoved {
+                from,
+                edge_type,
+                to,
+            },
+        )
     }
 
-    fn node_name(&self, id: usize) -> Option<Rc<str>> {
-        GRAPH.with(|g| g.borrow().node_name(id))
+    fn try_has_edge(&self, from: usize, edge_type: usize, to: usize) -> GraphResult<bool> {
+        self.kb
+            .graph_cell()?
+            .borrow()
+            .try_has_edge(from, edge_type, to)
     }
 
-    fn node_value(&self, id: usize) -> Option<Rc<dyn KBValue>> {
-        GRAPH.with(|g| g.borrow().node_value(id))
+    fn try_outgoing_nodes(&self, from: usize, edge_type: usize) -> GraphResult<Vec<usize>> {
+        self.kb
+            .graph_cell()?
+            .borrow()
+            .try_outgoing_nodes(from, edge_type)
     }
 
-    fn lookup(&self, name: &str) -> Vec<usize> {
-        GRAPH.with(|g| g.borrow().lookup(name))
+    fn try_outgoing_nodes_in_order(
+        &self,
+        from: usize,
+        edge_type: usize,
+    ) -> GraphResult<Vec<usize>> {
+        self.kb
+            .graph_cell()?
+            .borrow()
+            .try_outgoing_nodes_in_order(from, edge_type)
     }
 
-    fn add_flag(&mut self, id: usize, flag: usize) {
-        GRAPH.with(|g| g.borrow_mut().add_flag(id, flag));
+    fn try_incoming_nodes(&self, to: usize, edge_type: usize) -> GraphResult<Vec<usize>> {
+        self.kb
+            .graph_cell()?
+            .borrow()
+            .try_incoming_nodes(to, edge_type)
     }
 
-    fn has_flag(&self, id: usize, flag: usize) -> bool {
-        GRAPH.with(|g| g.borrow().has_flag(id, flag))
+    fn try_all_outgoing_nodes(&self, from: usize) -> GraphResult<Vec<usize>> {
+        self.kb.graph_cell()?.borrow().try_all_outgoing_nodes(from)
     }
 
-    fn add_edge(&mut self, from: usize, edge_type: usize, to: usize) {
-        GRAPH.with(|g| g.borrow_mut().add_edge(from, edge_type, to));
+    fn try_all_incoming_nodes(&self, to: usize) -> GraphResult<Vec<usize>> {
+        self.kb.graph_cell()?.borrow().try_all_incoming_nodes(to)
     }
 
-    fn has_edge(&self, from: usize, edge_type: usize, to: usize) -> bool {
-        GRAPH.with(|g| g.borrow().has_edge(from, edge_type, to))
+    fn try_node_page(
+        &self,
+        query: &NodeQuery,
+        after: Option<usize>,
+        limit: usize,
+    ) -> GraphResult<Vec<usize>> {
+        self.kb
+            .graph_cell()?
+            .borrow()
+            .try_node_page(query, after, limit)
     }
 
-    fn outgoing_nodes(&self, from: usize, edge_type: usize) -> Vec<usize> {
-        GRAPH.with(|g| g.borrow().outgoing_nodes(from, edge_type))
+    fn try_into_dot(&self) -> GraphResult<String> {
+        self.kb.graph_cell()?.borrow().try_into_dot()
     }
 
-    fn incoming_nodes(&self, to: usize, edge_type: usize) -> Vec<usize> {
-        GRAPH.with(|g| g.borrow().incoming_nodes(to, edge_type))
+    fn is_private(&self) -> bool {
+        self.kb
+            .graph_cell()
+            .map(|g| g.borrow().is_private())
+            .unwrap_or(false)
     }
 
-    fn all_outgoing_nodes(&self, from: usize) -> Vec<usize> {
-        GRAPH.with(|g| g.borrow().all_outgoing_nodes(from))
+    fn try_begin_transaction(&mut self) -> GraphResult<()> {
+        self.kb.graph_cell()?.borrow_mut().try_begin_transaction()
     }
 
-    fn all_incoming_nodes(&self, to: usize) -> Vec<usize> {
-        GRAPH.with(|g| g.borrow().all_incoming_nodes(to))
+    fn try_commit(&mut self) -> GraphResult<()> {
+        self.kb.graph_cell()?.borrow_mut().try_commit()
     }
 
-    fn into_dot(&self) -> String {
-        GRAPH.with(|g| g.borrow().into_dot())
+    fn try_rollback(&mut self) -> GraphResult<()> {
+        let result = self.kb.graph_cell()?.borrow_mut().try_rollback();
+        self.notify(result, GraphEvent::RolledBack)
+    }
+
+    #[cfg(feature = "snapshot")]
+    fn try_snapshot(&self) -> GraphResult<GraphSnapshot> {
+        self.kb.graph_cell()?.borrow().try_snapshot()
     }
 }
 
@@ -109,3 +411,176 @@ impl Graph for InjectionGraph {
 pub fn print_graph_debug() {
     println!("{}", InjectionGraph::new().into_dot());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::value_wrappers::StrongValue;
+    use crate::node_wrappers::CommonNodeTrait;
+    use crate::tao::archetype::{ArchetypeFormTrait, ArchetypeTrait};
+    use crate::tao::form::Form;
+    use crate::tao::initialize_kb;
+    use crate::tao::relation::attribute::Inherits;
+
+    /// Records every event that passes the filter.
+    fn record(g: &InjectionGraph, filter: EventFilter) -> Rc<RefCell<Vec<GraphEvent>>> {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let recorded = events.clone();
+        g.subscribe(filter, move |e| recorded.borrow_mut().push(e.clone()));
+        events
+    }
+
+    #[test]
+    fn test_subscribe_all() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let events = record(&g, EventFilter::All);
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        g.set_node_name(a_id, "A");
+        g.set_node_value(a_id, KBRc::new(StrongValue::new(5)));
+        g.add_flag(a_id, b_id);
+        g.add_edge(a_id, b_id, b_id);
+        g.remove_edge(a_id, b_id, b_id);
+        g.remove_flag(a_id, b_id);
+        g.remove_node(b_id);
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                GraphEvent::NodeAdded { id: a_id },
+                GraphEvent::NodeAdded { id: b_id },
+                GraphEvent::NodeNamed {
+                    id: a_id,
+                    name: Rc::from("A")
+                },
+                GraphEvent::ValueSet { id: a_id },
+                GraphEvent::FlagAdded {
+                    id: a_id,
+                    flag: b_id
+                },
+                GraphEvent::EdgeAdded {
+                    from: a_id,
+                    edge_type: b_id,
+                    to: b_id
+                },
+                GraphEvent::EdgeRemoved {
+                    from: a_id,
+                    edge_type: b_id,
+                    to: b_id
+                },
+                GraphEvent::FlagRemoved {
+                    id: a_id,
+                    flag: b_id
+                },
+                GraphEvent::NodeRemoved { id: b_id },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_subscribe_filtered() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let edge_type1 = g.add_node();
+        let edge_type2 = g.add_node();
+        let edges = record(&g, EventFilter::EdgeType(edge_type1));
+        let flags = record(&g, EventFilter::Flag(edge_type2));
+        g.add_edge(a_id, edge_type1, a_id);
+        g.add_edge(a_id, edge_type2, a_id);
+        g.add_flag(a_id, edge_type1);
+        g.add_flag(a_id, edge_type2);
+        assert_eq!(
+            *edges.borrow(),
+            vec![GraphEvent::EdgeAdded {
+                from: a_id,
+                edge_type: edge_type1,
+                to: a_id
+            }]
+        );
+        assert_eq!(
+            *flags.borrow(),
+            vec![GraphEvent::FlagAdded {
+                id: a_id,
+                flag: edge_type2
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let count = Rc::new(RefCell::new(0));
+        let counter = count.clone();
+        let subscription = g.subscribe(EventFilter::All, move |_| *counter.borrow_mut() += 1);
+        g.add_node();
+        assert!(g.unsubscribe(subscription));
+        assert!(!g.unsubscribe(subscription));
+        g.add_node();
+        assert_eq!(*count.borrow(), 1);
+    }
+
+    #[test]
+    fn test_failed_change_not_reported() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let events = record(&g, EventFilter::All);
+        assert!(g.try_add_flag(5, 6).is_err());
+        assert_eq!(*events.borrow(), Vec::new());
+    }
+
+    #[test]
+    fn test_listener_reads_graph() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let names = Rc::new(RefCell::new(Vec::new()));
+        let recorded = names.clone();
+        g.subscribe(EventFilter::All, move |e| {
+            if let GraphEvent::NodeNamed { id, .. } = e {
+                recorded
+                    .borrow_mut()
+                    .push(InjectionGraph::new().node_name(*id));
+            }
+        });
+        let a_id = g.add_node();
+        g.set_node_name(a_id, "A");
+        assert_eq!(*names.borrow(), vec![Some(Rc::from("A"))]);
+    }
+
+    #[test]
+    fn test_listeners_per_kb() {
+        bind_in_memory_graph();
+        let g = InjectionGraph::new();
+        let events = record(&g, EventFilter::All);
+        KnowledgeBase::new_in_memory().graph().add_node();
+        assert_eq!(*events.borrow(), Vec::new());
+    }
+
+    #[test]
+    fn test_rollback_reported() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        g.begin_transaction();
+        let a_id = g.add_node();
+        let events = record(&g, EventFilter::Flag(a_id));
+        g.rollback();
+        assert_eq!(*events.borrow(), vec![GraphEvent::RolledBack]);
+    }
+
+    #[test]
+    fn test_archetype_hierarchy_updates() {
+        initialize_kb();
+        let g = InjectionGraph::new();
+        let events = record(&g, EventFilter::EdgeType(Inherits::TYPE_ID));
+        let subtype = Form::archetype().individuate_as_archetype();
+        assert_eq!(
+            *events.borrow(),
+            vec![GraphEvent::EdgeAdded {
+                from: subtype.id(),
+                edge_type: Inherits::TYPE_ID,
+                to: Form::TYPE_ID
+            }]
+        );
+    }
+}
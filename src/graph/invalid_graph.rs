@@ -1,76 +1,126 @@
-use super::Graph;
-use super::KBValue;
+#[cfg(feature = "snapshot")]
+use super::GraphSnapshot;
+use super::{Graph, GraphError, GraphResult};
+use super::{KBRc, KBValue};
 use std::rc::Rc;
 
 /// Invalid default graph.
 pub struct InvalidGraph {}
 
-impl InvalidGraph {
-    const INVALID_MSG: &'static str = "Initialize graph binding before use";
-}
-
 impl Graph for InvalidGraph {
-    fn size(&self) -> usize {
-        panic!(Self::INVALID_MSG);
+    fn try_size(&self) -> GraphResult<usize> {
+        Err(GraphError::Unbound)
+    }
+
+    fn try_add_node(&mut self) -> GraphResult<usize> {
+        Err(GraphError::Unbound)
+    }
+
+    fn try_remove_node(&mut self, _: usize) -> GraphResult<()> {
+        Err(GraphError::Unbound)
+    }
+
+    fn try_set_node_value(&mut self, _: usize, _: KBRc<dyn KBValue>) -> GraphResult<()> {
+        Err(GraphError::Unbound)
+    }
+
+    fn try_set_node_name(&mut self, _: usize, _: &str) -> GraphResult<()> {
+        Err(GraphError::Unbound)
+    }
+
+    fn try_node_name(&self, _: usize) -> GraphResult<Option<Rc<str>>> {
+        Err(GraphError::Unbound)
     }
 
-    fn add_node(&mut self) -> usize {
-        panic!(Self::INVALID_MSG);
+    fn try_node_value(&self, _: usize) -> GraphResult<Option<KBRc<dyn KBValue>>> {
+        Err(GraphError::Unbound)
     }
 
-    fn set_node_value(&mut self, _: usize, _: Rc<dyn KBValue>) {
-        panic!(Self::INVALID_MSG);
+    fn try_lookup(&self, _: &str) -> GraphResult<Vec<usize>> {
+        Err(GraphError::Unbound)
     }
 
-    fn set_node_name(&mut self, _: usize, _: &str) {
-        panic!(Self::INVALID_MSG);
+    fn try_add_flag(&mut self, _: usize, _: usize) -> GraphResult<()> {
+        Err(GraphError::Unbound)
     }
 
-    fn node_name(&self, _: usize) -> Option<Rc<str>> {
-        panic!(Self::INVALID_MSG);
+    fn try_remove_flag(&mut self, _: usize, _: usize) -> GraphResult<()> {
+        Err(GraphError::Unbound)
     }
 
-    fn node_value(&self, _: usize) -> Option<Rc<dyn KBValue>> {
-        panic!(Self::INVALID_MSG);
+    fn try_has_flag(&self, _: usize, _: usize) -> GraphResult<bool> {
+        Err(GraphError::Unbound)
     }
 
-    fn lookup(&self, _: &str) -> Vec<usize> {
-        panic!(Self::INVALID_MSG);
+    fn try_nodes_with_flag(&self, _: usize) -> GraphResult<Vec<usize>> {
+        Err(GraphError::Unbound)
     }
 
-    fn add_flag(&mut self, _: usize, _: usize) {
-        panic!(Self::INVALID_MSG);
+    fn try_add_edge(&mut self, _: usize, _: usize, _: usize) -> GraphResult<()> {
+        Err(GraphError::Unbound)
     }
 
-    fn has_flag(&self, _: usize, _: usize) -> bool {
-        panic!(Self::INVALID_MSG);
+    fn try_remove_edge(&mut self, _: usize, _: usize, _: usize) -> GraphResult<()> {
+        Err(GraphError::Unbound)
     }
 
-    fn add_edge(&mut self, _: usize, _: usize, _: usize) {
-        panic!(Self::INVALID_MSG);
+    fn try_has_edge(&self, _: usize, _: usize, _: usize) -> GraphResult<bool> {
+        Err(GraphError::Unbound)
     }
 
-    fn has_edge(&self, _: usize, _: usize, _: usize) -> bool {
-        panic!(Self::INVALID_MSG);
+    fn try_outgoing_nodes(&self, _: usize, _: usize) -> GraphResult<Vec<usize>> {
+        Err(GraphError::Unbound)
     }
 
-    fn outgoing_nodes(&self, _: usize, _: usize) -> Vec<usize> {
-        panic!(Self::INVALID_MSG)
+    fn try_incoming_nodes(&self, _: usize, _: usize) -> GraphResult<Vec<usize>> {
+        Err(GraphError::Unbound)
     }
 
-    fn incoming_nodes(&self, _: usize, _: usize) -> Vec<usize> {
-        panic!(Self::INVALID_MSG)
+    fn try_all_outgoing_nodes(&self, _: usize) -> GraphResult<Vec<usize>> {
+        Err(GraphError::Unbound)
     }
 
-    fn all_outgoing_nodes(&self, _: usize) -> Vec<usize> {
-        panic!(Self::INVALID_MSG)
+    fn try_all_incoming_nodes(&self, _: usize) -> GraphResult<Vec<usize>> {
+        Err(GraphError::Unbound)
     }
 
-    fn all_incoming_nodes(&self, _: usize) -> Vec<usize> {
-        panic!(Self::INVALID_MSG)
+    fn try_into_dot(&self) -> GraphResult<String> {
+        Err(GraphError::Unbound)
+    }
+
+    fn try_begin_transaction(&mut self) -> GraphResult<()> {
+        Err(GraphError::Unbound)
+    }
+
+    fn try_commit(&mut self) -> GraphResult<()> {
+        Err(GraphError::Unbound)
+    }
+
+    fn try_rollback(&mut self) -> GraphResult<()> {
+        Err(GraphError::Unbound)
+    }
+
+    #[cfg(feature = "snapshot")]
+    fn try_snapshot(&self) -> GraphResult<GraphSnapshot> {
+        Err(GraphError::Unbound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unbound_error() {
+        let mut g = InvalidGraph {};
+        assert_eq!(g.try_add_node(), Err(GraphError::Unbound));
+        assert_eq!(g.try_node_name(0), Err(GraphError::Unbound));
     }
 
-    fn into_dot(&self) -> String {
-        panic!(Self::INVALID_MSG)
+    #[test]
+    #[should_panic(expected = "Initialize graph binding before use")]
+    fn test_unbound_panic() {
+        let g = InvalidGraph {};
+        g.size();
     }
 }
@@ -0,0 +1,184 @@
+use super::in_memory_graph::InMemoryGraph;
+use super::injection_graph::{global_graph, rebind_global_graph, GraphCell};
+use super::invalid_graph::InvalidGraph;
+use super::{Graph, GraphError, GraphResult, InjectionGraph};
+use crate::node_wrappers::ancestor_cache;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+thread_local! {
+    /// Graphs backing every KB other than the global one, indexed by KB ID minus one. Closed KBs
+    /// leave behind an empty slot, so that their IDs never get reused.
+    static KB_GRAPHS: RefCell<Vec<Option<GraphCell>>> = RefCell::new(Vec::new());
+    static CURRENT_KB: Cell<KnowledgeBase> = const { Cell::new(KnowledgeBase::GLOBAL) };
+}
+
+/// Handle to a single knowledge base. Node wrappers and forms remember which KB they were created
+/// in, and always talk to that KB's graph, so several KBs can be open side by side.
+///
+/// New nodes get bound to the current KB, which is the global one unless changed with
+/// `with_default`. The global KB is the one that functions such as `bind_in_memory_graph` and
+/// `initialize_kb` set up.
+///
+/// Like the global binding, KB handles are specific to the thread that created them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct KnowledgeBase {
+    id: usize,
+}
+
+impl KnowledgeBase {
+    /// The global KB that nodes get bound to by default.
+    pub const GLOBAL: KnowledgeBase = KnowledgeBase { id: 0 };
+
+    /// Open a new KB backed by the given graph.
+    pub fn new(graph: Box<dyn Graph>) -> Self {
+        KB_GRAPHS.with(|graphs| {
+            let mut graphs = graphs.borrow_mut();
+            graphs.push(Some(Rc::new(RefCell::new(graph))));
+            KnowledgeBase { id: graphs.len() }
+        })
+    }
+
+    /// Open a new KB backed by a graph that sits entirely in memory.
+    pub fn new_in_memory() -> Self {
+        Self::new(Box::new(InMemoryGraph::new()))
+    }
+
+    /// The KB that newly created nodes currently get bound to.
+    pub fn current() -> Self {
+        CURRENT_KB.with(|current| current.get())
+    }
+
+    /// Whether or not this is the global KB.
+    pub fn is_global(&self) -> bool {
+        *self == Self::GLOBAL
+    }
+
+    /// The graph backing this KB.
+    pub fn graph(&self) -> InjectionGraph {
+        InjectionGraph::from(*self)
+    }
+
+    /// Runs the given closure with this as the current KB, so that all nodes created or looked up
+    /// by ID inside the closure get bound to this KB. The previous current KB is restored
+    /// afterwards, even if the closure panics.
+    pub fn with_default<T, F: FnOnce() -> T>(&self, f: F) -> T {
+        let _guard = CurrentKbGuard {
+            previous: CURRENT_KB.with(|current| current.replace(*self)),
+        };
+        f()
+    }
+
+    /// Close this KB, releasing its graph. Any further operations on nodes bound to this KB will
+    /// fail with `GraphError::Unbound`.
+    pub fn close(self) {
+        if self.is_global() {
+            rebind_global_graph(Box::new(InvalidGraph {}));
+        } else {
+            ancestor_cache::forget(self);
+            KB_GRAPHS.with(|graphs| {
+                if let Some(slot) = graphs.borrow_mut().get_mut(self.id - 1) {
+                    *slot = None;
+                }
+            });
+        }
+    }
+
+    /// The cell holding the graph for this KB.
+    pub(super) fn graph_cell(&self) -> GraphResult<GraphCell> {
+        if self.is_global() {
+            Ok(global_graph())
+        } else {
+            KB_GRAPHS.with(|graphs| {
+                graphs
+                    .borrow()
+                    .get(self.id - 1)
+                    .cloned()
+                    .flatten()
+                    .ok_or(GraphError::Unbound)
+            })
+        }
+    }
+}
+
+/// Restores the previous current KB when dropped.
+struct CurrentKbGuard {
+    previous: KnowledgeBase,
+}
+
+impl Drop for CurrentKbGuard {
+    fn drop(&mut self) {
+        CURRENT_KB.with(|current| current.set(self.previous));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::bind_in_memory_graph;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[test]
+    fn test_global_by_default() {
+        assert_eq!(KnowledgeBase::current(), KnowledgeBase::GLOBAL);
+        assert!(KnowledgeBase::current().is_global());
+        assert_eq!(InjectionGraph::new().kb(), KnowledgeBase::GLOBAL);
+    }
+
+    #[test]
+    fn test_independent_kbs() {
+        bind_in_memory_graph();
+        let kb1 = KnowledgeBase::new_in_memory();
+        let kb2 = KnowledgeBase::new_in_memory();
+        assert_ne!(kb1, kb2);
+        let mut g1 = kb1.graph();
+        let mut g2 = kb2.graph();
+        let a_id = g1.add_node();
+        g1.set_node_name(a_id, "A");
+        let b_id = g2.add_node();
+        g2.set_node_name(b_id, "B");
+        assert_eq!(g1.node_name(a_id), Some(Rc::from("A")));
+        assert_eq!(g2.node_name(b_id), Some(Rc::from("B")));
+        assert_eq!(InjectionGraph::new().size(), 0);
+    }
+
+    #[test]
+    fn test_with_default() {
+        let kb = KnowledgeBase::new_in_memory();
+        let nested = KnowledgeBase::new_in_memory();
+        kb.with_default(|| {
+            assert_eq!(KnowledgeBase::current(), kb);
+            nested.with_default(|| assert_eq!(InjectionGraph::new().kb(), nested));
+            assert_eq!(InjectionGraph::new().kb(), kb);
+        });
+        assert_eq!(KnowledgeBase::current(), KnowledgeBase::GLOBAL);
+    }
+
+    #[test]
+    fn test_with_default_panic() {
+        let kb = KnowledgeBase::new_in_memory();
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            kb.with_default(|| panic!("Failing inside another KB"));
+        }));
+        assert!(result.is_err());
+        assert_eq!(KnowledgeBase::current(), KnowledgeBase::GLOBAL);
+    }
+
+    #[test]
+    fn test_close() {
+        let kb = KnowledgeBase::new_in_memory();
+        let mut g = kb.graph();
+        g.add_node();
+        kb.close();
+        assert_eq!(g.try_size(), Err(GraphError::Unbound));
+        // closed IDs are never handed out again
+        assert_ne!(KnowledgeBase::new_in_memory(), kb);
+    }
+
+    #[test]
+    fn test_close_global() {
+        bind_in_memory_graph();
+        KnowledgeBase::GLOBAL.close();
+        assert_eq!(InjectionGraph::new().try_size(), Err(GraphError::Unbound));
+    }
+}
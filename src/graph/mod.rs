@@ -45,19 +45,19 @@
 //! assert_eq!(g.node_name(a_id), Some(Rc::from("A")));
 //! ```
 //!
-//! We can also set a value for the node. We use `Rc` here because Yin being the map and not the
-//! territory, we generally don't want to have Yin itself own the data being operated on.
+//! We can also set a value for the node. We use `KBRc` here because Yin being the map and not the
+//! territory, we generally don't want to have Yin itself own the data being operated on. `KBRc` is
+//! just an `Rc`, unless the `thread-safe` feature is turned on, in which case it's an `Arc`.
 //!
 //! ```rust
 //! # use zamm_yin::graph::{bind_in_memory_graph, InjectionGraph, Graph};
 //! # bind_in_memory_graph();
 //! # let mut g = InjectionGraph::new();
 //! # let a_id = g.add_node();
-//! use zamm_yin::graph::value_wrappers::{unwrap_value, WeakValue};
-//! use std::rc::Rc;
+//! use zamm_yin::graph::value_wrappers::{unwrap_value, KBRc, WeakValue};
 //!
-//! let v = Rc::new(5);
-//! g.set_node_value(a_id, Rc::new(WeakValue::new(&v)));
+//! let v = KBRc::new(5);
+//! g.set_node_value(a_id, KBRc::new(WeakValue::new(&v)));
 //! assert_eq!(unwrap_value::<i32>(g.node_value(a_id)), Some(v));
 //! ```
 //!
@@ -97,26 +97,161 @@
 //! assert_eq!(g.outgoing_nodes(c_id, edge_type2), vec![a_id]);
 //! ```
 //!
+//! Large sets of neighbors don't have to be retrieved all at once. The `iter_*` variants fetch
+//! them lazily, so they can be cut short:
+//!
+//! ```rust
+//! # use zamm_yin::graph::{bind_in_memory_graph, InjectionGraph, Graph};
+//! # bind_in_memory_graph();
+//! # let mut g = InjectionGraph::new();
+//! # let a_id = g.add_node();
+//! # let b_id = g.add_node();
+//! # let d_id = g.add_node();
+//! # let edge_type1 = g.add_node();
+//! # g.add_edge(b_id, edge_type1, a_id);
+//! # g.add_edge(d_id, edge_type1, a_id);
+//! assert!(g.iter_incoming_nodes(a_id, edge_type1).any(|id| id == d_id));
+//! assert_eq!(g.iter_all_incoming_nodes(a_id).next(), Some(b_id));
+//! ```
+//!
+//! Facts can be retracted as well. Removing a node also removes every edge connected to it:
+//!
+//! ```rust
+//! # use zamm_yin::graph::{bind_in_memory_graph, InjectionGraph, Graph};
+//! # bind_in_memory_graph();
+//! # let mut g = InjectionGraph::new();
+//! # let a_id = g.add_node();
+//! # let b_id = g.add_node();
+//! # let c_id = g.add_node();
+//! # let d_id = g.add_node();
+//! # let edge_type1 = g.add_node();
+//! # let edge_type2 = g.add_node();
+//! # g.add_edge(b_id, edge_type1, a_id);
+//! # g.add_edge(c_id, edge_type2, a_id);
+//! # g.add_edge(d_id, edge_type1, a_id);
+//! g.remove_edge(b_id, edge_type1, a_id);
+//! assert_eq!(g.incoming_nodes(a_id, edge_type1), vec![d_id]);
+//!
+//! g.remove_node(d_id);
+//! assert_eq!(g.all_incoming_nodes(a_id), vec![c_id]);
+//! ```
+//!
+//! Every graph operation also has a fallible `try_*` counterpart that reports problems such as
+//! stale node IDs as errors instead of panicking:
+//!
+//! ```rust
+//! # use zamm_yin::graph::{bind_in_memory_graph, InjectionGraph, Graph};
+//! # bind_in_memory_graph();
+//! # let mut g = InjectionGraph::new();
+//! use zamm_yin::graph::GraphError;
+//!
+//! let stale_id = g.add_node();
+//! g.remove_node(stale_id);
+//! assert_eq!(g.try_node_name(stale_id), Err(GraphError::UnknownNode(stale_id)));
+//! ```
+//!
+//! The whole graph can be copied out into a snapshot, which can then be saved to disk as either
+//! JSON or a compact binary encoding. Only values of basic types such as strings and numbers are
+//! kept in the snapshot:
+//!
+//! ```rust
+//! # use zamm_yin::graph::{bind_in_memory_graph, InjectionGraph, Graph};
+//! # bind_in_memory_graph();
+//! # let mut g = InjectionGraph::new();
+//! # let a_id = g.add_node();
+//! # g.set_node_name(a_id, "A");
+//! use zamm_yin::graph::{bind_in_memory_snapshot, GraphSnapshot};
+//! use std::rc::Rc;
+//!
+//! let bytes = g.snapshot().to_bytes();
+//!
+//! bind_in_memory_graph(); // start over with an empty graph
+//! bind_in_memory_snapshot(&GraphSnapshot::from_bytes(&bytes).unwrap()).unwrap();
+//! assert_eq!(g.node_name(a_id), Some(Rc::from("A")));
+//! ```
+//!
+//! Groups of changes can be made atomic with a transaction. Everything done within a rolled-back
+//! transaction gets undone:
+//!
+//! ```rust
+//! # use zamm_yin::graph::{bind_in_memory_graph, InjectionGraph, Graph};
+//! # bind_in_memory_graph();
+//! # let mut g = InjectionGraph::new();
+//! # let a_id = g.add_node();
+//! # let edge_type1 = g.add_node();
+//! let size_before = g.size();
+//! g.begin_transaction();
+//! let e_id = g.add_node();
+//! g.add_edge(e_id, edge_type1, a_id);
+//! g.rollback();
+//!
+//! assert_eq!(g.size(), size_before);
+//! assert_eq!(g.incoming_nodes(a_id, edge_type1), Vec::<usize>::new());
+//! ```
+//!
+//! Everything so far has gone through the global KB. Additional KBs can be opened side by side
+//! with it, each one with its own graph:
+//!
+//! ```rust
+//! # use zamm_yin::graph::{bind_in_memory_graph, InjectionGraph, Graph};
+//! # bind_in_memory_graph();
+//! # let mut g = InjectionGraph::new();
+//! use zamm_yin::graph::KnowledgeBase;
+//!
+//! let scratch = KnowledgeBase::new_in_memory();
+//! let mut scratch_graph = scratch.graph();
+//! let size_before = g.size();
+//! scratch_graph.add_node();
+//!
+//! assert_eq!(scratch_graph.size(), 1);
+//! assert_eq!(g.size(), size_before);
+//! ```
+//!
+//! Callers can subscribe to changes made to a KB, optionally only hearing about edges or flags of a
+//! specific type:
+//!
+//! ```rust
+//! # use zamm_yin::graph::{bind_in_memory_graph, InjectionGraph, Graph};
+//! # bind_in_memory_graph();
+//! # let mut g = InjectionGraph::new();
+//! # let a_id = g.add_node();
+//! # let edge_type1 = g.add_node();
+//! # let edge_type2 = g.add_node();
+//! use zamm_yin::graph::{EventFilter, GraphEvent};
+//! use std::cell::RefCell;
+//! use std::rc::Rc;
+//!
+//! let new_edges = Rc::new(RefCell::new(Vec::new()));
+//! let recorded = new_edges.clone();
+//! g.subscribe(EventFilter::EdgeType(edge_type1), move |event| {
+//!     if let GraphEvent::EdgeAdded { from, .. } = event {
+//!         recorded.borrow_mut().push(*from);
+//!     }
+//! });
+//! let f_id = g.add_node();
+//! g.add_edge(f_id, edge_type1, a_id);
+//! g.add_edge(f_id, edge_type2, a_id);
+//!
+//! assert_eq!(*new_edges.borrow(), vec![f_id]);
+//! ```
+//!
 //! We can also use the KB to invoke certain functionality. Note that we are passing in a `Form`
 //! concept to the callback function because that's the only supported function at this moment.
 //!
 //! ```rust
 //! # use zamm_yin::graph::{bind_in_memory_graph, InjectionGraph, Graph};
-//! # use zamm_yin::graph::value_wrappers::{unwrap_value, WeakValue};
-//! # use std::rc::Rc;
+//! # use zamm_yin::graph::value_wrappers::{unwrap_value, KBRc, WeakValue};
 //! # bind_in_memory_graph();
 //! # let mut g = InjectionGraph::new();
 //! use zamm_yin::tao::archetype::ArchetypeTrait;
 //! use zamm_yin::tao::form::{Form, FormTrait};
-//! use zamm_yin::graph::value_wrappers::{run_closure, StrongValue, KBClosure};
+//! use zamm_yin::graph::value_wrappers::run_closure;
 //! use zamm_yin::define_closure;
 //! use zamm_yin::node_wrappers::BaseNodeTrait;
-//! use std::any::Any;
-//! use std::cell::{RefCell, RefMut};
 //!
 //! let count_id = g.add_node();
-//! let count_value: Rc<i64> = Rc::new(5);
-//! g.set_node_value(count_id, Rc::new(WeakValue::new(&count_value)));
+//! let count_value: KBRc<i64> = KBRc::new(5);
+//! g.set_node_value(count_id, KBRc::new(WeakValue::new(&count_value)));
 //!
 //! let mut triple_id = g.add_node();
 //! g.set_node_value(triple_id, define_closure!(|t: Form| {
@@ -134,11 +269,22 @@
 //! the actual computation of Dijkstra's algorithm should involve low-level data structures and
 //! logic outside of the KB.
 
+mod adjacency_index;
 #[cfg(feature = "cypher")]
 mod cypher_graph;
+mod graph_error;
+mod graph_events;
 mod in_memory_graph;
 mod injection_graph;
 mod invalid_graph;
+mod knowledge_base;
+mod node_iter;
+#[cfg(feature = "thread-safe")]
+mod shared_graph;
+#[cfg(feature = "snapshot")]
+mod snapshot;
+#[cfg(feature = "sqlite")]
+mod sqlite_graph;
 /// Wrappers around values associated with nodes in the KB. This differs from the other
 /// [`wrappers`](../wrappers/index.html) package because this abstraction only wraps the
 /// values associated with nodes, while the other one wraps the nodes themselves.
@@ -147,62 +293,363 @@ mod invalid_graph;
 /// wrapper.
 pub mod value_wrappers;
 
-use crate::graph::value_wrappers::KBValue;
+use crate::graph::value_wrappers::{KBRc, KBValue};
+pub(crate) use graph_error::expect_graph;
+pub use graph_error::{GraphError, GraphResult};
+pub use graph_events::{EventFilter, GraphEvent, Subscription};
 #[cfg(feature = "cypher")]
 pub use injection_graph::bind_cypher_graph;
+#[cfg(feature = "snapshot")]
+pub use injection_graph::bind_in_memory_snapshot;
+#[cfg(feature = "sqlite")]
+pub use injection_graph::bind_sqlite_graph;
 pub use injection_graph::{bind_in_memory_graph, print_graph_debug, InjectionGraph};
+#[cfg(feature = "thread-safe")]
+pub use injection_graph::{bind_shared_graph, bind_shared_in_memory_graph};
+pub use knowledge_base::KnowledgeBase;
+pub use node_iter::{NodeIter, NodeQuery, NODE_PAGE_SIZE};
+#[cfg(feature = "thread-safe")]
+pub use shared_graph::SharedGraph;
+#[cfg(feature = "snapshot")]
+pub use snapshot::{
+    EdgeSnapshot, GraphSnapshot, NodeSnapshot, SnapshotFormat, SnapshotValue, SNAPSHOT_VERSION,
+};
 
 use std::rc::Rc;
 
 /// A classic directed Graph with nodes and labeled links.
+///
+/// Every operation comes in two flavors. The `try_*` versions report unknown node IDs, unbound
+/// graphs, and backend failures as a `GraphError`, and are the ones that graph implementations
+/// need to define. The plain versions are provided for convenience, and panic whenever their
+/// fallible counterparts return an error.
 pub trait Graph {
+    /// Whether every change to this graph goes through the current thread, as is the case for
+    /// graphs that live in this process's memory and aren't shared with other threads. Only such
+    /// graphs get anything about them cached, because changes made by other threads or processes
+    /// don't get reported to listeners.
+    fn is_private(&self) -> bool {
+        false
+    }
+
     /// The number of nodes in the graph.
-    fn size(&self) -> usize;
+    fn size(&self) -> usize {
+        expect_graph(self.try_size())
+    }
+
+    /// Fallible version of `size`.
+    fn try_size(&self) -> GraphResult<usize>;
 
     /// Adds a new node to the graph, and returns the node's ID.
-    fn add_node(&mut self) -> usize;
+    fn add_node(&mut self) -> usize {
+        expect_graph(self.try_add_node())
+    }
+
+    /// Fallible version of `add_node`.
+    fn try_add_node(&mut self) -> GraphResult<usize>;
+
+    /// Removes a node from the graph, along with all edges leading to and from it. The ID of the
+    /// removed node may be reused by nodes that are added later.
+    fn remove_node(&mut self, id: usize) {
+        expect_graph(self.try_remove_node(id))
+    }
+
+    /// Fallible version of `remove_node`.
+    fn try_remove_node(&mut self, id: usize) -> GraphResult<()>;
 
     /// Sets the name for a given node. Names can only be set once.
-    fn set_node_name(&mut self, id: usize, name: &str);
+    fn set_node_name(&mut self, id: usize, name: &str) {
+        expect_graph(self.try_set_node_name(id, name))
+    }
+
+    /// Fallible version of `set_node_name`.
+    fn try_set_node_name(&mut self, id: usize, name: &str) -> GraphResult<()>;
 
     /// Sets the value for a given node. Values can only be set once.
-    fn set_node_value(&mut self, id: usize, value: Rc<dyn KBValue>);
+    fn set_node_value(&mut self, id: usize, value: KBRc<dyn KBValue>) {
+        expect_graph(self.try_set_node_value(id, value))
+    }
+
+    /// Fallible version of `set_node_value`.
+    fn try_set_node_value(&mut self, id: usize, value: KBRc<dyn KBValue>) -> GraphResult<()>;
 
     /// Retrieve's a node's name from the graph, or None if the node does not exist or is unnamed.
-    fn node_name(&self, id: usize) -> Option<Rc<str>>;
+    fn node_name(&self, id: usize) -> Option<Rc<str>> {
+        match self.try_node_name(id) {
+            Err(GraphError::UnknownNode(_)) => None,
+            result => expect_graph(result),
+        }
+    }
+
+    /// Retrieve's a node's name from the graph, or None if the node is unnamed. Unlike
+    /// `node_name`, a nonexistent node results in an error.
+    fn try_node_name(&self, id: usize) -> GraphResult<Option<Rc<str>>>;
 
     /// Retrieve's a node's name from the graph, or None if the node does not exist or does not
     /// have a value.
-    fn node_value(&self, id: usize) -> Option<Rc<dyn KBValue>>;
+    fn node_value(&self, id: usize) -> Option<KBRc<dyn KBValue>> {
+        match self.try_node_value(id) {
+            Err(GraphError::UnknownNode(_)) => None,
+            result => expect_graph(result),
+        }
+    }
+
+    /// Retrieve's a node's value from the graph, or None if the node does not have a value.
+    /// Unlike `node_value`, a nonexistent node results in an error.
+    fn try_node_value(&self, id: usize) -> GraphResult<Option<KBRc<dyn KBValue>>>;
 
     /// Look up a node ID based on name. A vec is returned because there are no constraints on name
     /// uniqueness.
-    fn lookup(&self, name: &str) -> Vec<usize>;
+    fn lookup(&self, name: &str) -> Vec<usize> {
+        expect_graph(self.try_lookup(name))
+    }
+
+    /// Fallible version of `lookup`.
+    fn try_lookup(&self, name: &str) -> GraphResult<Vec<usize>>;
 
     /// Add a flag to a node. The flag should be the ID of an existing node.
-    fn add_flag(&mut self, id: usize, flag: usize);
+    fn add_flag(&mut self, id: usize, flag: usize) {
+        expect_graph(self.try_add_flag(id, flag))
+    }
+
+    /// Fallible version of `add_flag`.
+    fn try_add_flag(&mut self, id: usize, flag: usize) -> GraphResult<()>;
+
+    /// Remove a flag from a node. Nothing happens if the node did not have the flag set.
+    fn remove_flag(&mut self, id: usize, flag: usize) {
+        expect_graph(self.try_remove_flag(id, flag))
+    }
+
+    /// Fallible version of `remove_flag`.
+    fn try_remove_flag(&mut self, id: usize, flag: usize) -> GraphResult<()>;
 
     /// Return true if this node has the flag set, false otherwise.
-    fn has_flag(&self, id: usize, flag: usize) -> bool;
+    fn has_flag(&self, id: usize, flag: usize) -> bool {
+        expect_graph(self.try_has_flag(id, flag))
+    }
+
+    /// Fallible version of `has_flag`.
+    fn try_has_flag(&self, id: usize, flag: usize) -> GraphResult<bool>;
+
+    /// Retrieve the IDs of all nodes that have this flag set.
+    fn nodes_with_flag(&self, flag: usize) -> Vec<usize> {
+        expect_graph(self.try_nodes_with_flag(flag))
+    }
+
+    /// Fallible version of `nodes_with_flag`.
+    fn try_nodes_with_flag(&self, flag: usize) -> GraphResult<Vec<usize>>;
 
     /// Add a labeled edge between two nodes. The label should be the ID of an existing node.
-    fn add_edge(&mut self, from: usize, edge_type: usize, to: usize);
+    fn add_edge(&mut self, from: usize, edge_type: usize, to: usize) {
+        expect_graph(self.try_add_edge(from, edge_type, to))
+    }
+
+    /// Fallible version of `add_edge`.
+    fn try_add_edge(&mut self, from: usize, edge_type: usize, to: usize) -> GraphResult<()>;
+
+    /// Remove all labeled edges of the given type between two nodes. Nothing happens if no such
+    /// edge exists.
+    fn remove_edge(&mut self, from: usize, edge_type: usize, to: usize) {
+        expect_graph(self.try_remove_edge(from, edge_type, to))
+    }
+
+    /// Fallible version of `remove_edge`.
+    fn try_remove_edge(&mut self, from: usize, edge_type: usize, to: usize) -> GraphResult<()>;
 
     /// Checks for a labeled edge between two nodes. The label should be the ID of an existing node.
-    fn has_edge(&self, from: usize, edge_type: usize, to: usize) -> bool;
+    fn has_edge(&self, from: usize, edge_type: usize, to: usize) -> bool {
+        expect_graph(self.try_has_edge(from, edge_type, to))
+    }
+
+    /// Fallible version of `has_edge`.
+    fn try_has_edge(&self, from: usize, edge_type: usize, to: usize) -> GraphResult<bool>;
 
     /// Retrieve all node IDs that are on the other end of an outgoing edge of the given type.
-    fn outgoing_nodes(&self, from: usize, edge_type: usize) -> Vec<usize>;
+    fn outgoing_nodes(&self, from: usize, edge_type: usize) -> Vec<usize> {
+        expect_graph(self.try_outgoing_nodes(from, edge_type))
+    }
+
+    /// Fallible version of `outgoing_nodes`.
+    fn try_outgoing_nodes(&self, from: usize, edge_type: usize) -> GraphResult<Vec<usize>>;
+
+    /// Like `outgoing_nodes`, but in the order that the first edge to each node was added, with
+    /// each node listed only once. Backends that don't keep track of this fall back on ordering
+    /// by ID.
+    fn outgoing_nodes_in_order(&self, from: usize, edge_type: usize) -> Vec<usize> {
+        expect_graph(self.try_outgoing_nodes_in_order(from, edge_type))
+    }
+
+    /// Fallible version of `outgoing_nodes_in_order`.
+    fn try_outgoing_nodes_in_order(
+        &self,
+        from: usize,
+        edge_type: usize,
+    ) -> GraphResult<Vec<usize>> {
+        let mut nodes = self.try_outgoing_nodes(from, edge_type)?;
+        nodes.dedup();
+        Ok(nodes)
+    }
 
     /// Retrieve all node IDs that are on the other end of an incoming edge of the given type.
-    fn incoming_nodes(&self, to: usize, edge_type: usize) -> Vec<usize>;
+    fn incoming_nodes(&self, to: usize, edge_type: usize) -> Vec<usize> {
+        expect_graph(self.try_incoming_nodes(to, edge_type))
+    }
+
+    /// Fallible version of `incoming_nodes`.
+    fn try_incoming_nodes(&self, to: usize, edge_type: usize) -> GraphResult<Vec<usize>>;
 
     /// Retrieve all node IDs that are on the other end of outgoing edges.
-    fn all_outgoing_nodes(&self, from: usize) -> Vec<usize>;
+    fn all_outgoing_nodes(&self, from: usize) -> Vec<usize> {
+        expect_graph(self.try_all_outgoing_nodes(from))
+    }
+
+    /// Fallible version of `all_outgoing_nodes`.
+    fn try_all_outgoing_nodes(&self, from: usize) -> GraphResult<Vec<usize>>;
 
     /// Retrieve all node IDs that are on the other end of incoming edges.
-    fn all_incoming_nodes(&self, to: usize) -> Vec<usize>;
+    fn all_incoming_nodes(&self, to: usize) -> Vec<usize> {
+        expect_graph(self.try_all_incoming_nodes(to))
+    }
+
+    /// Fallible version of `all_incoming_nodes`.
+    fn try_all_incoming_nodes(&self, to: usize) -> GraphResult<Vec<usize>>;
+
+    /// Retrieve up to `limit` of the nodes matching the query, in ascending order of ID, starting
+    /// with the first one after the node with ID `after`. Each node is returned only once, even if
+    /// it is connected by several edges. This allows large result sets to be paged through.
+    fn node_page(&self, query: &NodeQuery, after: Option<usize>, limit: usize) -> Vec<usize> {
+        expect_graph(self.try_node_page(query, after, limit))
+    }
+
+    /// Fallible version of `node_page`.
+    ///
+    /// The default implementation retrieves every matching node and then picks out the requested
+    /// page. Implementations that can do better should override it.
+    fn try_node_page(
+        &self,
+        query: &NodeQuery,
+        after: Option<usize>,
+        limit: usize,
+    ) -> GraphResult<Vec<usize>> {
+        let mut ids = match query {
+            NodeQuery::Outgoing { from, edge_type } => {
+                self.try_outgoing_nodes(*from, *edge_type)?
+            }
+            NodeQuery::Incoming { to, edge_type } => self.try_incoming_nodes(*to, *edge_type)?,
+            NodeQuery::AllOutgoing { from } => self.try_all_outgoing_nodes(*from)?,
+            NodeQuery::AllIncoming { to } => self.try_all_incoming_nodes(*to)?,
+            NodeQuery::Lookup { name } => self.try_lookup(name)?,
+            NodeQuery::Flagged { flag } => self.try_nodes_with_flag(*flag)?,
+        };
+        ids.dedup();
+        Ok(ids
+            .into_iter()
+            .filter(|id| Some(*id) > after)
+            .take(limit)
+            .collect())
+    }
+
+    /// Lazy version of `outgoing_nodes`. See `NodeIter` for details.
+    fn iter_outgoing_nodes(&self, from: usize, edge_type: usize) -> NodeIter<'_>
+    where
+        Self: Sized,
+    {
+        NodeIter::new(self, NodeQuery::Outgoing { from, edge_type })
+    }
+
+    /// Lazy version of `incoming_nodes`. See `NodeIter` for details.
+    fn iter_incoming_nodes(&self, to: usize, edge_type: usize) -> NodeIter<'_>
+    where
+        Self: Sized,
+    {
+        NodeIter::new(self, NodeQuery::Incoming { to, edge_type })
+    }
+
+    /// Lazy version of `all_outgoing_nodes`. See `NodeIter` for details.
+    fn iter_all_outgoing_nodes(&self, from: usize) -> NodeIter<'_>
+    where
+        Self: Sized,
+    {
+        NodeIter::new(self, NodeQuery::AllOutgoing { from })
+    }
+
+    /// Lazy version of `all_incoming_nodes`. See `NodeIter` for details.
+    fn iter_all_incoming_nodes(&self, to: usize) -> NodeIter<'_>
+    where
+        Self: Sized,
+    {
+        NodeIter::new(self, NodeQuery::AllIncoming { to })
+    }
+
+    /// Lazy version of `nodes_with_flag`. See `NodeIter` for details.
+    fn iter_nodes_with_flag(&self, flag: usize) -> NodeIter<'_>
+    where
+        Self: Sized,
+    {
+        NodeIter::new(self, NodeQuery::Flagged { flag })
+    }
+
+    /// Lazy version of `lookup`. See `NodeIter` for details.
+    fn iter_lookup(&self, name: &str) -> NodeIter<'_>
+    where
+        Self: Sized,
+    {
+        NodeIter::new(
+            self,
+            NodeQuery::Lookup {
+                name: name.to_owned(),
+            },
+        )
+    }
 
     /// Outputs the entire graph in DOT format.
-    fn into_dot(&self) -> String;
+    fn into_dot(&self) -> String {
+        expect_graph(self.try_into_dot())
+    }
+
+    /// Fallible version of `into_dot`.
+    fn try_into_dot(&self) -> GraphResult<String>;
+
+    /// Starts a transaction. None of the changes made from here on will be final until `commit`
+    /// is called, and all of them can be reversed with `rollback`.
+    ///
+    /// Transactions can be nested. Committing an inner transaction merely folds its changes into
+    /// the outer one, while rolling it back only reverses changes made since it began.
+    ///
+    /// Not every backend can roll back an inner transaction on its own. Those that can't return an
+    /// error from `rollback` instead, and mark the outermost transaction as rollback-only.
+    /// Committing that transaction then rolls back all of its changes and returns
+    /// `GraphError::RollbackOnly`, so that a half-undone transaction never gets committed.
+    fn begin_transaction(&mut self) {
+        expect_graph(self.try_begin_transaction())
+    }
+
+    /// Fallible version of `begin_transaction`.
+    fn try_begin_transaction(&mut self) -> GraphResult<()>;
+
+    /// Keeps all changes made since the innermost open transaction began.
+    fn commit(&mut self) {
+        expect_graph(self.try_commit())
+    }
+
+    /// Fallible version of `commit`.
+    fn try_commit(&mut self) -> GraphResult<()>;
+
+    /// Reverses all changes made since the innermost open transaction began.
+    fn rollback(&mut self) {
+        expect_graph(self.try_rollback())
+    }
+
+    /// Fallible version of `rollback`.
+    fn try_rollback(&mut self) -> GraphResult<()>;
+
+    /// Copies out the entire graph, so that it can be saved to disk and reloaded later.
+    #[cfg(feature = "snapshot")]
+    fn snapshot(&self) -> GraphSnapshot {
+        expect_graph(self.try_snapshot())
+    }
+
+    /// Fallible version of `snapshot`.
+    #[cfg(feature = "snapshot")]
+    fn try_snapshot(&self) -> GraphResult<GraphSnapshot>;
 }
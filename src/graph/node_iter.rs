@@ -0,0 +1,191 @@
+use super::{expect_graph, Graph, InjectionGraph};
+use std::vec;
+
+/// How many node IDs a `NodeIter` fetches from the graph at a time.
+pub const NODE_PAGE_SIZE: usize = 64;
+
+/// A set of nodes that can be retrieved from the graph one page at a time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NodeQuery {
+    /// Nodes that `from` links to via edges of type `edge_type`.
+    Outgoing {
+        /// ID of the node the edges start from.
+        from: usize,
+        /// The type of the edges.
+        edge_type: usize,
+    },
+    /// Nodes that link to `to` via edges of type `edge_type`.
+    Incoming {
+        /// ID of the node the edges lead to.
+        to: usize,
+        /// The type of the edges.
+        edge_type: usize,
+    },
+    /// Nodes that `from` links to via edges of any type.
+    AllOutgoing {
+        /// ID of the node the edges start from.
+        from: usize,
+    },
+    /// Nodes that link to `to` via edges of any type.
+    AllIncoming {
+        /// ID of the node the edges lead to.
+        to: usize,
+    },
+    /// Nodes with the given name.
+    Lookup {
+        /// The name to look for.
+        name: String,
+    },
+    /// Nodes that have the given flag set.
+    Flagged {
+        /// The flag to look for.
+        flag: usize,
+    },
+}
+
+/// Lazily iterates over the results of a `NodeQuery`, fetching them from the graph a page at a
+/// time. Nodes come out in ascending order of ID, and each node only comes out once even if it is
+/// connected by several edges.
+///
+/// Like the non-fallible graph functions, this will panic if the graph fails partway through, for
+/// example because the node being queried got removed.
+pub struct NodeIter<'a> {
+    graph: Source<'a>,
+    query: NodeQuery,
+    page: vec::IntoIter<usize>,
+    last: Option<usize>,
+    exhausted: bool,
+}
+
+/// Where a `NodeIter` gets its nodes from.
+enum Source<'a> {
+    Borrowed(&'a dyn Graph),
+    Kb(InjectionGraph),
+}
+
+impl<'a> NodeIter<'a> {
+    /// Iterate over the results of the query on the given graph.
+    pub fn new(graph: &'a dyn Graph, query: NodeQuery) -> Self {
+        Self::from_source(Source::Borrowed(graph), query)
+    }
+
+    fn from_source(graph: Source<'a>, query: NodeQuery) -> Self {
+        Self {
+            graph,
+            query,
+            page: Vec::new().into_iter(),
+            last: None,
+            exhausted: false,
+        }
+    }
+}
+
+impl NodeIter<'static> {
+    /// Iterate over the results of the query on the graph of a KB. The iterator holds onto its own
+    /// handle to the KB, so it doesn't borrow anything.
+    pub fn in_kb(graph: InjectionGraph, query: NodeQuery) -> Self {
+        Self::from_source(Source::Kb(graph), query)
+    }
+}
+
+impl<'a> Iterator for NodeIter<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if let Some(id) = self.page.next() {
+            self.last = Some(id);
+            return Some(id);
+        }
+        if self.exhausted {
+            return None;
+        }
+        let graph: &dyn Graph = match &self.graph {
+            Source::Borrowed(graph) => *graph,
+            Source::Kb(graph) => graph,
+        };
+        let page = expect_graph(graph.try_node_page(&self.query, self.last, NODE_PAGE_SIZE));
+        self.exhausted = page.len() < NODE_PAGE_SIZE;
+        self.page = page.into_iter();
+        let id = self.page.next()?;
+        self.last = Some(id);
+        Some(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::bind_in_memory_graph;
+
+    #[test]
+    fn test_iter_across_pages() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let edge_type = g.add_node();
+        let hub = g.add_node();
+        let mut expected = Vec::new();
+        for _ in 0..(NODE_PAGE_SIZE * 2 + 5) {
+            let id = g.add_node();
+            g.add_edge(hub, edge_type, id);
+            expected.push(id);
+        }
+        let query = NodeQuery::Outgoing {
+            from: hub,
+            edge_type,
+        };
+        assert_eq!(NodeIter::new(&g, query).collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_iter_exact_page() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let edge_type = g.add_node();
+        let hub = g.add_node();
+        for _ in 0..NODE_PAGE_SIZE {
+            let id = g.add_node();
+            g.add_edge(id, edge_type, hub);
+        }
+        let query = NodeQuery::Incoming { to: hub, edge_type };
+        assert_eq!(NodeIter::new(&g, query).count(), NODE_PAGE_SIZE);
+    }
+
+    #[test]
+    fn test_iter_duplicate_edges() {
+        bind_in_memory_graph();
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let edge_type = g.add_node();
+        g.add_edge(a_id, edge_type, b_id);
+        g.add_edge(a_id, edge_type, b_id);
+        g.add_edge(a_id, a_id, b_id);
+        let query = NodeQuery::AllOutgoing { from: a_id };
+        assert_eq!(NodeIter::new(&g, query).collect::<Vec<_>>(), vec![b_id]);
+    }
+
+    #[test]
+    fn test_iter_in_kb() {
+        bind_in_memory_graph();
+        let iter = {
+            let mut g = InjectionGraph::new();
+            let a_id = g.add_node();
+            g.set_node_name(a_id, "A");
+            NodeIter::in_kb(
+                g,
+                NodeQuery::Lookup {
+                    name: "A".to_owned(),
+                },
+            )
+        };
+        assert_eq!(iter.collect::<Vec<_>>(), vec![0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_iter_unknown_node() {
+        bind_in_memory_graph();
+        let g = InjectionGraph::new();
+        NodeIter::new(&g, NodeQuery::AllIncoming { to: 5 }).next();
+    }
+}
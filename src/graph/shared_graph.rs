@@ -0,0 +1,262 @@
+use super::in_memory_graph::InMemoryGraph;
+#[cfg(feature = "snapshot")]
+use super::GraphSnapshot;
+use super::{Graph, GraphError, GraphResult, KBRc, KBValue, NodeQuery};
+use std::rc::Rc;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// In-memory graph that can be shared between threads. Cloning a `SharedGraph` only clones the
+/// handle, so every clone refers to the same underlying graph. Any number of threads can read from
+/// the graph at the same time, but writes get exclusive access.
+///
+/// Transactions apply to the graph as a whole rather than to any one thread. Rolling back undoes
+/// the changes made by every thread since the transaction began.
+#[derive(Clone)]
+pub struct SharedGraph {
+    graph: Arc<RwLock<InMemoryGraph>>,
+}
+
+impl SharedGraph {
+    /// Constructs a new empty graph that can be shared between threads.
+    pub fn new() -> Self {
+        Self {
+            graph: Arc::new(RwLock::new(InMemoryGraph::new())),
+        }
+    }
+
+    fn read(&self) -> GraphResult<RwLockReadGuard<'_, InMemoryGraph>> {
+        self.graph.read().map_err(|_| poisoned())
+    }
+
+    fn write(&self) -> GraphResult<RwLockWriteGuard<'_, InMemoryGraph>> {
+        self.graph.write().map_err(|_| poisoned())
+    }
+}
+
+impl Default for SharedGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Error for when another thread panicked while writing to the graph.
+fn poisoned() -> GraphError {
+    GraphError::Backend("Shared graph poisoned by a panic in another thread".to_owned())
+}
+
+impl Graph for SharedGraph {
+    fn try_size(&self) -> GraphResult<usize> {
+        self.read()?.try_size()
+    }
+
+    fn try_add_node(&mut self) -> GraphResult<usize> {
+        self.write()?.try_add_node()
+    }
+
+    fn try_remove_node(&mut self, id: usize) -> GraphResult<()> {
+        self.write()?.try_remove_node(id)
+    }
+
+    fn try_set_node_value(&mut self, id: usize, value: KBRc<dyn KBValue>) -> GraphResult<()> {
+        self.write()?.try_set_node_value(id, value)
+    }
+
+    fn try_set_node_name(&mut self, id: usize, name: &str) -> GraphResult<()> {
+        self.write()?.try_set_node_name(id, name)
+    }
+
+    fn try_node_name(&self, id: usize) -> GraphResult<Option<Rc<str>>> {
+        self.read()?.try_node_name(id)
+    }
+
+    fn try_node_value(&self, id: usize) -> GraphResult<Option<KBRc<dyn KBValue>>> {
+        self.read()?.try_node_value(id)
+    }
+
+    fn try_lookup(&self, name: &str) -> GraphResult<Vec<usize>> {
+        self.read()?.try_lookup(name)
+    }
+
+    fn try_add_flag(&mut self, id: usize, flag: usize) -> GraphResult<()> {
+        self.write()?.try_add_flag(id, flag)
+    }
+
+    fn try_remove_flag(&mut self, id: usize, flag: usize) -> GraphResult<()> {
+        self.write()?.try_remove_flag(id, flag)
+    }
+
+    fn try_has_flag(&self, id: usize, flag: usize) -> GraphResult<bool> {
+        self.read()?.try_has_flag(id, flag)
+    }
+
+    fn try_nodes_with_flag(&self, flag: usize) -> GraphResult<Vec<usize>> {
+        self.read()?.try_nodes_with_flag(flag)
+    }
+
+    fn try_add_edge(&mut self, from: usize, edge_type: usize, to: usize) -> GraphResult<()> {
+        self.write()?.try_add_edge(from, edge_type, to)
+    }
+
+    fn try_remove_edge(&mut self, from: usize, edge_type: usize, to: usize) -> GraphResult<()> {
+        self.write()?.try_remove_edge(from, edge_type, to)
+    }
+
+    fn try_has_edge(&self, from: usize, edge_type: usize, to: usize) -> GraphResult<bool> {
+        self.read()?.try_has_edge(from, edge_type, to)
+    }
+
+    fn try_outgoing_nodes(&self, from: usize, edge_type: usize) -> GraphResult<Vec<usize>> {
+        self.read()?.try_outgoing_nodes(from, edge_type)
+    }
+
+    fn try_outgoing_nodes_in_order(
+        &self,
+        from: usize,
+        edge_type: usize,
+    ) -> GraphResult<Vec<usize>> {
+        self.read()?.try_outgoing_nodes_in_order(from, edge_type)
+    }
+
+    fn try_incoming_nodes(&self, to: usize, edge_type: usize) -> GraphResult<Vec<usize>> {
+        self.read()?.try_incoming_nodes(to, edge_type)
+    }
+
+    fn try_all_outgoing_nodes(&self, from: usize) -> GraphResult<Vec<usize>> {
+        self.read()?.try_all_outgoing_nodes(from)
+    }
+
+    fn try_all_incoming_nodes(&self, to: usize) -> GraphResult<Vec<usize>> {
+        self.read()?.try_all_incoming_nodes(to)
+    }
+
+    fn try_node_page(
+        &self,
+        query: &NodeQuery,
+        after: Option<usize>,
+        limit: usize,
+    ) -> GraphResult<Vec<usize>> {
+        self.read()?.try_node_page(query, after, limit)
+    }
+
+    fn try_into_dot(&self) -> GraphResult<String> {
+        self.read()?.try_into_dot()
+    }
+
+    fn try_begin_transaction(&mut self) -> GraphResult<()> {
+        self.write()?.try_begin_transaction()
+    }
+
+    fn try_commit(&mut self) -> GraphResult<()> {
+        self.write()?.try_commit()
+    }
+
+    fn try_rollback(&mut self) -> GraphResult<()> {
+        self.write()?.try_rollback()
+    }
+
+    #[cfg(feature = "snapshot")]
+    fn try_snapshot(&self) -> GraphResult<GraphSnapshot> {
+        self.read()?.try_snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::value_wrappers::{unwrap_value, StrongValue};
+    use crate::graph::{bind_shared_graph, InjectionGraph};
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_send_sync() {
+        assert_send_sync::<SharedGraph>();
+        assert_send_sync::<KBRc<dyn KBValue>>();
+    }
+
+    #[test]
+    fn test_clones_share_graph() {
+        let mut g = SharedGraph::new();
+        let g2 = g.clone();
+        let a_id = g.add_node();
+        g.set_node_name(a_id, "A");
+        assert_eq!(g2.size(), 1);
+        assert_eq!(g2.node_name(a_id), Some(Rc::from("A")));
+    }
+
+    #[test]
+    fn test_concurrent_writes() {
+        let mut g = SharedGraph::new();
+        let edge_type = g.add_node();
+        let hub = g.add_node();
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let mut g = g.clone();
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        let id = g.add_node();
+                        g.add_edge(hub, edge_type, id);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(g.size(), 42);
+        assert_eq!(g.outgoing_nodes(hub, edge_type).len(), 40);
+    }
+
+    #[test]
+    fn test_concurrent_reads() {
+        let mut g = SharedGraph::new();
+        let a_id = g.add_node();
+        g.set_node_name(a_id, "A");
+        g.set_node_value(a_id, KBRc::new(StrongValue::new(5)));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let g = g.clone();
+                thread::spawn(move || {
+                    bind_shared_graph(&g);
+                    let ig = InjectionGraph::new();
+                    (
+                        ig.lookup("A"),
+                        unwrap_value::<i32>(ig.node_value(a_id)).map(|v| *v),
+                    )
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), (vec![a_id], Some(5)));
+        }
+    }
+
+    #[test]
+    fn test_rollback_across_threads() {
+        let mut g = SharedGraph::new();
+        g.add_node();
+        g.begin_transaction();
+        let mut g2 = g.clone();
+        thread::spawn(move || {
+            g2.add_node();
+        })
+        .join()
+        .unwrap();
+        assert_eq!(g.size(), 2);
+        g.rollback();
+        assert_eq!(g.size(), 1);
+    }
+
+    #[test]
+    fn test_poisoned() {
+        let mut g = SharedGraph::new();
+        let g2 = g.clone();
+        let _ = thread::spawn(move || {
+            let _guard = g2.graph.write().unwrap();
+            panic!("Poisoning the graph");
+        })
+        .join();
+        assert!(matches!(g.try_add_node(), Err(GraphError::Backend(_))));
+    }
+}
@@ -0,0 +1,505 @@
+use super::value_wrappers::{downcast_value, KBRc, KBValue, StrongValue};
+use super::{GraphError, GraphResult};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+/// Current version of the snapshot format. Snapshots with a newer version will be rejected.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Magic bytes that every binary snapshot starts with.
+const BINARY_MAGIC: &[u8] = b"YINKB";
+
+/// Encodings that a snapshot can be written to disk in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    /// Human-readable JSON.
+    Json,
+    /// Compact binary encoding.
+    Binary,
+}
+
+impl SnapshotFormat {
+    /// Pick the format based on the file extension: JSON for `.json` files, and binary for
+    /// everything else.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => SnapshotFormat::Json,
+            _ => SnapshotFormat::Binary,
+        }
+    }
+}
+
+/// A node value that can be written out to a snapshot.
+///
+/// Only `StrongValue`s and live `WeakValue`s wrapping strings, booleans, integers, or floats can
+/// be saved. All other values, such as closures, are left out of the snapshot. Values always come
+/// back as `StrongValue`s, because there is nothing else left to own them after a reload.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SnapshotValue {
+    /// A `String` or `str` value.
+    Str(String),
+    /// A `bool` value.
+    Bool(bool),
+    /// An `i64` or `i32` value.
+    Int(i64),
+    /// A `u64` or `usize` value.
+    UInt(u64),
+    /// An `f64` value.
+    Float(f64),
+}
+
+impl SnapshotValue {
+    /// Convert a KB value into its snapshot form, if it is of a supported type.
+    pub fn from_kb_value(value: &dyn KBValue) -> Option<Self> {
+        downcast_value::<String>(value)
+            .map(|s| SnapshotValue::Str((*s).clone()))
+            .or_else(|| downcast_value::<str>(value).map(|s| SnapshotValue::Str(s.to_string())))
+            .or_else(|| downcast_value::<bool>(value).map(|b| SnapshotValue::Bool(*b)))
+            .or_else(|| downcast_value::<i64>(value).map(|i| SnapshotValue::Int(*i)))
+            .or_else(|| downcast_value::<i32>(value).map(|i| SnapshotValue::Int((*i).into())))
+            .or_else(|| downcast_value::<u64>(value).map(|u| SnapshotValue::UInt(*u)))
+            .or_else(|| downcast_value::<usize>(value).map(|u| SnapshotValue::UInt(*u as u64)))
+            .or_else(|| downcast_value::<f64>(value).map(|f| SnapshotValue::Float(*f)))
+    }
+
+    /// Convert this back into a value that can be stored in the KB.
+    pub fn to_kb_value(&self) -> KBRc<dyn KBValue> {
+        match self {
+            SnapshotValue::Str(s) => KBRc::new(StrongValue::new(s.clone())),
+            SnapshotValue::Bool(b) => KBRc::new(StrongValue::new(*b)),
+            SnapshotValue::Int(i) => KBRc::new(StrongValue::new(*i)),
+            SnapshotValue::UInt(u) => KBRc::new(StrongValue::new(*u)),
+            SnapshotValue::Float(f) => KBRc::new(StrongValue::new(*f)),
+        }
+    }
+}
+
+/// Everything the graph knows about a single node.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeSnapshot {
+    /// ID of the node. IDs are preserved across a save and reload.
+    pub id: usize,
+    /// Internal name of the node, if it has one.
+    pub name: Option<String>,
+    /// Value of the node, if it has one that can be saved.
+    pub value: Option<SnapshotValue>,
+    /// Flags set on the node, in ascending order.
+    pub flags: Vec<usize>,
+}
+
+/// A single labeled edge.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EdgeSnapshot {
+    /// ID of the node the edge starts from.
+    pub from: usize,
+    /// ID of the node representing the edge's type.
+    pub edge_type: usize,
+    /// ID of the node the edge points to.
+    pub to: usize,
+}
+
+/// Point-in-time copy of an entire graph, which can be saved to disk and loaded back later
+/// without having to replay every single graph operation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphSnapshot {
+    /// Version of the snapshot format.
+    pub version: u32,
+    /// All nodes in the graph, in ascending order of ID.
+    pub nodes: Vec<NodeSnapshot>,
+    /// All edges in the graph, in the order they were added.
+    pub edges: Vec<EdgeSnapshot>,
+}
+
+impl GraphSnapshot {
+    /// Create a new snapshot of the current version.
+    pub fn new(nodes: Vec<NodeSnapshot>, edges: Vec<EdgeSnapshot>) -> Self {
+        GraphSnapshot {
+            version: SNAPSHOT_VERSION,
+            nodes,
+            edges,
+        }
+    }
+
+    /// Make sure this snapshot can be read by the current version of Yin.
+    fn check_version(self) -> GraphResult<Self> {
+        if self.version > SNAPSHOT_VERSION {
+            Err(GraphError::Snapshot(format!(
+                "Version {} is newer than supported version {}",
+                self.version, SNAPSHOT_VERSION
+            )))
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// Serialize this snapshot to JSON.
+    pub fn to_json(&self) -> GraphResult<String> {
+        serde_json::to_string(self).map_err(|e| GraphError::Snapshot(e.to_string()))
+    }
+
+    /// Deserialize a snapshot from JSON.
+    pub fn from_json(json: &str) -> GraphResult<Self> {
+        serde_json::from_str::<GraphSnapshot>(json)
+            .map_err(|e| GraphError::Snapshot(e.to_string()))?
+            .check_version()
+    }
+
+    /// Serialize this snapshot to the compact binary encoding.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = BinaryWriter::default();
+        w.bytes.extend_from_slice(BINARY_MAGIC);
+        w.write_uint(self.version.into());
+        w.write_uint(self.nodes.len() as u64);
+        for node in &self.nodes {
+            w.write_uint(node.id as u64);
+            match &node.name {
+                Some(name) => {
+                    w.write_byte(1);
+                    w.write_str(name);
+                }
+                None => w.write_byte(0),
+            }
+            match &node.value {
+                None => w.write_byte(0),
+                Some(SnapshotValue::Str(s)) => {
+                    w.write_byte(1);
+                    w.write_str(s);
+                }
+                Some(SnapshotValue::Bool(b)) => {
+                    w.write_byte(2);
+                    w.write_byte(*b as u8);
+                }
+                Some(SnapshotValue::Int(i)) => {
+                    w.write_byte(3);
+                    // zigzag encoding keeps small negative numbers small
+                    w.write_uint(((*i << 1) ^ (*i >> 63)) as u64);
+                }
+                Some(SnapshotValue::UInt(u)) => {
+                    w.write_byte(4);
+                    w.write_uint(*u);
+                }
+                Some(SnapshotValue::Float(f)) => {
+                    w.write_byte(5);
+                    w.bytes.extend_from_slice(&f.to_le_bytes());
+                }
+            }
+            w.write_uint(node.flags.len() as u64);
+            for flag in &node.flags {
+                w.write_uint(*flag as u64);
+            }
+        }
+        w.write_uint(self.edges.len() as u64);
+        for edge in &self.edges {
+            w.write_uint(edge.from as u64);
+            w.write_uint(edge.edge_type as u64);
+            w.write_uint(edge.to as u64);
+        }
+        w.bytes
+    }
+
+    /// Deserialize a snapshot from the compact binary encoding.
+    pub fn from_bytes(bytes: &[u8]) -> GraphResult<Self> {
+        if !bytes.starts_with(BINARY_MAGIC) {
+            return Err(GraphError::Snapshot("Not a binary snapshot".to_owned()));
+        }
+        let mut r = BinaryReader {
+            bytes,
+            pos: BINARY_MAGIC.len(),
+        };
+        let version = u32::try_from(r.read_uint()?)
+            .map_err(|_| GraphError::Snapshot("Version out of range".to_owned()))?;
+        let node_count = r.read_usize()?;
+        let mut nodes = Vec::new();
+        for _ in 0..node_count {
+            let id = r.read_usize()?;
+            let name = match r.read_byte()? {
+                0 => None,
+                1 => Some(r.read_str()?),
+                tag => return Err(r.bad_tag(tag)),
+            };
+            let value = match r.read_byte()? {
+                0 => None,
+                1 => Some(SnapshotValue::Str(r.read_str()?)),
+                2 => Some(SnapshotValue::Bool(r.read_byte()? != 0)),
+                3 => {
+                    let zigzag = r.read_uint()?;
+                    Some(SnapshotValue::Int(
+                        ((zigzag >> 1) as i64) ^ -((zigzag & 1) as i64),
+                    ))
+                }
+                4 => Some(SnapshotValue::UInt(r.read_uint()?)),
+                5 => {
+                    let mut raw = [0u8; 8];
+                    raw.copy_from_slice(r.take(8)?);
+                    Some(SnapshotValue::Float(f64::from_le_bytes(raw)))
+                }
+                tag => return Err(r.bad_tag(tag)),
+            };
+            let flag_count = r.read_usize()?;
+            let mut flags = Vec::new();
+            for _ in 0..flag_count {
+                flags.push(r.read_usize()?);
+            }
+            nodes.push(NodeSnapshot {
+                id,
+                name,
+                value,
+                flags,
+            });
+        }
+        let edge_count = r.read_usize()?;
+        let mut edges = Vec::new();
+        for _ in 0..edge_count {
+            edges.push(EdgeSnapshot {
+                from: r.read_usize()?,
+                edge_type: r.read_usize()?,
+                to: r.read_usize()?,
+            });
+        }
+        if r.pos != bytes.len() {
+            return Err(GraphError::Snapshot("Trailing data at end".to_owned()));
+        }
+        GraphSnapshot {
+            version,
+            nodes,
+            edges,
+        }
+        .check_version()
+    }
+
+    /// Write this snapshot to a file in the given format.
+    pub fn save(&self, path: &Path, format: SnapshotFormat) -> GraphResult<()> {
+        let bytes = match format {
+            SnapshotFormat::Json => self.to_json()?.into_bytes(),
+            SnapshotFormat::Binary => self.to_bytes(),
+        };
+        fs::write(path, bytes).map_err(|e| GraphError::Snapshot(e.to_string()))
+    }
+
+    /// Read a snapshot from a file. The format is detected automatically.
+    pub fn load(path: &Path) -> GraphResult<Self> {
+        let bytes = fs::read(path).map_err(|e| GraphError::Snapshot(e.to_string()))?;
+        if bytes.starts_with(BINARY_MAGIC) {
+            Self::from_bytes(&bytes)
+        } else {
+            let json =
+                std::str::from_utf8(&bytes).map_err(|e| GraphError::Snapshot(e.to_string()))?;
+            Self::from_json(json)
+        }
+    }
+}
+
+/// Helper for writing out the binary encoding.
+#[derive(Default)]
+struct BinaryWriter {
+    bytes: Vec<u8>,
+}
+
+impl BinaryWriter {
+    fn write_byte(&mut self, b: u8) {
+        self.bytes.push(b);
+    }
+
+    /// Write an unsigned LEB128 varint.
+    fn write_uint(&mut self, mut u: u64) {
+        loop {
+            let low = (u & 0x7f) as u8;
+            u >>= 7;
+            if u == 0 {
+                self.bytes.push(low);
+                return;
+            }
+            self.bytes.push(low | 0x80);
+        }
+    }
+
+    fn write_str(&mut self, s: &str) {
+        self.write_uint(s.len() as u64);
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+}
+
+/// Helper for reading in the binary encoding.
+struct BinaryReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BinaryReader<'a> {
+    fn take(&mut self, n: usize) -> GraphResult<&'a [u8]> {
+        if self.bytes.len() - self.pos < n {
+            return Err(GraphError::Snapshot("Unexpected end of data".to_owned()));
+        }
+        let slice = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+
+    fn read_byte(&mut self) -> GraphResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Read an unsigned LEB128 varint.
+    fn read_uint(&mut self) -> GraphResult<u64> {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let b = self.read_byte()?;
+            if shift >= 64 {
+                return Err(GraphError::Snapshot("Integer too large".to_owned()));
+            }
+            result |= u64::from(b & 0x7f) << shift;
+            if b & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+
+    fn read_usize(&mut self) -> GraphResult<usize> {
+        usize::try_from(self.read_uint()?)
+            .map_err(|_| GraphError::Snapshot("Integer too large".to_owned()))
+    }
+
+    fn read_str(&mut self) -> GraphResult<String> {
+        let len = self.read_usize()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|e| GraphError::Snapshot(e.to_string()))
+    }
+
+    fn bad_tag(&self, tag: u8) -> GraphError {
+        GraphError::Snapshot(format!("Unknown tag {} at byte {}", tag, self.pos - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::value_wrappers::{unwrap_value, WeakValue};
+
+    fn sample() -> GraphSnapshot {
+        GraphSnapshot::new(
+            vec![
+                NodeSnapshot {
+                    id: 0,
+                    name: Some("A".to_owned()),
+                    value: Some(SnapshotValue::Str("résumé".to_owned())),
+                    flags: vec![2],
+                },
+                NodeSnapshot {
+                    id: 2,
+                    name: None,
+                    value: Some(SnapshotValue::Int(-300)),
+                    flags: vec![],
+                },
+                NodeSnapshot {
+                    id: 3,
+                    name: Some("edge".to_owned()),
+                    value: Some(SnapshotValue::Float(1.5)),
+                    flags: vec![0, 3],
+                },
+            ],
+            vec![
+                EdgeSnapshot {
+                    from: 0,
+                    edge_type: 3,
+                    to: 2,
+                },
+                EdgeSnapshot {
+                    from: 2,
+                    edge_type: 3,
+                    to: 0,
+                },
+            ],
+        )
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            SnapshotFormat::from_path(Path::new("kb.json")),
+            SnapshotFormat::Json
+        );
+        assert_eq!(
+            SnapshotFormat::from_path(Path::new("kb.yin")),
+            SnapshotFormat::Binary
+        );
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        let strong: KBRc<dyn KBValue> = KBRc::new(StrongValue::new("x".to_owned()));
+        let saved = SnapshotValue::from_kb_value(strong.as_ref());
+        assert_eq!(saved, Some(SnapshotValue::Str("x".to_owned())));
+        assert_eq!(
+            unwrap_value::<String>(Some(saved.unwrap().to_kb_value())),
+            Some(KBRc::new("x".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_value_weak() {
+        let v = KBRc::new(5);
+        let weak = WeakValue::new(&v);
+        assert_eq!(
+            SnapshotValue::from_kb_value(&weak),
+            Some(SnapshotValue::Int(5))
+        );
+        drop(v);
+        assert_eq!(SnapshotValue::from_kb_value(&weak), None);
+    }
+
+    #[test]
+    fn test_value_unsupported() {
+        let strong = StrongValue::new(vec![1, 2]);
+        assert_eq!(SnapshotValue::from_kb_value(&strong), None);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let snapshot = sample();
+        let json = snapshot.to_json().unwrap();
+        assert_eq!(GraphSnapshot::from_json(&json), Ok(snapshot));
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let snapshot = sample();
+        let bytes = snapshot.to_bytes();
+        assert!(bytes.len() < snapshot.to_json().unwrap().len());
+        assert_eq!(GraphSnapshot::from_bytes(&bytes), Ok(snapshot));
+    }
+
+    #[test]
+    fn test_binary_truncated() {
+        let bytes = sample().to_bytes();
+        assert_eq!(
+            GraphSnapshot::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(GraphError::Snapshot("Unexpected end of data".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_newer_version_rejected() {
+        let mut snapshot = sample();
+        snapshot.version = SNAPSHOT_VERSION + 1;
+        assert!(GraphSnapshot::from_bytes(&snapshot.to_bytes()).is_err());
+        assert!(GraphSnapshot::from_json(&snapshot.to_json().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let dir = std::env::temp_dir();
+        let snapshot = sample();
+        for (file, format) in &[
+            ("yin_snapshot_test.json", SnapshotFormat::Json),
+            ("yin_snapshot_test.yin", SnapshotFormat::Binary),
+        ] {
+            let path = dir.join(file);
+            snapshot.save(&path, *format).unwrap();
+            assert_eq!(GraphSnapshot::load(&path), Ok(snapshot.clone()));
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,875 @@
+use super::value_wrappers::{downcast_value, KBRc, KBValue, StrongValue};
+#[cfg(feature = "snapshot")]
+use super::{EdgeSnapshot, GraphSnapshot, NodeSnapshot, SnapshotValue};
+use super::{Graph, GraphError, GraphResult, NodeQuery};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
+
+/// Report a failure from SQLite as a backend error.
+fn backend_error<E: Display>(e: E) -> GraphError {
+    GraphError::Backend(e.to_string())
+}
+
+/// Tables that make up the graph. Edges are kept in their own table rather than as columns on the
+/// nodes, so that a node can have any number of edges of the same type.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS nodes (
+        id INTEGER PRIMARY KEY,
+        name TEXT,
+        value
+    );
+    CREATE INDEX IF NOT EXISTS nodes_by_name ON nodes (name);
+    CREATE TABLE IF NOT EXISTS flags (
+        node INTEGER NOT NULL,
+        flag INTEGER NOT NULL,
+        PRIMARY KEY (node, flag)
+    );
+    CREATE INDEX IF NOT EXISTS flags_by_flag ON flags (flag, node);
+    CREATE TABLE IF NOT EXISTS edges (
+        from_node INTEGER NOT NULL,
+        edge_type INTEGER NOT NULL,
+        to_node INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS edges_by_from ON edges (from_node, edge_type);
+    CREATE INDEX IF NOT EXISTS edges_by_to ON edges (to_node, edge_type);
+";
+
+/// Graph that is persisted to an embedded SQLite database file.
+///
+/// Current limitations:
+///
+///  * Only string and number values can be attached to nodes. Strings will be retrieved as
+///    `String`s, integers as `i64`s, and floats as `f64`s.
+///
+/// Transactions are implemented with SQLite savepoints, which can be nested.
+pub struct SqliteGraph {
+    db: Connection,
+    /// Number of transactions currently open.
+    transaction_depth: usize,
+}
+
+impl SqliteGraph {
+    /// Opens the SQLite database at the given path, creating it if it doesn't exist yet. Use
+    /// `:memory:` for a temporary database that will be discarded once the graph is dropped.
+    pub fn new(path: &str) -> Self {
+        let open = || -> rusqlite::Result<Connection> {
+            let db = Connection::open(path)?;
+            db.execute_batch(SCHEMA)?;
+            Ok(db)
+        };
+        match open() {
+            Ok(db) => SqliteGraph {
+                db,
+                transaction_depth: 0,
+            },
+            Err(e) => panic!("Couldn't open SQLite database at {}. Error: {}", path, e),
+        }
+    }
+
+    /// Return an error if the node with the given ID does not exist.
+    fn ensure_node(&self, id: usize) -> GraphResult<()> {
+        let exists: bool = self
+            .db
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM nodes WHERE id = ?1)",
+                params![id as i64],
+                |r| r.get(0),
+            )
+            .map_err(backend_error)?;
+        if exists {
+            Ok(())
+        } else {
+            Err(GraphError::UnknownNode(id))
+        }
+    }
+
+    /// Run a statement that modifies a single node, returning an error if the node does not
+    /// exist.
+    fn update_node(&self, id: usize, sql: &str, value: Value) -> GraphResult<()> {
+        let changed = self
+            .db
+            .execute(sql, params![value, id as i64])
+            .map_err(backend_error)?;
+        if changed == 0 {
+            Err(GraphError::UnknownNode(id))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Collect the IDs returned by a query that takes in the given parameters.
+    fn query_ids(&self, sql: &str, params: &[&dyn rusqlite::ToSql]) -> GraphResult<Vec<usize>> {
+        let mut stmt = self.db.prepare_cached(sql).map_err(backend_error)?;
+        let rows = stmt
+            .query_map(params, |r| r.get::<_, i64>(0))
+            .map_err(backend_error)?;
+        rows.map(|id| id.map(|i| i as usize).map_err(backend_error))
+            .collect()
+    }
+
+    /// Convert a KB value into something SQLite can store.
+    fn to_sql_value(value: &dyn KBValue) -> Option<Value> {
+        downcast_value::<String>(value)
+            .map(|s| Value::Text((*s).clone()))
+            .or_else(|| downcast_value::<str>(value).map(|s| Value::Text(s.to_string())))
+            .or_else(|| downcast_value::<i64>(value).map(|i| Value::Integer(*i)))
+            .or_else(|| downcast_value::<i32>(value).map(|i| Value::Integer((*i).into())))
+            .or_else(|| downcast_value::<usize>(value).map(|u| Value::Integer(*u as i64)))
+            .or_else(|| downcast_value::<f64>(value).map(|f| Value::Real(*f)))
+    }
+
+    /// Convert a value stored by SQLite back into a KB value.
+    fn from_sql_value(value: Value) -> Option<KBRc<dyn KBValue>> {
+        match value {
+            Value::Text(s) => Some(KBRc::new(StrongValue::new(s))),
+            Value::Integer(i) => Some(KBRc::new(StrongValue::new(i))),
+            Value::Real(f) => Some(KBRc::new(StrongValue::new(f))),
+            _ => None,
+        }
+    }
+}
+
+impl Graph for SqliteGraph {
+    fn try_size(&self) -> GraphResult<usize> {
+        self.db
+            .query_row("SELECT COUNT(*) FROM nodes", params![], |r| {
+                r.get::<_, i64>(0)
+            })
+            .map(|c| c as usize)
+            .map_err(backend_error)
+    }
+
+    fn try_add_node(&mut self) -> GraphResult<usize> {
+        // IDs start from zero to match the other graph implementations, which the type
+        // initialization logic relies on
+        self.db
+            .execute(
+                "INSERT INTO nodes (id) SELECT COALESCE(MAX(id) + 1, 0) FROM nodes",
+                params![],
+            )
+            .map_err(backend_error)?;
+        Ok(self.db.last_insert_rowid() as usize)
+    }
+
+    fn try_remove_node(&mut self, id: usize) -> GraphResult<()> {
+        self.ensure_node(id)?;
+        // a savepoint rather than a transaction, because there might already be one open
+        let tx = self.db.savepoint().map_err(backend_error)?;
+        tx.execute("DELETE FROM nodes WHERE id = ?1", params![id as i64])
+            .map_err(backend_error)?;
+        tx.execute(
+            "DELETE FROM flags WHERE node = ?1 OR flag = ?1",
+            params![id as i64],
+        )
+        .map_err(backend_error)?;
+        // edges typed by the removed node would otherwise dangle, and break loading snapshots
+        tx.execute(
+            "DELETE FROM edges WHERE from_node = ?1 OR to_node = ?1 OR edge_type = ?1",
+            params![id as i64],
+        )
+        .map_err(backend_error)?;
+        tx.commit().map_err(backend_error)
+    }
+
+    fn try_set_node_value(&mut self, id: usize, value: KBRc<dyn KBValue>) -> GraphResult<()> {
+        let sql_value = Self::to_sql_value(value.as_ref()).ok_or_else(|| {
+            backend_error("only string and number values can be stored in SQLite")
+        })?;
+        self.update_node(id, "UPDATE nodes SET value = ?1 WHERE id = ?2", sql_value)
+    }
+
+    fn try_set_node_name(&mut self, id: usize, name: &str) -> GraphResult<()> {
+        self.update_node(
+            id,
+            "UPDATE nodes SET name = ?1 WHERE id = ?2",
+            Value::Text(name.to_owned()),
+        )
+    }
+
+    fn try_node_name(&self, id: usize) -> GraphResult<Option<Rc<str>>> {
+        let name: Option<String> = self
+            .db
+            .query_row(
+                "SELECT name FROM nodes WHERE id = ?1",
+                params![id as i64],
+                |r| r.get(0),
+            )
+            .optional()
+            .map_err(backend_error)?
+            .ok_or(GraphError::UnknownNode(id))?;
+        Ok(name.map(|s| Rc::from(s.as_str())))
+    }
+
+    fn try_node_value(&self, id: usize) -> GraphResult<Option<KBRc<dyn KBValue>>> {
+        let value: Value = self
+            .db
+            .query_row(
+                "SELECT value FROM nodes WHERE id = ?1",
+                params![id as i64],
+                |r| r.get(0),
+            )
+            .optional()
+            .map_err(backend_error)?
+            .ok_or(GraphError::UnknownNode(id))?;
+        Ok(Self::from_sql_value(value))
+    }
+
+    fn try_lookup(&self, name: &str) -> GraphResult<Vec<usize>> {
+        self.query_ids(
+            "SELECT id FROM nodes WHERE name = ?1 ORDER BY id",
+            params![name],
+        )
+    }
+
+    fn try_add_flag(&mut self, id: usize, flag: usize) -> GraphResult<()> {
+        self.ensure_node(id)?;
+        self.db
+            .execute(
+                "INSERT OR IGNORE INTO flags (node, flag) VALUES (?1, ?2)",
+                params![id as i64, flag as i64],
+            )
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn try_remove_flag(&mut self, id: usize, flag: usize) -> GraphResult<()> {
+        self.ensure_node(id)?;
+        self.db
+            .execute(
+                "DELETE FROM flags WHERE node = ?1 AND flag = ?2",
+                params![id as i64, flag as i64],
+            )
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn try_has_flag(&self, id: usize, flag: usize) -> GraphResult<bool> {
+        self.ensure_node(id)?;
+        self.db
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM flags WHERE node = ?1 AND flag = ?2)",
+                params![id as i64, flag as i64],
+                |r| r.get(0),
+            )
+            .map_err(backend_error)
+    }
+
+    fn try_nodes_with_flag(&self, flag: usize) -> GraphResult<Vec<usize>> {
+        self.query_ids(
+            "SELECT node FROM flags WHERE flag = ?1 ORDER BY node",
+            params![flag as i64],
+        )
+    }
+
+    fn try_add_edge(&mut self, from: usize, edge_type: usize, to: usize) -> GraphResult<()> {
+        self.ensure_node(from)?;
+        self.ensure_node(to)?;
+        self.ensure_node(edge_type)?;
+        self.db
+            .execute(
+                "INSERT INTO edges (from_node, edge_type, to_node) VALUES (?1, ?2, ?3)",
+                params![from as i64, edge_type as i64, to as i64],
+            )
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn try_remove_edge(&mut self, from: usize, edge_type: usize, to: usize) -> GraphResult<()> {
+        self.ensure_node(from)?;
+        self.ensure_node(to)?;
+        self.db
+            .execute(
+                "DELETE FROM edges WHERE from_node = ?1 AND edge_type = ?2 AND to_node = ?3",
+                params![from as i64, edge_type as i64, to as i64],
+            )
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn try_has_edge(&self, from: usize, edge_type: usize, to: usize) -> GraphResult<bool> {
+        self.ensure_node(from)?;
+        self.ensure_node(to)?;
+        self.db
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM edges \
+                WHERE from_node = ?1 AND edge_type = ?2 AND to_node = ?3)",
+                params![from as i64, edge_type as i64, to as i64],
+                |r| r.get(0),
+            )
+            .map_err(backend_error)
+    }
+
+    fn try_outgoing_nodes(&self, from: usize, edge_type: usize) -> GraphResult<Vec<usize>> {
+        self.ensure_node(from)?;
+        self.query_ids(
+            "SELECT to_node FROM edges WHERE from_node = ?1 AND edge_type = ?2 ORDER BY to_node",
+            params![from as i64, edge_type as i64],
+        )
+    }
+
+    fn try_outgoing_nodes_in_order(
+        &self,
+        from: usize,
+        edge_type: usize,
+    ) -> GraphResult<Vec<usize>> {
+        self.ensure_node(from)?;
+        self.query_ids(
+            "SELECT to_node FROM edges WHERE from_node = ?1 AND edge_type = ?2 \
+            GROUP BY to_node ORDER BY MIN(rowid)",
+            params![from as i64, edge_type as i64],
+        )
+    }
+
+    fn try_incoming_nodes(&self, to: usize, edge_type: usize) -> GraphResult<Vec<usize>> {
+        self.ensure_node(to)?;
+        self.query_ids(
+            "SELECT from_node FROM edges WHERE to_node = ?1 AND edge_type = ?2 ORDER BY from_node",
+            params![to as i64, edge_type as i64],
+        )
+    }
+
+    fn try_all_outgoing_nodes(&self, from: usize) -> GraphResult<Vec<usize>> {
+        self.ensure_node(from)?;
+        self.query_ids(
+            "SELECT to_node FROM edges WHERE from_node = ?1 ORDER BY to_node",
+            params![from as i64],
+        )
+    }
+
+    fn try_all_incoming_nodes(&self, to: usize) -> GraphResult<Vec<usize>> {
+        self.ensure_node(to)?;
+        self.query_ids(
+            "SELECT from_node FROM edges WHERE to_node = ?1 ORDER BY from_node",
+            params![to as i64],
+        )
+    }
+
+    fn try_node_page(
+        &self,
+        query: &NodeQuery,
+        after: Option<usize>,
+        limit: usize,
+    ) -> GraphResult<Vec<usize>> {
+        // IDs start from zero, so -1 is before all of them
+        let after = after.map_or(-1, |id| id as i64);
+        let limit = limit as i64;
+        match query {
+            NodeQuery::Outgoing { from, edge_type } => {
+                self.ensure_node(*from)?;
+                self.query_ids(
+                    "SELECT DISTINCT to_node FROM edges \
+                    WHERE from_node = ?1 AND edge_type = ?2 AND to_node > ?3 \
+                    ORDER BY to_node LIMIT ?4",
+                    params![*from as i64, *edge_type as i64, after, limit],
+                )
+            }
+            NodeQuery::Incoming { to, edge_type } => {
+                self.ensure_node(*to)?;
+                self.query_ids(
+                    "SELECT DISTINCT from_node FROM edges \
+                    WHERE to_node = ?1 AND edge_type = ?2 AND from_node > ?3 \
+                    ORDER BY from_node LIMIT ?4",
+                    params![*to as i64, *edge_type as i64, after, limit],
+                )
+            }
+            NodeQuery::AllOutgoing { from } => {
+                self.ensure_node(*from)?;
+                self.query_ids(
+                    "SELECT DISTINCT to_node FROM edges WHERE from_node = ?1 AND to_node > ?2 \
+                    ORDER BY to_node LIMIT ?3",
+                    params![*from as i64, after, limit],
+                )
+            }
+            NodeQuery::AllIncoming { to } => {
+                self.ensure_node(*to)?;
+                self.query_ids(
+                    "SELECT DISTINCT from_node FROM edges WHERE to_node = ?1 AND from_node > ?2 \
+                    ORDER BY from_node LIMIT ?3",
+                    params![*to as i64, after, limit],
+                )
+            }
+            NodeQuery::Lookup { name } => self.query_ids(
+                "SELECT id FROM nodes WHERE name = ?1 AND id > ?2 ORDER BY id LIMIT ?3",
+                params![name, after, limit],
+            ),
+            NodeQuery::Flagged { flag } => self.query_ids(
+                "SELECT node FROM flags WHERE flag = ?1 AND node > ?2 ORDER BY node LIMIT ?3",
+                params![*flag as i64, after, limit],
+            ),
+        }
+    }
+
+    fn try_into_dot(&self) -> GraphResult<String> {
+        let mut node_names = HashMap::new();
+        let mut dot: String = "digraph {\n".to_owned();
+        let mut stmt = self
+            .db
+            .prepare("SELECT id, name FROM nodes ORDER BY id")
+            .map_err(backend_error)?;
+        let nodes = stmt
+            .query_map(params![], |r| {
+                Ok((r.get::<_, i64>(0)? as usize, r.get::<_, Option<String>>(1)?))
+            })
+            .map_err(backend_error)?;
+        for node in nodes {
+            let (id, name) = node.map_err(backend_error)?;
+            let name = name.unwrap_or_else(|| id.to_string());
+            dot.push_str(&format!("    {} [ label = \"{}\" ]\n", id, name));
+            node_names.insert(id, name);
+        }
+        let mut stmt = self
+            .db
+            .prepare("SELECT from_node, edge_type, to_node FROM edges ORDER BY from_node, rowid")
+            .map_err(backend_error)?;
+        let edges = stmt
+            .query_map(params![], |r| {
+                Ok((
+                    r.get::<_, i64>(0)? as usize,
+                    r.get::<_, i64>(1)? as usize,
+                    r.get::<_, i64>(2)? as usize,
+                ))
+            })
+            .map_err(backend_error)?;
+        for edge in edges {
+            let (from, edge_type, to) = edge.map_err(backend_error)?;
+            dot.push_str(&format!(
+                "    {} -> {} [ label = \"{}\" ]\n",
+                from,
+                to,
+                node_names.get(&edge_type).unwrap_or(&edge_type.to_string())
+            ));
+        }
+        dot.push('}');
+        Ok(dot)
+    }
+
+    fn try_begin_transaction(&mut self) -> GraphResult<()> {
+        self.db
+            .execute_batch(&format!("SAVEPOINT yin_{}", self.transaction_depth))
+            .map_err(backend_error)?;
+        self.transaction_depth += 1;
+        Ok(())
+    }
+
+    fn try_commit(&mut self) -> GraphResult<()> {
+        if self.transaction_depth == 0 {
+            return Err(GraphError::NoTransaction);
+        }
+        self.db
+            .execute_batch(&format!("RELEASE yin_{}", self.transaction_depth - 1))
+            .map_err(backend_error)?;
+        self.transaction_depth -= 1;
+        Ok(())
+    }
+
+    fn try_rollback(&mut self) -> GraphResult<()> {
+        if self.transaction_depth == 0 {
+            return Err(GraphError::NoTransaction);
+        }
+        let savepoint = format!("yin_{}", self.transaction_depth - 1);
+        self.db
+            .execute_batch(&format!("ROLLBACK TO {0}; RELEASE {0}", savepoint))
+            .map_err(backend_error)?;
+        self.transaction_depth -= 1;
+        Ok(())
+    }
+
+    #[cfg(feature = "snapshot")]
+    fn try_snapshot(&self) -> GraphResult<GraphSnapshot> {
+        let mut flags = HashMap::<usize, Vec<usize>>::new();
+        let mut stmt = self
+            .db
+            .prepare("SELECT node, flag FROM flags ORDER BY node, flag")
+            .map_err(backend_error)?;
+        let rows = stmt
+            .query_map(params![], |r| {
+                Ok((r.get::<_, i64>(0)? as usize, r.get::<_, i64>(1)? as usize))
+            })
+            .map_err(backend_error)?;
+        for row in rows {
+            let (node, flag) = row.map_err(backend_error)?;
+            flags.entry(node).or_default().push(flag);
+        }
+
+        let mut nodes = Vec::new();
+        let mut stmt = self
+            .db
+            .prepare("SELECT id, name, value FROM nodes ORDER BY id")
+            .map_err(backend_error)?;
+        let rows = stmt
+            .query_map(params![], |r| {
+                Ok((
+                    r.get::<_, i64>(0)? as usize,
+                    r.get::<_, Option<String>>(1)?,
+                    r.get::<_, Value>(2)?,
+                ))
+            })
+            .map_err(backend_error)?;
+        for row in rows {
+            let (id, name, value) = row.map_err(backend_error)?;
+            nodes.push(NodeSnapshot {
+                id,
+                name,
+                value: Self::from_sql_value(value)
+                    .and_then(|v| SnapshotValue::from_kb_value(v.as_ref())),
+                flags: flags.remove(&id).unwrap_or_default(),
+            });
+        }
+
+        let mut edges = Vec::new();
+        let mut stmt = self
+            .db
+            .prepare("SELECT from_node, edge_type, to_node FROM edges ORDER BY rowid")
+            .map_err(backend_error)?;
+        let rows = stmt
+            .query_map(params![], |r| {
+                Ok(EdgeSnapshot {
+                    from: r.get::<_, i64>(0)? as usize,
+                    edge_type: r.get::<_, i64>(1)? as usize,
+                    to: r.get::<_, i64>(2)? as usize,
+                })
+            })
+            .map_err(backend_error)?;
+        for row in rows {
+            edges.push(row.map_err(backend_error)?);
+        }
+        Ok(GraphSnapshot::new(nodes, edges))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+    use crate::graph::value_wrappers::{unwrap_value, WeakValue};
+
+    /// Each test gets its own throwaway database, so that tests can run in parallel.
+    const TEST_DB_PATH: &str = ":memory:";
+
+    #[test]
+    fn test_create() {
+        bind_sqlite_graph(TEST_DB_PATH);
+    }
+
+    #[test]
+    fn test_add_node() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        assert_eq!(g.add_node(), 0);
+        assert_eq!(g.add_node(), 1);
+        assert!(g.node_value(0).is_none());
+        assert_eq!(g.node_name(0), None);
+    }
+
+    #[test]
+    fn test_size() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        assert_eq!(g.size(), 0);
+        g.add_node();
+        assert_eq!(g.size(), 1);
+    }
+
+    #[test]
+    fn test_remove_node() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let edge_type = g.add_node();
+        g.set_node_name(a_id, "A");
+        g.add_flag(a_id, edge_type);
+        g.add_edge(a_id, edge_type, b_id);
+        g.remove_node(a_id);
+        assert_eq!(g.size(), 2);
+        assert_eq!(g.lookup("A"), Vec::<usize>::new());
+        assert_eq!(g.all_incoming_nodes(b_id), Vec::<usize>::new());
+        assert_eq!(g.try_node_name(a_id), Err(GraphError::UnknownNode(a_id)));
+    }
+
+    #[test]
+    fn test_remove_edge_type_node() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let edge_type = g.add_node();
+        g.add_flag(a_id, edge_type);
+        g.add_edge(a_id, edge_type, b_id);
+        g.remove_node(edge_type);
+        assert!(!g.has_flag(a_id, edge_type));
+        assert_eq!(g.all_outgoing_nodes(a_id), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_outgoing_nodes_in_order() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let c_id = g.add_node();
+        let edge_type = g.add_node();
+        g.add_edge(a_id, edge_type, c_id);
+        g.add_edge(a_id, edge_type, b_id);
+        g.add_edge(a_id, edge_type, c_id);
+        assert_eq!(g.outgoing_nodes(a_id, edge_type), vec![b_id, c_id, c_id]);
+        assert_eq!(g.outgoing_nodes_in_order(a_id, edge_type), vec![c_id, b_id]);
+    }
+
+    #[test]
+    fn test_set_node_value() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let v = KBRc::new("5".to_string());
+        g.set_node_value(a_id, KBRc::new(WeakValue::new(&v)));
+        assert_eq!(unwrap_value(g.node_value(a_id)), Some(v));
+        assert_eq!(g.node_name(a_id), None);
+    }
+
+    #[test]
+    fn test_set_node_number_values() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        g.set_node_value(a_id, KBRc::new(StrongValue::new(-5i64)));
+        g.set_node_value(b_id, KBRc::new(StrongValue::new(2.5f64)));
+        assert_eq!(unwrap_value::<i64>(g.node_value(a_id)), Some(KBRc::new(-5)));
+        assert_eq!(
+            unwrap_value::<f64>(g.node_value(b_id)),
+            Some(KBRc::new(2.5))
+        );
+    }
+
+    #[test]
+    fn test_set_unsupported_value() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        assert!(g
+            .try_set_node_value(a_id, KBRc::new(StrongValue::new(vec![1])))
+            .is_err());
+    }
+
+    #[test]
+    fn test_retrieve_node_name() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        g.set_node_name(a_id, "A");
+        assert_eq!(g.node_name(a_id), Some(Rc::from("A")));
+    }
+
+    #[test]
+    fn test_lookup_by_name() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        g.set_node_name(a_id, "A");
+        g.add_node();
+        let c_id = g.add_node();
+        g.set_node_name(c_id, "A");
+        assert_eq!(g.lookup("A"), vec![a_id, c_id]);
+        assert_eq!(g.lookup("B"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_nodes_with_flag() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let flag = g.add_node();
+        g.add_flag(b_id, flag);
+        g.add_flag(a_id, flag);
+        g.add_flag(a_id, flag);
+        assert_eq!(g.nodes_with_flag(flag), vec![a_id, b_id]);
+        g.remove_node(b_id);
+        assert_eq!(g.nodes_with_flag(flag), vec![a_id]);
+        assert_eq!(
+            g.node_page(&NodeQuery::Flagged { flag }, Some(a_id), 5),
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn test_node_page() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let edge_type = g.add_node();
+        let b_id = g.add_node();
+        let c_id = g.add_node();
+        let d_id = g.add_node();
+        g.add_edge(a_id, edge_type, b_id);
+        g.add_edge(a_id, edge_type, b_id);
+        g.add_edge(a_id, edge_type, c_id);
+        g.add_edge(a_id, edge_type, d_id);
+        let query = NodeQuery::Outgoing {
+            from: a_id,
+            edge_type,
+        };
+        assert_eq!(g.node_page(&query, None, 2), vec![b_id, c_id]);
+        assert_eq!(g.node_page(&query, Some(c_id), 2), vec![d_id]);
+        assert_eq!(
+            g.iter_all_incoming_nodes(b_id).collect::<Vec<_>>(),
+            vec![a_id]
+        );
+    }
+
+    #[test]
+    fn test_flags() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let flag = g.add_node();
+        assert!(!g.has_flag(a_id, flag));
+        g.add_flag(a_id, flag);
+        g.add_flag(a_id, flag);
+        assert!(g.has_flag(a_id, flag));
+        g.remove_flag(a_id, flag);
+        assert!(!g.has_flag(a_id, flag));
+    }
+
+    #[test]
+    fn test_edges() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let c_id = g.add_node();
+        let edge_type1 = g.add_node();
+        let edge_type2 = g.add_node();
+        g.add_edge(c_id, edge_type1, a_id);
+        g.add_edge(b_id, edge_type1, a_id);
+        g.add_edge(b_id, edge_type2, c_id);
+        assert!(g.has_edge(b_id, edge_type1, a_id));
+        assert!(!g.has_edge(a_id, edge_type1, b_id));
+        assert_eq!(g.incoming_nodes(a_id, edge_type1), vec![b_id, c_id]);
+        assert_eq!(g.incoming_nodes(a_id, edge_type2), Vec::<usize>::new());
+        assert_eq!(g.outgoing_nodes(b_id, edge_type2), vec![c_id]);
+        assert_eq!(g.all_outgoing_nodes(b_id), vec![a_id, c_id]);
+        assert_eq!(g.all_incoming_nodes(c_id), vec![b_id]);
+    }
+
+    #[test]
+    fn test_remove_duplicate_edges() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let edge_type = g.add_node();
+        g.add_edge(a_id, edge_type, b_id);
+        g.add_edge(a_id, edge_type, b_id);
+        g.remove_edge(a_id, edge_type, b_id);
+        assert!(!g.has_edge(a_id, edge_type, b_id));
+    }
+
+    #[test]
+    fn test_edge_to_unknown_node() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        assert_eq!(
+            g.try_add_edge(a_id, a_id, 5),
+            Err(GraphError::UnknownNode(5))
+        );
+    }
+
+    #[test]
+    fn test_persistence() {
+        let path = std::env::temp_dir().join("yin_sqlite_graph_test.db");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        let (a_id, edge_type) = {
+            let mut g = SqliteGraph::new(path);
+            let a_id = g.add_node();
+            let edge_type = g.add_node();
+            g.set_node_name(a_id, "A");
+            g.add_edge(a_id, edge_type, a_id);
+            (a_id, edge_type)
+        };
+        let g = SqliteGraph::new(path);
+        assert_eq!(g.node_name(a_id), Some(Rc::from("A")));
+        assert_eq!(g.outgoing_nodes(a_id, edge_type), vec![a_id]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_into_dot() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        let edge_type = g.add_node();
+        g.set_node_name(a_id, "A");
+        g.set_node_name(edge_type, "E");
+        g.add_edge(a_id, edge_type, b_id);
+        assert_eq!(
+            g.into_dot(),
+            "digraph {\n    0 [ label = \"A\" ]\n    1 [ label = \"1\" ]\n    \
+            2 [ label = \"E\" ]\n    0 -> 1 [ label = \"E\" ]\n}"
+        );
+    }
+
+    #[test]
+    fn test_rollback() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let edge_type = g.add_node();
+        g.begin_transaction();
+        let b_id = g.add_node();
+        g.set_node_name(a_id, "A");
+        g.add_edge(a_id, edge_type, b_id);
+        g.remove_node(edge_type);
+        g.rollback();
+        assert_eq!(g.size(), 2);
+        assert_eq!(g.node_name(a_id), None);
+        assert_eq!(g.all_outgoing_nodes(a_id), Vec::<usize>::new());
+        assert_eq!(g.try_node_name(edge_type), Ok(None));
+        assert_eq!(g.add_node(), b_id);
+    }
+
+    #[test]
+    fn test_nested_transactions() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        g.begin_transaction();
+        let a_id = g.add_node();
+        g.begin_transaction();
+        g.set_node_name(a_id, "A");
+        g.rollback();
+        g.begin_transaction();
+        g.add_flag(a_id, a_id);
+        g.commit();
+        g.commit();
+        assert_eq!(g.node_name(a_id), None);
+        assert!(g.has_flag(a_id, a_id));
+        assert_eq!(g.try_commit(), Err(GraphError::NoTransaction));
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn test_snapshot_to_in_memory() {
+        bind_sqlite_graph(TEST_DB_PATH);
+        let mut g = InjectionGraph::new();
+        let a_id = g.add_node();
+        let b_id = g.add_node();
+        g.set_node_name(a_id, "A");
+        g.set_node_value(b_id, KBRc::new(StrongValue::new(3i64)));
+        g.add_flag(b_id, a_id);
+        g.add_edge(a_id, b_id, b_id);
+        let snapshot = g.snapshot();
+
+        bind_in_memory_snapshot(&snapshot).unwrap();
+        assert_eq!(g.node_name(a_id), Some(Rc::from("A")));
+        assert_eq!(unwrap_value::<i64>(g.node_value(b_id)), Some(KBRc::new(3)));
+        assert!(g.has_flag(b_id, a_id));
+        assert_eq!(g.outgoing_nodes(a_id, b_id), vec![b_id]);
+    }
+}
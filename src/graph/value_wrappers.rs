@@ -1,17 +1,68 @@
 use crate::tao::form::Form;
 use std::any::Any;
-use std::cell::{RefCell, RefMut};
-use std::rc::{Rc, Weak};
+#[cfg(not(feature = "thread-safe"))]
+use std::cell::RefCell;
+#[cfg(feature = "thread-safe")]
+use std::sync::Mutex;
+
+/// Reference-counted pointer for values in the KB. This is an `Rc` normally, and an `Arc` when
+/// the `thread-safe` feature is turned on.
+#[cfg(not(feature = "thread-safe"))]
+pub type KBRc<T> = std::rc::Rc<T>;
+
+/// Reference-counted pointer for values in the KB. This is an `Rc` normally, and an `Arc` when
+/// the `thread-safe` feature is turned on.
+#[cfg(feature = "thread-safe")]
+pub type KBRc<T> = std::sync::Arc<T>;
+
+/// Weak version of `KBRc`.
+#[cfg(not(feature = "thread-safe"))]
+pub type KBWeak<T> = std::rc::Weak<T>;
+
+/// Weak version of `KBRc`.
+#[cfg(feature = "thread-safe")]
+pub type KBWeak<T> = std::sync::Weak<T>;
+
+/// Marker for data that can be stored in the KB. When the `thread-safe` feature is turned on, this
+/// requires the data to be `Send + Sync`, so that the KB can be shared between threads.
+/// Otherwise, anything goes.
+#[cfg(not(feature = "thread-safe"))]
+pub trait KBShareable {}
+
+#[cfg(not(feature = "thread-safe"))]
+impl<T: ?Sized> KBShareable for T {}
+
+/// Marker for data that can be stored in the KB. When the `thread-safe` feature is turned on, this
+/// requires the data to be `Send + Sync`, so that the KB can be shared between threads.
+/// Otherwise, anything goes.
+#[cfg(feature = "thread-safe")]
+pub trait KBShareable: Send + Sync {}
+
+#[cfg(feature = "thread-safe")]
+impl<T: ?Sized + Send + Sync> KBShareable for T {}
 
 /// Closure stored inside the KB.
+#[cfg(not(feature = "thread-safe"))]
 pub type KBClosure = Box<dyn FnMut(Form) -> Box<dyn Any>>;
 
+/// Closure stored inside the KB.
+#[cfg(feature = "thread-safe")]
+pub type KBClosure = Box<dyn FnMut(Form) -> Box<dyn Any> + Send>;
+
+/// Cell that allows a closure stored inside the KB to be called mutably.
+#[cfg(not(feature = "thread-safe"))]
+pub type KBClosureCell = RefCell<KBClosure>;
+
+/// Cell that allows a closure stored inside the KB to be called mutably.
+#[cfg(feature = "thread-safe")]
+pub type KBClosureCell = Mutex<KBClosure>;
+
 /// Wrapper for KB values, because Rust doesn't support upcasting at the moment, and the KB should
 /// support referring to external data structures that it doesn't own itself.
 ///
 /// This is an implementation of
 /// [https://stackoverflow.com/a/42057047/257583](https://stackoverflow.com/a/42057047/257583).
-pub trait KBValue: Any {
+pub trait KBValue: Any + KBShareable {
     /// Because Rust doesn't support upcasting at the moment, this allows us to manually upcast to
     /// `Any` and then downcast to the desired struct thereafter.
     fn as_any(&self) -> &dyn Any;
@@ -31,7 +82,9 @@ pub trait KBValue: Any {
 ///     guaranteed to return a value even if there was originally one associated with the node.
 ///
 /// This function encapsulates all of the above into one simpler return value.
-pub fn unwrap_value<'a, T: ?Sized + 'a>(wrapper: Option<Rc<dyn KBValue + 'a>>) -> Option<Rc<T>> {
+pub fn unwrap_value<'a, T: ?Sized + 'a>(
+    wrapper: Option<KBRc<dyn KBValue + 'a>>,
+) -> Option<KBRc<T>> {
     wrapper
         .map(|v| {
             let any_value = v.as_any();
@@ -43,31 +96,58 @@ pub fn unwrap_value<'a, T: ?Sized + 'a>(wrapper: Option<Rc<dyn KBValue + 'a>>) -
         .flatten()
 }
 
+/// Like `unwrap_value`, except that this returns `None` instead of panicking if the wrapped data
+/// turns out to be of some other type.
+pub fn downcast_value<T: ?Sized + 'static>(value: &dyn KBValue) -> Option<KBRc<T>> {
+    let any_value = value.as_any();
+    match any_value.downcast_ref::<WeakValue<T>>() {
+        Some(weak_value) => weak_value.value(),
+        None => any_value
+            .downcast_ref::<StrongValue<T>>()
+            .map(|strong_value| strong_value.value()),
+    }
+}
+
 /// Unwrap a StrongValue holding a closure, and return the result after running on the input.
 pub fn run_closure<'a, 'b, T: 'static>(
-    wrapper: &'b Option<Rc<dyn KBValue + 'a>>,
+    wrapper: &'b Option<KBRc<dyn KBValue + 'a>>,
     input: Form,
 ) -> Option<Box<T>> {
     wrapper.as_ref().map(|v| {
         let any: &'b dyn Any = v.as_any();
-        let value_wrappers: &'b StrongValue<RefCell<KBClosure>> = any
-            .downcast_ref::<StrongValue<RefCell<KBClosure>>>()
-            .unwrap();
-        let closure_ref: Rc<RefCell<KBClosure>> = value_wrappers.value();
-        let mut closure: RefMut<'_, KBClosure> = closure_ref.borrow_mut();
-        let result: Box<dyn Any> = closure(input);
+        let value_wrappers: &'b StrongValue<KBClosureCell> =
+            any.downcast_ref::<StrongValue<KBClosureCell>>().unwrap();
+        let closure_ref: KBRc<KBClosureCell> = value_wrappers.value();
+        let result: Box<dyn Any> = call_closure(&closure_ref, input);
         let cast_result: Box<T> = result.downcast().expect("Downcast type failure");
         cast_result
     })
 }
 
+/// Call a closure stored inside the KB.
+#[cfg(not(feature = "thread-safe"))]
+fn call_closure(cell: &KBClosureCell, input: Form) -> Box<dyn Any> {
+    let mut closure = cell.borrow_mut();
+    closure(input)
+}
+
+/// Call a closure stored inside the KB.
+#[cfg(feature = "thread-safe")]
+fn call_closure(cell: &KBClosureCell, input: Form) -> Box<dyn Any> {
+    let mut closure = cell
+        .lock()
+        .expect("KB closure poisoned by an earlier panic");
+    closure(input)
+}
+
 /// Unwrap a StrongValue holding a closure, and return the result after running on the input.
 #[macro_export]
 macro_rules! define_closure {
     ($closure:expr) => {{
+        use $crate::graph::value_wrappers::{KBClosureCell, KBRc, StrongValue};
         // explicitly declare the type to help the Rust compiler understand
-        let strong: Rc<StrongValue<RefCell<KBClosure>>> =
-            Rc::new(StrongValue::new(RefCell::new(Box::new($closure))));
+        let strong: KBRc<StrongValue<KBClosureCell>> =
+            KBRc::new(StrongValue::new(KBClosureCell::new(Box::new($closure))));
         strong
     }};
 }
@@ -75,25 +155,25 @@ macro_rules! define_closure {
 /// KBValue for weak references to data.
 #[derive(Debug)]
 pub struct WeakValue<T: ?Sized> {
-    item: Weak<T>,
+    item: KBWeak<T>,
 }
 
 impl<T: ?Sized> WeakValue<T> {
     /// Create a new KB wrapper that contains a weak reference to the given data.
-    pub fn new(rc: &Rc<T>) -> Self {
+    pub fn new(rc: &KBRc<T>) -> Self {
         WeakValue {
-            item: Rc::downgrade(rc),
+            item: KBRc::downgrade(rc),
         }
     }
 
     /// Retrieve the value that this wrapper points to -- if it still exists, because the KB does
     /// not own the data.
-    pub fn value(&self) -> Option<Rc<T>> {
+    pub fn value(&self) -> Option<KBRc<T>> {
         self.item.upgrade()
     }
 }
 
-impl<'a, T: ?Sized + 'static> KBValue for WeakValue<T> {
+impl<'a, T: ?Sized + KBShareable + 'static> KBValue for WeakValue<T> {
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -102,29 +182,29 @@ impl<'a, T: ?Sized + 'static> KBValue for WeakValue<T> {
 /// KBValue for owned immutable data.
 #[derive(Debug)]
 pub struct StrongValue<T: ?Sized> {
-    item: Rc<T>,
+    item: KBRc<T>,
 }
 
 impl<T: Any> StrongValue<T> {
     /// Create a new KB wrapper that owns the given data.
     pub fn new(t: T) -> Self {
-        StrongValue { item: Rc::new(t) }
+        StrongValue { item: KBRc::new(t) }
     }
 }
 
 impl<T: ?Sized> StrongValue<T> {
     /// Create a new KB wrapper that owns the given data.
-    pub fn new_rc(rc: Rc<T>) -> Self {
+    pub fn new_rc(rc: KBRc<T>) -> Self {
         StrongValue { item: rc }
     }
 
     /// Get value that this wrapper owns. Guaranteed to still exist because the KB owns this data.
-    pub fn value(&self) -> Rc<T> {
+    pub fn value(&self) -> KBRc<T> {
         self.item.clone()
     }
 }
 
-impl<'a, T: ?Sized + 'static> KBValue for StrongValue<T> {
+impl<'a, T: ?Sized + KBShareable + 'static> KBValue for StrongValue<T> {
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -138,12 +218,13 @@ mod tests {
     use crate::tao::form::FormTrait;
     use crate::tao::initialize_kb;
     use crate::tao::relation::attribute::Inherits;
+    use std::rc::Rc;
 
     #[test]
     fn test_weak_value() {
-        let item = Rc::new("something expensive".to_string());
+        let item = KBRc::new("something expensive".to_string());
         let weak = WeakValue::new(&item);
-        assert_eq!(unwrap_value(Some(Rc::new(weak))), Some(item));
+        assert_eq!(unwrap_value(Some(KBRc::new(weak))), Some(item));
     }
 
     #[test]
@@ -151,8 +232,8 @@ mod tests {
         let item = "something owned".to_string();
         let strong = StrongValue::new(item);
         assert_eq!(
-            unwrap_value(Some(Rc::new(strong))),
-            Some(Rc::new("something owned".to_string()))
+            unwrap_value(Some(KBRc::new(strong))),
+            Some(KBRc::new("something owned".to_string()))
         );
     }
 
@@ -161,16 +242,30 @@ mod tests {
         let item: i64 = -5;
         let strong = StrongValue::new(item);
         assert_eq!(
-            unwrap_value::<i64>(Some(Rc::new(strong))),
-            Some(Rc::new(-5))
+            unwrap_value::<i64>(Some(KBRc::new(strong))),
+            Some(KBRc::new(-5))
+        );
+    }
+
+    #[test]
+    fn test_downcast_value() {
+        let item = KBRc::new(5);
+        let weak = WeakValue::new(&item);
+        let strong = StrongValue::new("owned".to_string());
+        assert_eq!(downcast_value::<i32>(&weak), Some(item));
+        assert_eq!(downcast_value::<i64>(&weak), None);
+        assert_eq!(
+            downcast_value::<String>(&strong),
+            Some(KBRc::new("owned".to_string()))
         );
+        assert_eq!(downcast_value::<str>(&strong), None);
     }
 
     #[test]
     fn test_function_value() {
         initialize_kb();
         let i = Inherits::archetype();
-        let kb_result: Option<Rc<dyn KBValue>> = Some(define_closure!(|t: Form| {
+        let kb_result: Option<KBRc<dyn KBValue>> = Some(define_closure!(|t: Form| {
             Box::new(t.internal_name().unwrap())
         }));
         assert_eq!(
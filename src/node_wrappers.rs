@@ -1,12 +1,14 @@
 //! Wrappers around graph nodes that provide extended functionality at every level.
 
+pub(crate) mod ancestor_cache;
 mod base_node;
 mod final_node;
 mod inheritance_node;
 
+use crate::graph::{GraphResult, KnowledgeBase};
 pub use base_node::{BaseNode, BaseNodeTrait};
 pub use final_node::FinalNode;
-pub use inheritance_node::{InheritanceNode, InheritanceNodeTrait};
+pub use inheritance_node::{Explanation, InheritanceNode, InheritanceNodeTrait};
 use std::fmt::{Formatter, Result};
 use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
@@ -16,11 +18,18 @@ pub trait CommonNodeTrait {
     /// The unique integer that's associated with this concept.
     fn id(&self) -> usize;
 
+    /// The KB that this concept lives in.
+    fn kb(&self) -> KnowledgeBase;
+
     /// Associate this concept with an internal name. The name does not need to be unique.
     fn set_internal_name(&mut self, name: &str);
 
     /// The internal name that's associated with this concept, if one exists.
     fn internal_name(&self) -> Option<Rc<str>>;
+
+    /// The internal name that's associated with this concept, or an error if the concept no
+    /// longer exists in the KB.
+    fn try_internal_name(&self) -> GraphResult<Option<Rc<str>>>;
 }
 
 impl<T> CommonNodeTrait for T
@@ -32,6 +41,10 @@ where
         (**self).id()
     }
 
+    fn kb(&self) -> KnowledgeBase {
+        (**self).kb()
+    }
+
     fn set_internal_name(&mut self, name: &str) {
         (**self).set_internal_name(name);
     }
@@ -39,6 +52,10 @@ where
     fn internal_name(&self) -> Option<Rc<str>> {
         (**self).internal_name()
     }
+
+    fn try_internal_name(&self) -> GraphResult<Option<Rc<str>>> {
+        (**self).try_internal_name()
+    }
 }
 
 /// Helper function for implementing the Debug trait for a node wrapper.
@@ -0,0 +1,182 @@
+use crate::graph::{Graph, GraphEvent, GraphResult, KnowledgeBase};
+use crate::tao::archetype::ArchetypeTrait;
+use crate::tao::relation::attribute::Inherits;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// IDs of every node's ancestors, including the node itself, sorted in ascending order.
+type AncestorSets = HashMap<usize, Rc<[usize]>>;
+
+thread_local! {
+    /// Ancestors that have been looked up so far. Kept separately for each KB, so that changing
+    /// one KB leaves the others' entries alone.
+    static ANCESTORS: RefCell<HashMap<KnowledgeBase, AncestorSets>> = RefCell::new(HashMap::new());
+}
+
+/// The ancestors of the node with this ID, which get computed with `search` on a cache miss.
+///
+/// Only private graphs get cached. Other threads or processes can change the inheritance in graphs
+/// that are shared with them, or that are stored in a database, without this thread finding out.
+pub(crate) fn ancestors<F>(kb: KnowledgeBase, id: usize, search: F) -> GraphResult<Rc<[usize]>>
+where
+    F: FnOnce() -> GraphResult<Vec<usize>>,
+{
+    let cached = ANCESTORS.with(|cache| {
+        cache
+            .borrow()
+            .get(&kb)
+            .and_then(|kb_cache| kb_cache.get(&id).cloned())
+    });
+    if let Some(ancestors) = cached {
+        return Ok(ancestors);
+    }
+
+    let ancestors: Rc<[usize]> = search()?.into();
+    if kb.graph().is_private() {
+        ANCESTORS.with(|cache| {
+            cache
+                .borrow_mut()
+                .entry(kb)
+                .or_default()
+                .insert(id, ancestors.clone())
+        });
+    }
+    Ok(ancestors)
+}
+
+/// Drop cached ancestors that may no longer be accurate after this change to the KB's graph.
+pub(crate) fn invalidate(kb: KnowledgeBase, event: &GraphEvent) {
+    let stale = match event {
+        GraphEvent::EdgeAdded { edge_type, .. } | GraphEvent::EdgeRemoved { edge_type, .. } => {
+            *edge_type == Inherits::TYPE_ID
+        }
+        // removed nodes take their edges with them, and their IDs may get reused
+        GraphEvent::NodeRemoved { .. } | GraphEvent::RolledBack => true,
+        _ => false,
+    };
+    if stale {
+        forget(kb);
+    }
+}
+
+/// Drop all cached ancestors for this KB, for when its graph gets replaced or closed.
+pub(crate) fn forget(kb: KnowledgeBase) {
+    ANCESTORS.with(|cache| cache.borrow_mut().remove(&kb));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node_wrappers::{BaseNodeTrait, CommonNodeTrait, InheritanceNodeTrait};
+    use crate::tao::archetype::ArchetypeFormTrait;
+    use crate::tao::form::{Form, FormTrait};
+    use crate::tao::{initialize_kb, new_in_memory_kb};
+
+    fn is_cached(kb: KnowledgeBase, id: usize) -> bool {
+        ANCESTORS.with(|cache| {
+            cache
+                .borrow()
+                .get(&kb)
+                .map(|kb_cache| kb_cache.contains_key(&id))
+                .unwrap_or(false)
+        })
+    }
+
+    #[test]
+    fn test_cached_after_lookup() {
+        initialize_kb();
+        let form = Form::archetype().individuate_as_archetype();
+        let ancestors = form.inheritance_nodes();
+        assert!(is_cached(KnowledgeBase::GLOBAL, form.id()));
+        assert_eq!(form.inheritance_nodes(), ancestors);
+    }
+
+    #[test]
+    fn test_invalidated_by_new_parent() {
+        initialize_kb();
+        let parent = Form::archetype().individuate_as_archetype();
+        let mut child = Form::archetype().individuate_as_archetype();
+        assert!(!child.has_ancestor(parent));
+        child.add_parent(parent);
+        assert!(!is_cached(KnowledgeBase::GLOBAL, child.id()));
+        assert!(child.has_ancestor(parent));
+    }
+
+    #[test]
+    fn test_invalidated_by_removed_parent() {
+        initialize_kb();
+        let parent = Form::archetype().individuate_as_archetype();
+        let mut child = parent.individuate_as_archetype();
+        assert!(child.has_ancestor(parent));
+        child.remove_outgoing(Inherits::TYPE_ID, &parent);
+        assert!(!child.has_ancestor(parent));
+    }
+
+    #[test]
+    fn test_invalidated_by_rollback() {
+        initialize_kb();
+        let parent = Form::archetype().individuate_as_archetype();
+        let mut child = Form::archetype().individuate_as_archetype();
+        let mut graph = KnowledgeBase::GLOBAL.graph();
+        graph.begin_transaction();
+        child.add_parent(parent);
+        assert!(child.has_ancestor(parent));
+        graph.rollback();
+        assert!(!child.has_ancestor(parent));
+    }
+
+    #[test]
+    fn test_unaffected_by_other_edges() {
+        initialize_kb();
+        let mut form = Form::new();
+        form.inheritance_nodes();
+        form.set_internal_name("unrelated");
+        form.add_flag(Inherits::TYPE_ID);
+        assert!(is_cached(KnowledgeBase::GLOBAL, form.id()));
+    }
+
+    #[test]
+    fn test_kbs_cached_separately() {
+        initialize_kb();
+        let kb = new_in_memory_kb();
+        let form = Form::archetype().individuate_as_archetype();
+        let other = kb.with_default(|| Form::archetype().individuate_as_archetype());
+        form.inheritance_nodes();
+        other.inheritance_nodes();
+        kb.with_default(|| {
+            Form::archetype()
+                .individuate_as_archetype()
+                .add_parent(other)
+        });
+        assert!(is_cached(KnowledgeBase::GLOBAL, form.id()));
+        assert!(!is_cached(kb, other.id()));
+    }
+
+    #[test]
+    fn test_forgotten_on_rebind() {
+        initialize_kb();
+        let form = Form::archetype().individuate_as_archetype();
+        form.inheritance_nodes();
+        initialize_kb();
+        assert!(!is_cached(KnowledgeBase::GLOBAL, form.id()));
+    }
+
+    #[cfg(feature = "thread-safe")]
+    #[test]
+    fn test_shared_graph_not_cached() {
+        crate::tao::initialize_shared_kb();
+        let form = Form::archetype().individuate_as_archetype();
+        form.inheritance_nodes();
+        assert!(!is_cached(KnowledgeBase::GLOBAL, form.id()));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_graph_not_cached() {
+        crate::tao::initialize_sqlite_kb(":memory:");
+        let form = Form::archetype().individuate_as_archetype();
+        form.inheritance_nodes();
+        assert!(!is_cached(KnowledgeBase::GLOBAL, form.id()));
+    }
+}
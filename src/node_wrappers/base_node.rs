@@ -1,6 +1,6 @@
 use super::{debug_wrapper, CommonNodeTrait};
-use crate::graph::value_wrappers::KBValue;
-use crate::graph::{Graph, InjectionGraph};
+use crate::graph::value_wrappers::{KBRc, KBValue};
+use crate::graph::{Graph, GraphResult, InjectionGraph, KnowledgeBase, NodeIter, NodeQuery};
 use std::cmp::{Eq, Ordering, PartialEq};
 use std::convert::TryFrom;
 use std::fmt;
@@ -11,10 +11,13 @@ use std::rc::Rc;
 /// All low-level wrappers will have these functions available.
 pub trait BaseNodeTrait<T>: CommonNodeTrait {
     /// Associate this node with a value.
-    fn set_value(&mut self, value: Rc<dyn KBValue>);
+    fn set_value(&mut self, value: KBRc<dyn KBValue>);
 
     /// Retrieve the value associated with this node.
-    fn value(&self) -> Option<Rc<dyn KBValue>>;
+    fn value(&self) -> Option<KBRc<dyn KBValue>>;
+
+    /// Fallible version of `value`.
+    fn try_value(&self) -> GraphResult<Option<KBRc<dyn KBValue>>>;
 
     /// Add flag to node.
     fn add_flag(&mut self, flag_type: usize);
@@ -22,12 +25,23 @@ pub trait BaseNodeTrait<T>: CommonNodeTrait {
     /// Check if node has flag.
     fn has_flag(&self, flag_type: usize) -> bool;
 
+    /// Fallible version of `has_flag`.
+    fn try_has_flag(&self, flag_type: usize) -> GraphResult<bool>;
+
     /// Link this node to another one via an outgoing edge.
     fn add_outgoing(&mut self, edge_type: usize, to: &T);
 
     /// Link this node to another one via an incoming edge.
     fn add_incoming(&mut self, edge_type: usize, from: &T);
 
+    /// Remove all outgoing edges of a certain type that link this node to another one. Nothing
+    /// happens if there are no such edges.
+    fn remove_outgoing(&mut self, edge_type: usize, to: &T);
+
+    /// Remove all incoming edges of a certain type that link another node to this one. Nothing
+    /// happens if there are no such edges.
+    fn remove_incoming(&mut self, edge_type: usize, from: &T);
+
     /// Whether or not this node is linked to another one via an outgoing edge of a certain type.
     fn has_outgoing(&self, edge_type: usize, to: &T) -> bool;
 
@@ -37,11 +51,29 @@ pub trait BaseNodeTrait<T>: CommonNodeTrait {
     /// All nodes that this one links to via outgoing edges of a certain type.
     fn outgoing_nodes(&self, edge_type: usize) -> Vec<T>;
 
+    /// Fallible version of `outgoing_nodes`.
+    fn try_outgoing_nodes(&self, edge_type: usize) -> GraphResult<Vec<T>>;
+
     /// All nodes that this one links to via incoming edges of a certain type.
     fn incoming_nodes(&self, edge_type: usize) -> Vec<T>;
+
+    /// Fallible version of `incoming_nodes`.
+    fn try_incoming_nodes(&self, edge_type: usize) -> GraphResult<Vec<T>>;
+
+    /// Lazy version of `outgoing_nodes`, for when there may be too many nodes to retrieve all at
+    /// once. Nodes still come out in ascending order of ID.
+    fn iter_outgoing_nodes(&self, edge_type: usize) -> Box<dyn Iterator<Item = T>>;
+
+    /// Lazy version of `incoming_nodes`, for when there may be too many nodes to retrieve all at
+    /// once. Nodes still come out in ascending order of ID.
+    fn iter_incoming_nodes(&self, edge_type: usize) -> Box<dyn Iterator<Item = T>>;
 }
 
 /// Implementation for the most basic of node wrappers. Offers no additional functionality.
+///
+/// Each node is bound to the KB that was current when the node wrapper was created. Nodes are
+/// compared by ID alone, so that the same type concepts are equal no matter which KB they were
+/// looked up in.
 #[derive(Copy, Clone)]
 pub struct BaseNode {
     graph: InjectionGraph,
@@ -52,28 +84,60 @@ pub struct BaseNode {
 impl BaseNode {
     /// Create a new node.
     pub fn new() -> Self {
-        let mut g = InjectionGraph::new();
+        Self::new_in(KnowledgeBase::current())
+    }
+
+    /// Create a new node in a specific KB.
+    pub fn new_in(kb: KnowledgeBase) -> Self {
+        let mut g = kb.graph();
         BaseNode {
             graph: g,
             id: g.add_node(),
         }
     }
-}
 
-impl From<usize> for BaseNode {
-    fn from(id: usize) -> Self {
+    /// Wrap an existing node from a specific KB.
+    pub fn from_id_in(kb: KnowledgeBase, id: usize) -> Self {
         BaseNode {
-            graph: InjectionGraph::new(),
+            graph: kb.graph(),
             id,
         }
     }
+
+    /// Wrap another node from the same KB as this one.
+    fn sibling(&self, id: usize) -> Self {
+        BaseNode {
+            graph: self.graph,
+            id,
+        }
+    }
+
+    /// Nodes that this one links to via outgoing edges of a certain type, in the order the links
+    /// were first added.
+    pub(crate) fn try_outgoing_nodes_in_order(
+        &self,
+        edge_type: usize,
+    ) -> GraphResult<Vec<BaseNode>> {
+        Ok(self
+            .graph
+            .try_outgoing_nodes_in_order(self.id(), edge_type)?
+            .into_iter()
+            .map(|id| self.sibling(id))
+            .collect())
+    }
+}
+
+impl From<usize> for BaseNode {
+    fn from(id: usize) -> Self {
+        Self::from_id_in(KnowledgeBase::current(), id)
+    }
 }
 
 impl<'a> TryFrom<&'a str> for BaseNode {
     type Error = String;
 
     fn try_from(name: &'a str) -> Result<Self, Self::Error> {
-        let g = InjectionGraph {};
+        let g = InjectionGraph::new();
         // The last ID will be the most recently added node. We want later nodes to override
         // earlier ones.
         match g.lookup(name).last() {
@@ -120,6 +184,10 @@ impl CommonNodeTrait for BaseNode {
         self.id
     }
 
+    fn kb(&self) -> KnowledgeBase {
+        self.graph.kb()
+    }
+
     fn set_internal_name(&mut self, name: &str) {
         self.graph.set_node_name(self.id, name);
     }
@@ -127,17 +195,25 @@ impl CommonNodeTrait for BaseNode {
     fn internal_name(&self) -> Option<Rc<str>> {
         self.graph.node_name(self.id)
     }
+
+    fn try_internal_name(&self) -> GraphResult<Option<Rc<str>>> {
+        self.graph.try_node_name(self.id)
+    }
 }
 
 impl BaseNodeTrait<BaseNode> for BaseNode {
-    fn set_value(&mut self, value: Rc<dyn KBValue>) {
+    fn set_value(&mut self, value: KBRc<dyn KBValue>) {
         self.graph.set_node_value(self.id, value)
     }
 
-    fn value(&self) -> Option<Rc<dyn KBValue>> {
+    fn value(&self) -> Option<KBRc<dyn KBValue>> {
         self.graph.node_value(self.id)
     }
 
+    fn try_value(&self) -> GraphResult<Option<KBRc<dyn KBValue>>> {
+        self.graph.try_node_value(self.id)
+    }
+
     fn add_flag(&mut self, flag_type: usize) {
         // note: we can also reuse the edge API as such:
         //self.graph.add_edge(self.id, flag_type, self.id);
@@ -150,6 +226,10 @@ impl BaseNodeTrait<BaseNode> for BaseNode {
         self.graph.has_flag(self.id, flag_type)
     }
 
+    fn try_has_flag(&self, flag_type: usize) -> GraphResult<bool> {
+        self.graph.try_has_flag(self.id, flag_type)
+    }
+
     fn add_outgoing(&mut self, edge_type: usize, to: &BaseNode) {
         self.graph.add_edge(self.id(), edge_type, to.id())
     }
@@ -158,6 +238,14 @@ impl BaseNodeTrait<BaseNode> for BaseNode {
         self.graph.add_edge(from.id(), edge_type, self.id())
     }
 
+    fn remove_outgoing(&mut self, edge_type: usize, to: &BaseNode) {
+        self.graph.remove_edge(self.id(), edge_type, to.id())
+    }
+
+    fn remove_incoming(&mut self, edge_type: usize, from: &BaseNode) {
+        self.graph.remove_edge(from.id(), edge_type, self.id())
+    }
+
     fn has_outgoing(&self, edge_type: usize, to: &BaseNode) -> bool {
         self.graph.has_edge(self.id, edge_type, to.id)
     }
@@ -170,23 +258,60 @@ impl BaseNodeTrait<BaseNode> for BaseNode {
         self.graph
             .outgoing_nodes(self.id(), edge_type)
             .into_iter()
-            .map(BaseNode::from)
+            .map(|id| self.sibling(id))
             .collect()
     }
 
+    fn try_outgoing_nodes(&self, edge_type: usize) -> GraphResult<Vec<BaseNode>> {
+        Ok(self
+            .graph
+            .try_outgoing_nodes(self.id(), edge_type)?
+            .into_iter()
+            .map(|id| self.sibling(id))
+            .collect())
+    }
+
     fn incoming_nodes(&self, edge_type: usize) -> Vec<BaseNode> {
         self.graph
             .incoming_nodes(self.id(), edge_type)
             .into_iter()
-            .map(BaseNode::from)
+            .map(|id| self.sibling(id))
             .collect()
     }
+
+    fn try_incoming_nodes(&self, edge_type: usize) -> GraphResult<Vec<BaseNode>> {
+        Ok(self
+            .graph
+            .try_incoming_nodes(self.id(), edge_type)?
+            .into_iter()
+            .map(|id| self.sibling(id))
+            .collect())
+    }
+
+    fn iter_outgoing_nodes(&self, edge_type: usize) -> Box<dyn Iterator<Item = BaseNode>> {
+        let node = *self;
+        let query = NodeQuery::Outgoing {
+            from: self.id,
+            edge_type,
+        };
+        Box::new(NodeIter::in_kb(self.graph, query).map(move |id| node.sibling(id)))
+    }
+
+    fn iter_incoming_nodes(&self, edge_type: usize) -> Box<dyn Iterator<Item = BaseNode>> {
+        let node = *self;
+        let query = NodeQuery::Incoming {
+            to: self.id,
+            edge_type,
+        };
+        Box::new(NodeIter::in_kb(self.graph, query).map(move |id| node.sibling(id)))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::graph::value_wrappers::{unwrap_value, WeakValue};
+    use crate::graph::GraphError;
     use crate::tao::initialize_kb;
 
     #[test]
@@ -222,12 +347,36 @@ mod tests {
         assert_eq!(node.internal_name(), Some(Rc::from("A")));
     }
 
+    #[test]
+    fn node_in_other_kb() {
+        initialize_kb();
+        let kb = KnowledgeBase::new_in_memory();
+        let mut node = BaseNode::new_in(kb);
+        let mut other = BaseNode::new_in(kb);
+        node.set_internal_name("A");
+        node.add_outgoing(0, &other);
+        other.add_outgoing(0, &BaseNode::from_id_in(kb, other.id()));
+        assert_eq!(node.kb(), kb);
+        assert_eq!(BaseNode::from(node.id()).kb(), KnowledgeBase::GLOBAL);
+        assert_ne!(
+            BaseNode::from(node.id()).internal_name(),
+            Some(Rc::from("A"))
+        );
+        assert_eq!(node.internal_name(), Some(Rc::from("A")));
+        // nodes reached by following edges stay in the same KB
+        let neighbors = node.outgoing_nodes(0);
+        assert_eq!(neighbors, vec![other]);
+        assert_eq!(neighbors[0].kb(), kb);
+        assert_eq!(neighbors[0].outgoing_nodes(0), vec![other]);
+        assert_eq!(node.iter_outgoing_nodes(0).next().unwrap().kb(), kb);
+    }
+
     #[test]
     fn retrieve_node_value() {
         initialize_kb();
         let mut node = BaseNode::new();
-        let v = Rc::new(5);
-        node.set_value(Rc::new(WeakValue::new(&v)));
+        let v = KBRc::new(5);
+        node.set_value(KBRc::new(WeakValue::new(&v)));
         assert_eq!(unwrap_value::<i32>(node.value()), Some(v));
     }
 
@@ -290,6 +439,11 @@ mod tests {
         a.add_incoming(edge_type1.id(), &d);
         e.add_incoming(edge_type1.id(), &a);
         assert_eq!(a.incoming_nodes(edge_type1.id()), vec![b, d]);
+        assert_eq!(
+            a.iter_incoming_nodes(edge_type1.id()).collect::<Vec<_>>(),
+            vec![b, d]
+        );
+        assert!(a.iter_incoming_nodes(edge_type1.id()).any(|n| n == d));
     }
 
     #[test]
@@ -305,6 +459,43 @@ mod tests {
         assert!(!b.has_outgoing(edge_type1.id(), &a));
     }
 
+    #[test]
+    fn test_remove_outgoing() {
+        initialize_kb();
+        let mut a = BaseNode::new();
+        let mut b = BaseNode::new();
+        let edge_type = BaseNode::new();
+        a.add_outgoing(edge_type.id(), &b);
+        a.add_outgoing(edge_type.id(), &b);
+        a.remove_outgoing(edge_type.id(), &b);
+        assert!(!a.has_outgoing(edge_type.id(), &b));
+        a.add_outgoing(edge_type.id(), &b);
+        b.remove_incoming(edge_type.id(), &a);
+        assert_eq!(a.outgoing_nodes(edge_type.id()), vec![]);
+    }
+
+    #[test]
+    fn test_stale_node_errors() {
+        initialize_kb();
+        let mut g = InjectionGraph::new();
+        let stale = BaseNode::new();
+        g.remove_node(stale.id());
+        assert_eq!(
+            stale.try_internal_name(),
+            Err(GraphError::UnknownNode(stale.id()))
+        );
+        assert_eq!(
+            stale.try_has_flag(stale.id()),
+            Err(GraphError::UnknownNode(stale.id()))
+        );
+        assert_eq!(
+            stale.try_outgoing_nodes(stale.id()),
+            Err(GraphError::UnknownNode(stale.id()))
+        );
+        // the infallible version still treats missing nodes as unnamed
+        assert_eq!(stale.internal_name(), None);
+    }
+
     #[test]
     fn test_has_incoming() {
         initialize_kb();
@@ -1,12 +1,14 @@
-use super::{debug_wrapper, BaseNode, BaseNodeTrait, InheritanceNode, InheritanceNodeTrait};
-use crate::graph::value_wrappers::KBValue;
+use super::{
+    debug_wrapper, BaseNode, BaseNodeTrait, Explanation, InheritanceNode, InheritanceNodeTrait,
+};
+use crate::graph::value_wrappers::{KBRc, KBValue};
+use crate::graph::{GraphResult, KnowledgeBase};
 use std::cmp::{Eq, PartialEq};
 use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::{Debug, Formatter};
 use std::hash::Hash;
 use std::ops::{Deref, DerefMut};
-use std::rc::Rc;
 
 /// Final node wrapper that offers a stable API for all concept abstractions dependent on it.
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
@@ -23,6 +25,20 @@ impl FinalNode {
         }
     }
 
+    /// Create a new node in a specific KB.
+    pub fn new_in(kb: KnowledgeBase) -> Self {
+        FinalNode {
+            inode: InheritanceNode::new_in(kb),
+        }
+    }
+
+    /// Wrap an existing node from a specific KB.
+    pub fn from_id_in(kb: KnowledgeBase, id: usize) -> Self {
+        FinalNode {
+            inode: InheritanceNode::from_id_in(kb, id),
+        }
+    }
+
     /// Create a new node with an inheritance relation.
     pub fn new_with_inheritance(type_id: usize) -> Self {
         Self::from(InheritanceNode::new_with_inheritance(type_id))
@@ -90,14 +106,18 @@ impl DerefMut for FinalNode {
 }
 
 impl BaseNodeTrait<FinalNode> for FinalNode {
-    fn set_value(&mut self, value: Rc<dyn KBValue>) {
+    fn set_value(&mut self, value: KBRc<dyn KBValue>) {
         self.inode.set_value(value)
     }
 
-    fn value(&self) -> Option<Rc<dyn KBValue>> {
+    fn value(&self) -> Option<KBRc<dyn KBValue>> {
         self.inode.value()
     }
 
+    fn try_value(&self) -> GraphResult<Option<KBRc<dyn KBValue>>> {
+        self.inode.try_value()
+    }
+
     fn add_flag(&mut self, flag_type: usize) {
         self.inode.add_flag(flag_type);
     }
@@ -106,6 +126,10 @@ impl BaseNodeTrait<FinalNode> for FinalNode {
         self.inode.has_flag(flag_type)
     }
 
+    fn try_has_flag(&self, flag_type: usize) -> GraphResult<bool> {
+        self.inode.try_has_flag(flag_type)
+    }
+
     fn add_outgoing(&mut self, edge_type: usize, to: &FinalNode) {
         self.inode.add_outgoing(edge_type, &to.inode)
     }
@@ -114,6 +138,14 @@ impl BaseNodeTrait<FinalNode> for FinalNode {
         self.inode.add_incoming(edge_type, &from.inode)
     }
 
+    fn remove_outgoing(&mut self, edge_type: usize, to: &FinalNode) {
+        self.inode.remove_outgoing(edge_type, &to.inode)
+    }
+
+    fn remove_incoming(&mut self, edge_type: usize, from: &FinalNode) {
+        self.inode.remove_incoming(edge_type, &from.inode)
+    }
+
     fn has_outgoing(&self, edge_type: usize, to: &FinalNode) -> bool {
         self.inode.has_outgoing(edge_type, &to.inode)
     }
@@ -130,6 +162,15 @@ impl BaseNodeTrait<FinalNode> for FinalNode {
             .collect()
     }
 
+    fn try_outgoing_nodes(&self, edge_type: usize) -> GraphResult<Vec<FinalNode>> {
+        Ok(self
+            .inode
+            .try_outgoing_nodes(edge_type)?
+            .into_iter()
+            .map(FinalNode::from)
+            .collect())
+    }
+
     fn incoming_nodes(&self, edge_type: usize) -> Vec<FinalNode> {
         self.inode
             .incoming_nodes(edge_type)
@@ -137,6 +178,31 @@ impl BaseNodeTrait<FinalNode> for FinalNode {
             .map(FinalNode::from)
             .collect()
     }
+
+    fn try_incoming_nodes(&self, edge_type: usize) -> GraphResult<Vec<FinalNode>> {
+        Ok(self
+            .inode
+            .try_incoming_nodes(edge_type)?
+            .into_iter()
+            .map(FinalNode::from)
+            .collect())
+    }
+
+    fn iter_outgoing_nodes(&self, edge_type: usize) -> Box<dyn Iterator<Item = FinalNode>> {
+        Box::new(
+            self.inode
+                .iter_outgoing_nodes(edge_type)
+                .map(FinalNode::from),
+        )
+    }
+
+    fn iter_incoming_nodes(&self, edge_type: usize) -> Box<dyn Iterator<Item = FinalNode>> {
+        Box::new(
+            self.inode
+                .iter_incoming_nodes(edge_type)
+                .map(FinalNode::from),
+        )
+    }
 }
 
 impl InheritanceNodeTrait<FinalNode> for FinalNode {
@@ -147,6 +213,45 @@ impl InheritanceNodeTrait<FinalNode> for FinalNode {
             .map(FinalNode::from)
             .collect()
     }
+
+    fn try_inheritance_nodes(&self) -> GraphResult<Vec<FinalNode>> {
+        Ok(self
+            .inode
+            .try_inheritance_nodes()?
+            .into_iter()
+            .map(FinalNode::from)
+            .collect())
+    }
+
+    fn explain_flag(&self, flag_type: usize) -> Option<Explanation<FinalNode>> {
+        self.inode
+            .explain_flag(flag_type)
+            .map(|e| e.map(FinalNode::from))
+    }
+
+    fn try_explain_flag(&self, flag_type: usize) -> GraphResult<Option<Explanation<FinalNode>>> {
+        Ok(self
+            .inode
+            .try_explain_flag(flag_type)?
+            .map(|e| e.map(FinalNode::from)))
+    }
+
+    fn explain_outgoing(&self, edge_type: usize, to: &FinalNode) -> Option<Explanation<FinalNode>> {
+        self.inode
+            .explain_outgoing(edge_type, &to.inode)
+            .map(|e| e.map(FinalNode::from))
+    }
+
+    fn try_explain_outgoing(
+        &self,
+        edge_type: usize,
+        to: &FinalNode,
+    ) -> GraphResult<Option<Explanation<FinalNode>>> {
+        Ok(self
+            .inode
+            .try_explain_outgoing(edge_type, &to.inode)?
+            .map(|e| e.map(FinalNode::from)))
+    }
 }
 
 #[cfg(test)]
@@ -179,8 +284,8 @@ mod tests {
     fn retrieve_node_value() {
         initialize_kb();
         let mut node = FinalNode::new();
-        let v = Rc::new(5);
-        node.set_value(Rc::new(WeakValue::new(&v)));
+        let v = KBRc::new(5);
+        node.set_value(KBRc::new(WeakValue::new(&v)));
         assert_eq!(unwrap_value::<i32>(node.value()), Some(v));
     }
 
@@ -1,21 +1,63 @@
+use super::ancestor_cache;
 use super::BaseNode;
-use super::{debug_wrapper, BaseNodeTrait};
-use crate::graph::value_wrappers::KBValue;
+use super::{debug_wrapper, BaseNodeTrait, CommonNodeTrait};
+use crate::graph::value_wrappers::{KBRc, KBValue};
+use crate::graph::{expect_graph, GraphError, GraphResult, KnowledgeBase};
 use crate::tao::archetype::ArchetypeTrait;
-use crate::tao::relation::attribute::Inherits;
+use crate::tao::relation::attribute::{Inherits, Negation};
+use crate::tao::relation::flag::Nonhereditary;
 use std::cmp::{Eq, PartialEq};
-use std::collections::{HashSet, VecDeque};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::{Debug, Formatter};
 use std::hash::Hash;
+use std::iter::Peekable;
 use std::ops::{Deref, DerefMut};
-use std::rc::Rc;
 
 /// All wrappers that are aware of attribute inheritance will have these functions available.
 pub trait InheritanceNodeTrait<T>: BaseNodeTrait<T> {
     /// The set of nodes, including this one, whose attributes count as this one's.
     fn inheritance_nodes(&self) -> Vec<T>;
+
+    /// Fallible version of `inheritance_nodes`.
+    fn try_inheritance_nodes(&self) -> GraphResult<Vec<T>>;
+
+    /// Why this node has the given flag, or `None` if it doesn't have it.
+    fn explain_flag(&self, flag_type: usize) -> Option<Explanation<T>>;
+
+    /// Fallible version of `explain_flag`.
+    fn try_explain_flag(&self, flag_type: usize) -> GraphResult<Option<Explanation<T>>>;
+
+    /// Why this node has an outgoing edge of the given type to `to`, or `None` if it doesn't.
+    fn explain_outgoing(&self, edge_type: usize, to: &T) -> Option<Explanation<T>>;
+
+    /// Fallible version of `explain_outgoing`.
+    fn try_explain_outgoing(&self, edge_type: usize, to: &T)
+        -> GraphResult<Option<Explanation<T>>>;
+}
+
+/// Why a node has a flag, edge, or ancestor that it may have inherited from somewhere else.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Explanation<T> {
+    /// The nodes visited when following `Inherits` edges from the node being explained up to
+    /// `asserted_by`, including both ends. This is only the node itself if it made the assertion
+    /// on its own.
+    pub chain: Vec<T>,
+    /// The node that the flag or edge was actually set on. When explaining an ancestor, this is
+    /// the node that inherits directly from that ancestor.
+    pub asserted_by: T,
+}
+
+impl<T> Explanation<T> {
+    /// Convert the nodes in this explanation to a different wrapper type.
+    pub(crate) fn map<U, F: Fn(T) -> U>(self, f: F) -> Explanation<U> {
+        Explanation {
+            chain: self.chain.into_iter().map(&f).collect(),
+            asserted_by: f(self.asserted_by),
+        }
+    }
 }
 
 /// Implementation for a node wrapper that offers inheritance of nodes.
@@ -33,6 +75,268 @@ impl InheritanceNode {
         }
     }
 
+    /// Create a new node in a specific KB.
+    pub fn new_in(kb: KnowledgeBase) -> Self {
+        InheritanceNode {
+            bnode: BaseNode::new_in(kb),
+        }
+    }
+
+    /// Walk up the `Inherits` edges to find the IDs of this node and all of its ancestors, in
+    /// ascending order. `try_inheritance_nodes` caches the results of this.
+    fn try_search_ancestors(&self) -> GraphResult<Vec<usize>> {
+        let mut visited = HashSet::new();
+        visited.insert(self.bnode);
+        let mut to_be_visited = VecDeque::new();
+        to_be_visited.push_back(self.bnode);
+        while let Some(next) = to_be_visited.pop_front() {
+            for neighbor in next.try_outgoing_nodes(Inherits::TYPE_ID)? {
+                if !visited.contains(&neighbor) {
+                    visited.insert(neighbor);
+                    to_be_visited.push_back(neighbor);
+                }
+            }
+        }
+        let mut result: Vec<usize> = visited.into_iter().map(|b| b.id()).collect();
+        result.sort_unstable();
+        Ok(result)
+    }
+
+    /// The nodes whose attributes or flags of the given type count as this one's. This is just the
+    /// node itself for inheritance links, and for any type marked as nonhereditary. Otherwise, it
+    /// is every node in the inheritance chain.
+    fn try_inheritance_nodes_for(&self, type_id: usize) -> GraphResult<Vec<InheritanceNode>> {
+        if type_id == Inherits::TYPE_ID || self.try_is_nonhereditary(type_id)? {
+            Ok(vec![*self])
+        } else {
+            self.try_inheritance_nodes()
+        }
+    }
+
+    /// Whether the given attribute or flag type, or any of its ancestors, is marked as
+    /// nonhereditary. The flag is checked directly on each ancestor rather than via `has_flag`,
+    /// because `has_flag` itself needs to know whether the flag is hereditary.
+    fn try_is_nonhereditary(&self, type_id: usize) -> GraphResult<bool> {
+        let type_node = InheritanceNode::from(BaseNode::from_id_in(self.kb(), type_id));
+        let type_ancestors = match type_node.try_inheritance_nodes() {
+            Ok(ancestors) => ancestors,
+            // types don't have to be nodes, and a type that isn't one can't be marked at all
+            Err(GraphError::UnknownNode(id)) if id == type_id => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        for ancestor in type_ancestors {
+            if ancestor.bnode.try_has_flag(Nonhereditary::TYPE_ID)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// The type that negates the given flag or attribute type, if it has ever been negated.
+    fn try_negation_of(&self, type_id: usize) -> GraphResult<Option<BaseNode>> {
+        let type_node = BaseNode::from_id_in(self.kb(), type_id);
+        match type_node.try_outgoing_nodes(Negation::TYPE_ID) {
+            Ok(negations) => Ok(negations.first().copied()),
+            // as with nonhereditary types, a type that isn't a node can't have been negated
+            Err(GraphError::UnknownNode(id)) if id == type_id => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether an assertion made by any of `asserters` still stands after taking the negations
+    /// made by `negators` into account.
+    fn try_survives(
+        &self,
+        asserters: &[InheritanceNode],
+        negators: &[InheritanceNode],
+    ) -> GraphResult<bool> {
+        if negators.is_empty() {
+            return Ok(!asserters.is_empty());
+        }
+        Ok(self.try_winning_asserter(asserters, negators)?.is_some())
+    }
+
+    /// The asserter whose assertion stands after taking the negations made by `negators` into
+    /// account. Whichever comes first in the linearization wins, with a negation beating an
+    /// assertion made on the same node.
+    ///
+    /// Fails if there are negations to weigh but no consistent linearization to weigh them by.
+    fn try_winning_asserter(
+        &self,
+        asserters: &[InheritanceNode],
+        negators: &[InheritanceNode],
+    ) -> GraphResult<Option<InheritanceNode>> {
+        if asserters.is_empty() {
+            return Ok(None);
+        }
+        let order = match self.try_linearization()? {
+            Some(order) => order,
+            // without any negations, every assertion stands anyway
+            None if negators.is_empty() => return Ok(asserters.first().copied()),
+            None => return Err(GraphError::InconsistentLinearization(self.id())),
+        };
+        for iw in order {
+            if negators.contains(&iw) {
+                return Ok(None);
+            }
+            if asserters.contains(&iw) {
+                return Ok(Some(iw));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Explain an assertion made by one of `asserters`, if it still stands after taking the
+    /// negations made by `negators` into account.
+    fn try_explain(
+        &self,
+        asserters: &[InheritanceNode],
+        negators: &[InheritanceNode],
+    ) -> GraphResult<Option<Explanation<InheritanceNode>>> {
+        let asserted_by = match self.try_winning_asserter(asserters, negators)? {
+            Some(asserter) => asserter,
+            None => return Ok(None),
+        };
+        Ok(self
+            .try_inheritance_chain(&asserted_by)?
+            .map(|chain| Explanation { chain, asserted_by }))
+    }
+
+    /// The shortest path of `Inherits` edges from this node up to `ancestor`, including both
+    /// ends. Returns `None` if `ancestor` is not actually an ancestor of this node.
+    pub fn try_inheritance_chain(
+        &self,
+        ancestor: &InheritanceNode,
+    ) -> GraphResult<Option<Vec<InheritanceNode>>> {
+        let mut reached_from = HashMap::new();
+        reached_from.insert(self.bnode, self.bnode);
+        let mut to_be_visited = VecDeque::new();
+        to_be_visited.push_back(self.bnode);
+        while let Some(next) = to_be_visited.pop_front() {
+            if next == ancestor.bnode {
+                let mut chain = vec![InheritanceNode::from(next)];
+                let mut current = next;
+                while current != self.bnode {
+                    current = reached_from[&current];
+                    chain.push(InheritanceNode::from(current));
+                }
+                chain.reverse();
+                return Ok(Some(chain));
+            }
+            for neighbor in next.try_outgoing_nodes(Inherits::TYPE_ID)? {
+                if let Entry::Vacant(entry) = reached_from.entry(neighbor) {
+                    entry.insert(next);
+                    to_be_visited.push_back(neighbor);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Direct parents of this node in the order they were added, leaving out any parent that is
+    /// already an ancestor of another parent.
+    fn try_direct_parents(&self) -> GraphResult<Vec<InheritanceNode>> {
+        let candidates: Vec<InheritanceNode> = self
+            .bnode
+            .try_outgoing_nodes_in_order(Inherits::TYPE_ID)?
+            .into_iter()
+            .filter(|p| *p != self.bnode)
+            .map(InheritanceNode::from)
+            .collect();
+        let mut ancestries = Vec::new();
+        for candidate in &candidates {
+            ancestries.push(candidate.try_inheritance_nodes()?);
+        }
+        Ok(candidates
+            .iter()
+            .enumerate()
+            .filter(|(i, c)| {
+                !ancestries
+                    .iter()
+                    .enumerate()
+                    .any(|(j, ancestry)| j != *i && ancestry.contains(c))
+            })
+            .map(|(_, c)| *c)
+            .collect())
+    }
+
+    /// This node followed by all of its ancestors, ordered by the C3 method resolution order.
+    /// Every node comes before its own ancestors, and the direct parents of any node keep their
+    /// relative order, which is the order they were added in.
+    ///
+    /// Returns `None` if there is no order that satisfies both of these constraints, or if the
+    /// inheritance chain loops back on itself.
+    pub fn try_linearization(&self) -> GraphResult<Option<Vec<InheritanceNode>>> {
+        self.try_c3(&mut HashMap::new(), &mut HashSet::new())
+    }
+
+    fn try_c3(
+        &self,
+        done: &mut HashMap<InheritanceNode, Vec<InheritanceNode>>,
+        in_progress: &mut HashSet<InheritanceNode>,
+    ) -> GraphResult<Option<Vec<InheritanceNode>>> {
+        if let Some(linearization) = done.get(self) {
+            return Ok(Some(linearization.clone()));
+        }
+        if !in_progress.insert(*self) {
+            return Ok(None);
+        }
+        let parents = self.try_direct_parents()?;
+        let mut sequences = Vec::new();
+        for parent in &parents {
+            match parent.try_c3(done, in_progress)? {
+                Some(linearization) => sequences.push(linearization),
+                None => return Ok(None),
+            }
+        }
+        sequences.push(parents);
+        let mut linearization = vec![*self];
+        match c3_merge(sequences) {
+            Some(merged) => linearization.extend(merged),
+            None => return Ok(None),
+        }
+        in_progress.remove(self);
+        done.insert(*self, linearization.clone());
+        Ok(Some(linearization))
+    }
+
+    /// Linked nodes for an edge type that has been negated somewhere, so that the nearest
+    /// assertion or negation along the inheritance chain decides whether each node is included.
+    /// `links` looks up the nodes linked to a single node in the inheritance chain, in whichever
+    /// direction is wanted.
+    fn try_linked_nodes_with_negation(
+        &self,
+        edge_type: usize,
+        negation: BaseNode,
+        links: fn(&BaseNode, usize) -> GraphResult<Vec<BaseNode>>,
+    ) -> GraphResult<Vec<InheritanceNode>> {
+        #[allow(clippy::type_complexity)]
+        let mut claims: BTreeMap<BaseNode, (Vec<InheritanceNode>, Vec<InheritanceNode>)> =
+            BTreeMap::new();
+        for iw in self.try_inheritance_nodes_for(edge_type)? {
+            for n in links(&iw.bnode, edge_type)? {
+                claims.entry(n).or_default().0.push(iw);
+            }
+            for n in links(&iw.bnode, negation.id())? {
+                claims.entry(n).or_default().1.push(iw);
+            }
+        }
+        let mut nodes = Vec::new();
+        for (n, (asserters, negators)) in claims {
+            if self.try_survives(&asserters, &negators)? {
+                nodes.push(InheritanceNode::from(n));
+            }
+        }
+        Ok(nodes)
+    }
+
+    /// Wrap an existing node from a specific KB.
+    pub fn from_id_in(kb: KnowledgeBase, id: usize) -> Self {
+        InheritanceNode {
+            bnode: BaseNode::from_id_in(kb, id),
+        }
+    }
+
     /// Create a new node with an inheritance relation.
     pub fn new_with_inheritance(type_id: usize) -> Self {
         let mut new_iw = Self::new();
@@ -128,22 +432,51 @@ impl DerefMut for InheritanceNode {
 }
 
 impl BaseNodeTrait<InheritanceNode> for InheritanceNode {
-    fn set_value(&mut self, value: Rc<dyn KBValue>) {
+    fn set_value(&mut self, value: KBRc<dyn KBValue>) {
         self.bnode.set_value(value)
     }
 
-    fn value(&self) -> Option<Rc<dyn KBValue>> {
+    fn value(&self) -> Option<KBRc<dyn KBValue>> {
         self.bnode.value()
     }
 
+    fn try_value(&self) -> GraphResult<Option<KBRc<dyn KBValue>>> {
+        self.bnode.try_value()
+    }
+
     fn add_flag(&mut self, flag_type: usize) {
         self.bnode.add_flag(flag_type);
     }
 
     fn has_flag(&self, flag_type: usize) -> bool {
-        self.inheritance_nodes()
-            .into_iter()
-            .any(|iw| iw.bnode.has_flag(flag_type))
+        expect_graph(self.try_has_flag(flag_type))
+    }
+
+    fn try_has_flag(&self, flag_type: usize) -> GraphResult<bool> {
+        let inheritance_nodes = self.try_inheritance_nodes_for(flag_type)?;
+        match self.try_negation_of(flag_type)? {
+            None => {
+                for iw in inheritance_nodes {
+                    if iw.bnode.try_has_flag(flag_type)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            Some(negation) => {
+                let mut asserters = Vec::new();
+                let mut negators = Vec::new();
+                for iw in inheritance_nodes {
+                    if iw.bnode.try_has_flag(flag_type)? {
+                        asserters.push(iw);
+                    }
+                    if iw.bnode.try_has_flag(negation.id())? {
+                        negators.push(iw);
+                    }
+                }
+                self.try_survives(&asserters, &negators)
+            }
+        }
     }
 
     fn add_outgoing(&mut self, edge_type: usize, to: &InheritanceNode) {
@@ -154,87 +487,271 @@ impl BaseNodeTrait<InheritanceNode> for InheritanceNode {
         self.bnode.add_incoming(edge_type, &from.bnode)
     }
 
+    fn remove_outgoing(&mut self, edge_type: usize, to: &InheritanceNode) {
+        self.bnode.remove_outgoing(edge_type, &to.bnode)
+    }
+
+    fn remove_incoming(&mut self, edge_type: usize, from: &InheritanceNode) {
+        self.bnode.remove_incoming(edge_type, &from.bnode)
+    }
+
     fn has_outgoing(&self, edge_type: usize, to: &InheritanceNode) -> bool {
-        if edge_type == Inherits::TYPE_ID {
-            self.bnode.has_outgoing(edge_type, &to.bnode)
-        } else {
-            self.inheritance_nodes()
-                .into_iter()
-                .any(|iw| iw.bnode.has_outgoing(edge_type, &to.bnode))
+        if expect_graph(self.try_negation_of(edge_type)).is_some() {
+            return self.outgoing_nodes(edge_type).contains(to);
         }
+        expect_graph(self.try_inheritance_nodes_for(edge_type))
+            .into_iter()
+            .any(|iw| iw.bnode.has_outgoing(edge_type, &to.bnode))
     }
 
     fn has_incoming(&self, edge_type: usize, from: &InheritanceNode) -> bool {
-        if edge_type == Inherits::TYPE_ID {
-            self.bnode.has_incoming(edge_type, &from.bnode)
-        } else {
-            self.inheritance_nodes()
-                .into_iter()
-                .any(|iw| iw.bnode.has_incoming(edge_type, &from.bnode))
+        if expect_graph(self.try_negation_of(edge_type)).is_some() {
+            return self.incoming_nodes(edge_type).contains(from);
         }
+        expect_graph(self.try_inheritance_nodes_for(edge_type))
+            .into_iter()
+            .any(|iw| iw.bnode.has_incoming(edge_type, &from.bnode))
     }
 
     fn outgoing_nodes(&self, edge_type: usize) -> Vec<InheritanceNode> {
-        if edge_type == Inherits::TYPE_ID {
-            self.bnode
-                .outgoing_nodes(edge_type)
+        expect_graph(self.try_outgoing_nodes(edge_type))
+    }
+
+    fn try_outgoing_nodes(&self, edge_type: usize) -> GraphResult<Vec<InheritanceNode>> {
+        if let Some(negation) = self.try_negation_of(edge_type)? {
+            return self.try_linked_nodes_with_negation(
+                edge_type,
+                negation,
+                BaseNode::try_outgoing_nodes,
+            );
+        }
+        let inheritance_nodes = self.try_inheritance_nodes_for(edge_type)?;
+        if inheritance_nodes.len() == 1 {
+            Ok(self
+                .bnode
+                .try_outgoing_nodes(edge_type)?
                 .into_iter()
                 .map(InheritanceNode::from)
-                .collect()
+                .collect())
         } else {
-            let mut nodes = self
-                .inheritance_nodes()
-                .into_iter()
-                .map(|iw| iw.bnode.outgoing_nodes(edge_type))
-                .flatten()
-                .map(InheritanceNode::from)
-                .collect::<Vec<InheritanceNode>>();
+            let mut nodes = Vec::<InheritanceNode>::new();
+            for iw in inheritance_nodes {
+                for n in iw.bnode.try_outgoing_nodes(edge_type)? {
+                    nodes.push(InheritanceNode::from(n));
+                }
+            }
             nodes.sort();
             nodes.dedup();
-            nodes
+            Ok(nodes)
         }
     }
 
     fn incoming_nodes(&self, edge_type: usize) -> Vec<InheritanceNode> {
-        if edge_type == Inherits::TYPE_ID {
-            self.bnode
-                .incoming_nodes(edge_type)
+        expect_graph(self.try_incoming_nodes(edge_type))
+    }
+
+    fn try_incoming_nodes(&self, edge_type: usize) -> GraphResult<Vec<InheritanceNode>> {
+        if let Some(negation) = self.try_negation_of(edge_type)? {
+            return self.try_linked_nodes_with_negation(
+                edge_type,
+                negation,
+                BaseNode::try_incoming_nodes,
+            );
+        }
+        let inheritance_nodes = self.try_inheritance_nodes_for(edge_type)?;
+        if inheritance_nodes.len() == 1 {
+            Ok(self
+                .bnode
+                .try_incoming_nodes(edge_type)?
                 .into_iter()
                 .map(InheritanceNode::from)
-                .collect()
+                .collect())
         } else {
-            let mut nodes = self
-                .inheritance_nodes()
-                .into_iter()
-                .map(|iw| iw.bnode.incoming_nodes(edge_type))
-                .flatten()
-                .map(InheritanceNode::from)
-                .collect::<Vec<InheritanceNode>>();
+            let mut nodes = Vec::<InheritanceNode>::new();
+            for iw in inheritance_nodes {
+                for n in iw.bnode.try_incoming_nodes(edge_type)? {
+                    nodes.push(InheritanceNode::from(n));
+                }
+            }
             nodes.sort();
             nodes.dedup();
-            nodes
+            Ok(nodes)
+        }
+    }
+
+    fn iter_outgoing_nodes(&self, edge_type: usize) -> Box<dyn Iterator<Item = InheritanceNode>> {
+        if let Some(negation) = expect_graph(self.try_negation_of(edge_type)) {
+            // negations have to be weighed against each other, so there's nothing lazy to do here
+            let nodes = expect_graph(self.try_linked_nodes_with_negation(
+                edge_type,
+                negation,
+                BaseNode::try_outgoing_nodes,
+            ));
+            return Box::new(nodes.into_iter());
+        }
+        let inheritance_nodes = expect_graph(self.try_inheritance_nodes_for(edge_type));
+        if inheritance_nodes.len() == 1 {
+            Box::new(
+                self.bnode
+                    .iter_outgoing_nodes(edge_type)
+                    .map(InheritanceNode::from),
+            )
+        } else {
+            let inherited = inheritance_nodes
+                .into_iter()
+                .map(|iw| iw.bnode.iter_outgoing_nodes(edge_type))
+                .collect();
+            Box::new(SortedUnion::new(inherited).map(InheritanceNode::from))
+        }
+    }
+
+    fn iter_incoming_nodes(&self, edge_type: usize) -> Box<dyn Iterator<Item = InheritanceNode>> {
+        if let Some(negation) = expect_graph(self.try_negation_of(edge_type)) {
+            let nodes = expect_graph(self.try_linked_nodes_with_negation(
+                edge_type,
+                negation,
+                BaseNode::try_incoming_nodes,
+            ));
+            return Box::new(nodes.into_iter());
+        }
+        let inheritance_nodes = expect_graph(self.try_inheritance_nodes_for(edge_type));
+        if inheritance_nodes.len() == 1 {
+            Box::new(
+                self.bnode
+                    .iter_incoming_nodes(edge_type)
+                    .map(InheritanceNode::from),
+            )
+        } else {
+            let inherited = inheritance_nodes
+                .into_iter()
+                .map(|iw| iw.bnode.iter_incoming_nodes(edge_type))
+                .collect();
+            Box::new(SortedUnion::new(inherited).map(InheritanceNode::from))
+        }
+    }
+}
+
+/// Merge the linearizations of a node's parents, along with the list of parents itself, as per
+/// C3. Returns `None` if there is no way to merge them without contradicting one of them.
+fn c3_merge(mut sequences: Vec<Vec<InheritanceNode>>) -> Option<Vec<InheritanceNode>> {
+    let mut merged = Vec::new();
+    loop {
+        sequences.retain(|s| !s.is_empty());
+        if sequences.is_empty() {
+            return Some(merged);
+        }
+        let next = sequences
+            .iter()
+            .map(|s| s[0])
+            .find(|candidate| !sequences.iter().any(|s| s[1..].contains(candidate)))?;
+        merged.push(next);
+        for sequence in &mut sequences {
+            if sequence[0] == next {
+                sequence.remove(0);
+            }
         }
     }
 }
 
+/// Lazily merges several sorted streams of nodes into a single sorted stream without duplicates.
+struct SortedUnion {
+    streams: Vec<Peekable<Box<dyn Iterator<Item = BaseNode>>>>,
+}
+
+impl SortedUnion {
+    fn new(streams: Vec<Box<dyn Iterator<Item = BaseNode>>>) -> Self {
+        SortedUnion {
+            streams: streams.into_iter().map(Iterator::peekable).collect(),
+        }
+    }
+}
+
+impl Iterator for SortedUnion {
+    type Item = BaseNode;
+
+    fn next(&mut self) -> Option<BaseNode> {
+        let smallest = self
+            .streams
+            .iter_mut()
+            .filter_map(|stream| stream.peek().copied())
+            .min()?;
+        for stream in &mut self.streams {
+            // every stream is sorted, so any copies of the smallest node will be at the front
+            stream.next_if_eq(&smallest);
+        }
+        Some(smallest)
+    }
+}
+
 impl InheritanceNodeTrait<InheritanceNode> for InheritanceNode {
     fn inheritance_nodes(&self) -> Vec<InheritanceNode> {
-        let mut visited = HashSet::new();
-        visited.insert(self.bnode);
-        let mut to_be_visited = VecDeque::new();
-        to_be_visited.push_back(self.bnode);
-        while let Some(next) = to_be_visited.pop_front() {
-            for neighbor in next.outgoing_nodes(Inherits::TYPE_ID) {
-                if !visited.contains(&neighbor) {
-                    visited.insert(neighbor);
-                    to_be_visited.push_back(neighbor);
+        expect_graph(self.try_inheritance_nodes())
+    }
+
+    fn try_inheritance_nodes(&self) -> GraphResult<Vec<InheritanceNode>> {
+        let kb = self.kb();
+        let ancestors = ancestor_cache::ancestors(kb, self.id(), || self.try_search_ancestors())?;
+        Ok(ancestors
+            .iter()
+            .map(|id| InheritanceNode::from_id_in(kb, *id))
+            .collect())
+    }
+
+    fn explain_flag(&self, flag_type: usize) -> Option<Explanation<InheritanceNode>> {
+        expect_graph(self.try_explain_flag(flag_type))
+    }
+
+    fn try_explain_flag(
+        &self,
+        flag_type: usize,
+    ) -> GraphResult<Option<Explanation<InheritanceNode>>> {
+        let negation = self.try_negation_of(flag_type)?;
+        let mut asserters = Vec::new();
+        let mut negators = Vec::new();
+        for iw in self.try_inheritance_nodes_for(flag_type)? {
+            if iw.bnode.try_has_flag(flag_type)? {
+                asserters.push(iw);
+            }
+            if let Some(negation) = negation {
+                if iw.bnode.try_has_flag(negation.id())? {
+                    negators.push(iw);
                 }
             }
         }
-        let mut result: Vec<InheritanceNode> =
-            visited.into_iter().map(InheritanceNode::from).collect();
-        result.sort();
-        result
+        self.try_explain(&asserters, &negators)
+    }
+
+    fn explain_outgoing(
+        &self,
+        edge_type: usize,
+        to: &InheritanceNode,
+    ) -> Option<Explanation<InheritanceNode>> {
+        expect_graph(self.try_explain_outgoing(edge_type, to))
+    }
+
+    fn try_explain_outgoing(
+        &self,
+        edge_type: usize,
+        to: &InheritanceNode,
+    ) -> GraphResult<Option<Explanation<InheritanceNode>>> {
+        let negation = self.try_negation_of(edge_type)?;
+        let mut asserters = Vec::new();
+        let mut negators = Vec::new();
+        for iw in self.try_inheritance_nodes_for(edge_type)? {
+            if iw.bnode.try_outgoing_nodes(edge_type)?.contains(&to.bnode) {
+                asserters.push(iw);
+            }
+            if let Some(negation) = negation {
+                if iw
+                    .bnode
+                    .try_outgoing_nodes(negation.id())?
+                    .contains(&to.bnode)
+                {
+                    negators.push(iw);
+                }
+            }
+        }
+        self.try_explain(&asserters, &negators)
     }
 }
 
@@ -242,6 +759,7 @@ impl InheritanceNodeTrait<InheritanceNode> for InheritanceNode {
 mod tests {
     use super::*;
     use crate::graph::value_wrappers::{unwrap_value, WeakValue};
+    use crate::graph::{Graph, GraphError, InjectionGraph};
     use crate::node_wrappers::CommonNodeTrait;
     use crate::tao::initialize_kb;
     use crate::tao::relation::attribute::Owner;
@@ -267,8 +785,8 @@ mod tests {
     fn retrieve_node_value() {
         initialize_kb();
         let mut node = InheritanceNode::new();
-        let v = Rc::new(5);
-        node.set_value(Rc::new(WeakValue::new(&v)));
+        let v = KBRc::new(5);
+        node.set_value(KBRc::new(WeakValue::new(&v)));
         assert_eq!(unwrap_value::<i32>(node.value()), Some(v));
     }
 
@@ -319,6 +837,196 @@ mod tests {
         assert!(c.has_flag(b.id()));
     }
 
+    #[test]
+    fn test_nonhereditary_flags() {
+        initialize_kb();
+        let mut a = InheritanceNode::new();
+        let mut flag = InheritanceNode::new();
+        let mut c = InheritanceNode::new();
+        c.add_outgoing(Inherits::TYPE_ID, &a);
+        flag.add_flag(Nonhereditary::TYPE_ID);
+        a.add_flag(flag.id());
+        assert!(a.has_flag(flag.id()));
+        assert!(!c.has_flag(flag.id()));
+    }
+
+    #[test]
+    fn nonhereditary_outgoing_nodes() {
+        initialize_kb();
+        let mut type1 = InheritanceNode::new();
+        let mut a = InheritanceNode::new();
+        let b = InheritanceNode::new();
+        let c = InheritanceNode::new();
+        let mut doc_link = InheritanceNode::new();
+        let mut specific_doc_link = InheritanceNode::new();
+        doc_link.add_flag(Nonhereditary::TYPE_ID);
+        // subtypes of nonhereditary attributes are nonhereditary too
+        specific_doc_link.add_outgoing(Inherits::TYPE_ID, &doc_link);
+        type1.add_outgoing(doc_link.id(), &b);
+        type1.add_outgoing(specific_doc_link.id(), &c);
+        type1.add_incoming(doc_link.id(), &c);
+        a.add_outgoing(Inherits::TYPE_ID, &type1);
+
+        assert_eq!(type1.outgoing_nodes(doc_link.id()), vec![b]);
+        assert_eq!(a.outgoing_nodes(doc_link.id()), vec![]);
+        assert_eq!(a.outgoing_nodes(specific_doc_link.id()), vec![]);
+        assert_eq!(a.iter_outgoing_nodes(doc_link.id()).count(), 0);
+        assert!(!a.has_outgoing(doc_link.id(), &b));
+        assert!(!a.has_incoming(doc_link.id(), &c));
+        assert_eq!(a.incoming_nodes(doc_link.id()), vec![]);
+        assert_eq!(type1.incoming_nodes(doc_link.id()), vec![c]);
+    }
+
+    #[test]
+    fn test_diamond_linearization() {
+        initialize_kb();
+        let top = InheritanceNode::new();
+        let mut left = InheritanceNode::new();
+        let mut right = InheritanceNode::new();
+        let mut bottom = InheritanceNode::new();
+        left.add_outgoing(Inherits::TYPE_ID, &top);
+        right.add_outgoing(Inherits::TYPE_ID, &top);
+        bottom.add_outgoing(Inherits::TYPE_ID, &right);
+        bottom.add_outgoing(Inherits::TYPE_ID, &left);
+        // redundant parents don't change anything
+        bottom.add_outgoing(Inherits::TYPE_ID, &top);
+        assert_eq!(
+            bottom.try_linearization(),
+            Ok(Some(vec![bottom, right, left, top]))
+        );
+    }
+
+    #[test]
+    fn test_parents_in_insertion_order() {
+        initialize_kb();
+        let older = InheritanceNode::new();
+        let newer = InheritanceNode::new();
+        assert!(newer.id() > older.id());
+        let mut child = InheritanceNode::new();
+        child.add_outgoing(Inherits::TYPE_ID, &newer);
+        child.add_outgoing(Inherits::TYPE_ID, &older);
+        assert_eq!(
+            child.try_linearization(),
+            Ok(Some(vec![child, newer, older]))
+        );
+    }
+
+    #[test]
+    fn test_inconsistent_linearization() {
+        initialize_kb();
+        let b = InheritanceNode::new();
+        let a = InheritanceNode::new();
+        let mut c = InheritanceNode::new();
+        c.add_outgoing(Inherits::TYPE_ID, &b);
+        // p wants a before c, and therefore before b
+        let mut p = InheritanceNode::new();
+        p.add_outgoing(Inherits::TYPE_ID, &a);
+        p.add_outgoing(Inherits::TYPE_ID, &c);
+        // q wants b before a
+        let mut q = InheritanceNode::new();
+        q.add_outgoing(Inherits::TYPE_ID, &b);
+        q.add_outgoing(Inherits::TYPE_ID, &a);
+        let mut z = InheritanceNode::new();
+        z.add_outgoing(Inherits::TYPE_ID, &p);
+        z.add_outgoing(Inherits::TYPE_ID, &q);
+        assert_eq!(p.try_linearization(), Ok(Some(vec![p, a, c, b])));
+        assert_eq!(z.try_linearization(), Ok(None));
+    }
+
+    #[test]
+    fn test_negation_without_linearization() {
+        initialize_kb();
+        let mut flag = InheritanceNode::new();
+        let negated_flag = InheritanceNode::new();
+        flag.add_outgoing(Negation::TYPE_ID, &negated_flag);
+        let mut b = InheritanceNode::new();
+        let mut a = InheritanceNode::new();
+        b.add_flag(flag.id());
+        a.add_flag(negated_flag.id());
+        let mut c = InheritanceNode::new();
+        c.add_outgoing(Inherits::TYPE_ID, &b);
+        let mut p = InheritanceNode::new();
+        p.add_outgoing(Inherits::TYPE_ID, &a);
+        p.add_outgoing(Inherits::TYPE_ID, &c);
+        let mut q = InheritanceNode::new();
+        q.add_outgoing(Inherits::TYPE_ID, &b);
+        q.add_outgoing(Inherits::TYPE_ID, &a);
+        let mut z = InheritanceNode::new();
+        z.add_outgoing(Inherits::TYPE_ID, &p);
+        z.add_outgoing(Inherits::TYPE_ID, &q);
+        assert!(!p.has_flag(flag.id()));
+        assert_eq!(
+            z.try_has_flag(flag.id()),
+            Err(GraphError::InconsistentLinearization(z.id()))
+        );
+    }
+
+    #[test]
+    fn test_negated_incoming() {
+        initialize_kb();
+        let mut edge_type = InheritanceNode::new();
+        let negated_edge_type = InheritanceNode::new();
+        edge_type.add_outgoing(Negation::TYPE_ID, &negated_edge_type);
+        let parent = InheritanceNode::new();
+        let mut fan = InheritanceNode::new();
+        let mut critic = InheritanceNode::new();
+        fan.add_outgoing(edge_type.id(), &parent);
+        critic.add_outgoing(edge_type.id(), &parent);
+        let mut child = InheritanceNode::new();
+        child.add_outgoing(Inherits::TYPE_ID, &parent);
+        critic.add_outgoing(negated_edge_type.id(), &child);
+
+        assert_eq!(parent.incoming_nodes(edge_type.id()), vec![fan, critic]);
+        assert_eq!(child.incoming_nodes(edge_type.id()), vec![fan]);
+        assert!(child.has_incoming(edge_type.id(), &fan));
+        assert!(!child.has_incoming(edge_type.id(), &critic));
+        assert_eq!(
+            child
+                .iter_incoming_nodes(edge_type.id())
+                .collect::<Vec<_>>(),
+            vec![fan]
+        );
+    }
+
+    #[test]
+    fn test_negation_follows_linearization() {
+        initialize_kb();
+        let mut flag = InheritanceNode::new();
+        let negated_flag = InheritanceNode::new();
+        flag.add_outgoing(Negation::TYPE_ID, &negated_flag);
+        let mut grounded = InheritanceNode::new();
+        let mut flies = InheritanceNode::new();
+        let mut also_grounded = InheritanceNode::new();
+        grounded.add_flag(negated_flag.id());
+        flies.add_flag(flag.id());
+        also_grounded.add_flag(negated_flag.id());
+        // whichever parent was added first takes precedence
+        let mut a = InheritanceNode::new();
+        a.add_outgoing(Inherits::TYPE_ID, &grounded);
+        a.add_outgoing(Inherits::TYPE_ID, &flies);
+        let mut b = InheritanceNode::new();
+        b.add_outgoing(Inherits::TYPE_ID, &flies);
+        b.add_outgoing(Inherits::TYPE_ID, &also_grounded);
+        assert!(!a.has_flag(flag.id()));
+        assert!(b.has_flag(flag.id()));
+    }
+
+    #[test]
+    fn test_stale_inherited_flag() {
+        initialize_kb();
+        let mut g = InjectionGraph::new();
+        let stale = InheritanceNode::new();
+        g.remove_node(stale.id());
+        assert_eq!(
+            stale.try_has_flag(stale.id()),
+            Err(GraphError::UnknownNode(stale.id()))
+        );
+        assert_eq!(
+            stale.try_inheritance_nodes(),
+            Err(GraphError::UnknownNode(stale.id()))
+        );
+    }
+
     #[test]
     fn no_outgoing_nodes() {
         initialize_kb();
@@ -364,6 +1072,10 @@ mod tests {
         a.add_outgoing(Inherits::TYPE_ID, &type2);
         assert_eq!(a.outgoing_nodes(edge_type.id()), vec![b, c]);
         assert_eq!(type1.outgoing_nodes(edge_type.id()), vec![c]);
+        assert_eq!(
+            a.iter_outgoing_nodes(edge_type.id()).collect::<Vec<_>>(),
+            vec![b, c]
+        );
     }
 
     #[test]
@@ -424,6 +1136,10 @@ mod tests {
         a.add_outgoing(Inherits::TYPE_ID, &type2);
         assert_eq!(a.incoming_nodes(edge_type.id()), vec![b, c]);
         assert_eq!(type1.incoming_nodes(edge_type.id()), vec![c]);
+        assert_eq!(
+            a.iter_incoming_nodes(edge_type.id()).collect::<Vec<_>>(),
+            vec![b, c]
+        );
     }
 
     #[test]
@@ -528,4 +1244,111 @@ mod tests {
         assert!(type1.has_incoming(Inherits::TYPE_ID, &type2));
         assert!(!type1.has_incoming(Inherits::TYPE_ID, &a));
     }
+
+    #[test]
+    fn explain_own_flag() {
+        initialize_kb();
+        let flag = InheritanceNode::new();
+        let mut a = InheritanceNode::new();
+        a.add_flag(flag.id());
+        assert_eq!(
+            a.explain_flag(flag.id()),
+            Some(Explanation {
+                chain: vec![a],
+                asserted_by: a,
+            })
+        );
+    }
+
+    #[test]
+    fn explain_inherited_flag() {
+        initialize_kb();
+        let flag = InheritanceNode::new();
+        let mut type1 = InheritanceNode::new();
+        let mut type2 = InheritanceNode::new();
+        let mut a = InheritanceNode::new();
+        let b = InheritanceNode::new();
+        type1.add_flag(flag.id());
+        type2.add_outgoing(Inherits::TYPE_ID, &type1);
+        a.add_outgoing(Inherits::TYPE_ID, &type2);
+        a.add_outgoing(Inherits::TYPE_ID, &type1);
+        assert_eq!(
+            a.explain_flag(flag.id()),
+            Some(Explanation {
+                chain: vec![a, type1],
+                asserted_by: type1,
+            })
+        );
+        assert_eq!(b.explain_flag(flag.id()), None);
+    }
+
+    #[test]
+    fn explain_negated_flag() {
+        initialize_kb();
+        let mut flag = InheritanceNode::new();
+        let negation = InheritanceNode::new();
+        flag.add_outgoing(Negation::TYPE_ID, &negation);
+        let mut type1 = InheritanceNode::new();
+        let mut type2 = InheritanceNode::new();
+        let mut a = InheritanceNode::new();
+        type1.add_flag(flag.id());
+        type2.add_outgoing(Inherits::TYPE_ID, &type1);
+        type2.add_flag(negation.id());
+        a.add_outgoing(Inherits::TYPE_ID, &type2);
+        assert_eq!(a.explain_flag(flag.id()), None);
+
+        a.add_flag(flag.id());
+        assert_eq!(
+            a.explain_flag(flag.id()),
+            Some(Explanation {
+                chain: vec![a],
+                asserted_by: a,
+            })
+        );
+    }
+
+    #[test]
+    fn explain_inherited_outgoing() {
+        initialize_kb();
+        let mut type1 = InheritanceNode::new();
+        let mut type2 = InheritanceNode::new();
+        let mut a = InheritanceNode::new();
+        let b = InheritanceNode::new();
+        let c = InheritanceNode::new();
+        let edge_type = InheritanceNode::new();
+        type1.add_outgoing(edge_type.id(), &b);
+        type2.add_outgoing(Inherits::TYPE_ID, &type1);
+        a.add_outgoing(Inherits::TYPE_ID, &type2);
+        assert_eq!(
+            a.explain_outgoing(edge_type.id(), &b),
+            Some(Explanation {
+                chain: vec![a, type2, type1],
+                asserted_by: type1,
+            })
+        );
+        assert_eq!(a.explain_outgoing(edge_type.id(), &c), None);
+    }
+
+    #[test]
+    fn explain_nonhereditary_outgoing() {
+        initialize_kb();
+        let mut edge_type = InheritanceNode::new();
+        edge_type.add_flag(Nonhereditary::TYPE_ID);
+        let mut type1 = InheritanceNode::new();
+        let mut a = InheritanceNode::new();
+        let b = InheritanceNode::new();
+        type1.add_outgoing(edge_type.id(), &b);
+        a.add_outgoing(Inherits::TYPE_ID, &type1);
+        assert!(type1.explain_outgoing(edge_type.id(), &b).is_some());
+        assert_eq!(a.explain_outgoing(edge_type.id(), &b), None);
+    }
+
+    #[test]
+    fn inheritance_chain_not_an_ancestor() {
+        initialize_kb();
+        let type1 = InheritanceNode::new();
+        let a = InheritanceNode::new();
+        assert_eq!(a.try_inheritance_chain(&type1), Ok(None));
+        assert_eq!(a.try_inheritance_chain(&a), Ok(Some(vec![a])));
+    }
 }
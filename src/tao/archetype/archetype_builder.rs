@@ -0,0 +1,330 @@
+use super::{Archetype, ArchetypeFormTrait, AttributeArchetype, AttributeArchetypeFormTrait};
+use crate::node_wrappers::{CommonNodeTrait, FinalNode};
+use crate::tao::archetype::ArchetypeTrait;
+use crate::tao::form::{Form, FormTrait};
+use crate::tao::relation::Relation;
+use crate::tao::schema::expect_schema;
+use crate::tao::{SchemaError, SchemaResult};
+
+/// Defines a new archetype at runtime, complete with everything that would otherwise have been
+/// set up by code generated from a schema.
+///
+/// # Examples
+///
+/// ```rust
+/// # use zamm_yin::tao::initialize_kb;
+/// # initialize_kb();
+/// use zamm_yin::node_wrappers::CommonNodeTrait;
+/// use zamm_yin::tao::archetype::{
+///     ArchetypeBuilder, ArchetypeFormTrait, ArchetypeTrait, AttributeArchetype,
+///     AttributeArchetypeFormTrait,
+/// };
+/// use zamm_yin::tao::form::{Form, FormTrait};
+/// use zamm_yin::tao::relation::attribute::Attribute;
+///
+/// let program = ArchetypeBuilder::new(Form::archetype())
+///     .name("program")
+///     .description("A set of instructions for a computer.")
+///     .build();
+/// let language = AttributeArchetype::from(
+///     ArchetypeBuilder::new(Attribute::archetype().into())
+///         .name("language")
+///         .owner_archetype(program)
+///         .build()
+///         .id(),
+/// );
+/// assert_eq!(language.owner_archetype(), program);
+/// assert_eq!(program.internal_name().as_deref(), Some("program"));
+/// assert_eq!(
+///     program.description().as_deref(),
+///     Some("A set of instructions for a computer.")
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct ArchetypeBuilder {
+    parents: Vec<Archetype>,
+    name: Option<String>,
+    description: Option<String>,
+    owner_archetype: Option<Archetype>,
+    value_archetype: Option<Archetype>,
+    nonhereditary: bool,
+    meta: bool,
+    multi_valued: bool,
+    attributes: Vec<AttributeArchetype>,
+    default_attributes: Vec<(AttributeArchetype, Form)>,
+    flags: Vec<Archetype>,
+}
+
+impl ArchetypeBuilder {
+    /// Start defining a new archetype that inherits from the given parent. The new archetype will
+    /// live in the same KB as its parent.
+    pub fn new(parent: Archetype) -> Self {
+        Self {
+            parents: vec![parent],
+            name: None,
+            description: None,
+            owner_archetype: None,
+            value_archetype: None,
+            nonhereditary: false,
+            meta: false,
+            multi_valued: false,
+            attributes: Vec::new(),
+            default_attributes: Vec::new(),
+            flags: Vec::new(),
+        }
+    }
+
+    /// Have the new archetype inherit from another parent as well.
+    pub fn parent(mut self, parent: Archetype) -> Self {
+        self.parents.push(parent);
+        self
+    }
+
+    /// Set the internal name of the new archetype.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Describe the new archetype for the benefit of humans.
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_owned());
+        self
+    }
+
+    /// Restrict the owners of the new relation archetype.
+    pub fn owner_archetype(mut self, owner_archetype: Archetype) -> Self {
+        self.owner_archetype = Some(owner_archetype);
+        self
+    }
+
+    /// Restrict the values of the new attribute archetype.
+    pub fn value_archetype(mut self, value_archetype: Archetype) -> Self {
+        self.value_archetype = Some(value_archetype);
+        self
+    }
+
+    /// Mark the new relation archetype as not being inherited.
+    pub fn nonhereditary(mut self) -> Self {
+        self.nonhereditary = true;
+        self
+    }
+
+    /// Mark the new relation archetype as a meta-property.
+    pub fn meta(mut self) -> Self {
+        self.meta = true;
+        self
+    }
+
+    /// Mark the new attribute archetype as having multiple possible values for the same owner.
+    pub fn multi_valued(mut self) -> Self {
+        self.multi_valued = true;
+        self
+    }
+
+    /// Declare that instances of the new archetype have this type of attribute.
+    pub fn attribute(mut self, attribute_type: AttributeArchetype) -> Self {
+        self.attributes.push(attribute_type);
+        self
+    }
+
+    /// Declare that instances of the new archetype have this type of attribute, with the given
+    /// value unless they set their own.
+    pub fn default_attribute(mut self, attribute_type: AttributeArchetype, value: Form) -> Self {
+        self.default_attributes.push((attribute_type, value));
+        self
+    }
+
+    /// Declare that instances of the new archetype have this type of flag.
+    pub fn flag(mut self, flag_type: Archetype) -> Self {
+        self.flags.push(flag_type);
+        self
+    }
+
+    /// Define the new archetype in the KB. Either everything gets defined, or nothing does.
+    ///
+    /// This will panic if the new archetype was given the properties of a relation without any
+    /// of its parents descending from `Relation`.
+    pub fn build(self) -> Archetype {
+        expect_schema(self.try_build())
+    }
+
+    /// Fallible version of `build`, which defines nothing instead of panicking if the new
+    /// archetype was given the properties of a relation without any of its parents descending
+    /// from `Relation`.
+    pub fn try_build(self) -> SchemaResult<Archetype> {
+        let (first_parent, other_parents) = self.parents.split_first().unwrap();
+        let kb = first_parent.kb();
+        let has_relation_properties = self.owner_archetype.is_some()
+            || self.value_archetype.is_some()
+            || self.nonhereditary
+            || self.meta
+            || self.multi_valued;
+        let relation = Archetype::from(FinalNode::from_id_in(kb, Relation::TYPE_ID));
+        if has_relation_properties && !self.parents.iter().any(|p| p.has_ancestor(relation)) {
+            return Err(SchemaError::NotARelation {
+                parent: *first_parent,
+            });
+        }
+
+        Ok(kb.graph().transaction(|| {
+            let mut archetype = first_parent.individuate_as_archetype();
+            for parent in other_parents {
+                archetype.add_parent(*parent);
+            }
+            if let Some(name) = &self.name {
+                archetype.set_internal_name(name);
+            }
+            if let Some(description) = &self.description {
+                archetype.set_description(description);
+            }
+
+            if has_relation_properties {
+                let mut relation = AttributeArchetype::from(*archetype);
+                if let Some(owner_archetype) = &self.owner_archetype {
+                    relation.set_owner_archetype(owner_archetype);
+                }
+                if let Some(value_archetype) = &self.value_archetype {
+                    relation.set_value_archetype(value_archetype);
+                }
+                if self.nonhereditary {
+                    relation.mark_nonhereditary_attr();
+                }
+                if self.meta {
+                    relation.mark_meta_attr();
+                }
+                if self.multi_valued {
+                    relation.mark_multi_valued_attr();
+                }
+            }
+
+            for attribute_type in &self.attributes {
+                archetype.add_attribute(attribute_type);
+            }
+            for (attribute_type, value) in &self.default_attributes {
+                archetype.set_default_attribute(attribute_type, value);
+            }
+            for flag_type in &self.flags {
+                ArchetypeFormTrait::add_flag(&mut archetype, flag_type);
+            }
+            archetype
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{Graph, KnowledgeBase};
+    use crate::node_wrappers::BaseNodeTrait;
+    use crate::tao::initialize_kb;
+    use crate::tao::relation::attribute::{Attribute, Owner};
+    use crate::tao::relation::flag::{Flag, Nonhereditary};
+    use std::ops::Deref;
+
+    #[test]
+    fn test_build_with_parents() {
+        initialize_kb();
+        let animal = Form::archetype().individuate_as_archetype();
+        let pet = Form::archetype().individuate_as_archetype();
+        let dog = ArchetypeBuilder::new(animal)
+            .parent(pet)
+            .name("dog")
+            .description("Man's best friend.")
+            .build();
+        assert_eq!(dog.parents(), vec![animal, pet]);
+        assert_eq!(dog.internal_name().as_deref(), Some("dog"));
+        assert_eq!(dog.description().as_deref(), Some("Man's best friend."));
+    }
+
+    #[test]
+    fn test_build_attribute() {
+        initialize_kb();
+        let person = Form::archetype().individuate_as_archetype();
+        let pet = Form::archetype().individuate_as_archetype();
+        let owns = AttributeArchetype::from(
+            *ArchetypeBuilder::new(Attribute::archetype().into())
+                .owner_archetype(person)
+                .value_archetype(pet)
+                .nonhereditary()
+                .meta()
+                .multi_valued()
+                .build(),
+        );
+        assert_eq!(owns.owner_archetype(), person);
+        assert_eq!(owns.value_archetype(), pet);
+        assert!(owns.is_nonhereditary_attr());
+        assert!(owns.is_meta_attr());
+        assert!(owns.is_multi_valued_attr());
+        assert!(owns.deref().has_flag(Nonhereditary::TYPE_ID));
+    }
+
+    #[test]
+    fn test_build_properties() {
+        initialize_kb();
+        let name = Attribute::archetype().individuate_as_archetype();
+        let language = Attribute::archetype().individuate_as_archetype();
+        let compiled = Flag::archetype().individuate_as_archetype();
+        let rust = Form::new();
+        let program = ArchetypeBuilder::new(Form::archetype())
+            .attribute(name)
+            .default_attribute(language, rust)
+            .flag(compiled)
+            .build();
+        assert_eq!(program.added_attributes(), vec![name, language]);
+        assert_eq!(program.added_flags(), vec![compiled]);
+        assert_eq!(
+            program.individuate_as_form().attribute_values(&language),
+            vec![rust]
+        );
+    }
+
+    #[test]
+    fn test_relation_properties_on_non_relation() {
+        initialize_kb();
+        let person = Form::archetype().individuate_as_archetype();
+        let size = KnowledgeBase::current().graph().size();
+        assert_eq!(
+            ArchetypeBuilder::new(Form::archetype())
+                .owner_archetype(person)
+                .try_build(),
+            Err(SchemaError::NotARelation {
+                parent: Form::archetype()
+            })
+        );
+        assert_eq!(
+            ArchetypeBuilder::new(person).multi_valued().try_build(),
+            Err(SchemaError::NotARelation { parent: person })
+        );
+        // nothing got defined
+        assert_eq!(KnowledgeBase::current().graph().size(), size);
+
+        // any one parent being a relation is enough
+        let friend = ArchetypeBuilder::new(person)
+            .parent(Relation::archetype())
+            .owner_archetype(person)
+            .build();
+        assert_eq!(AttributeArchetype::from(*friend).owner_archetype(), person);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only relations have owner or value archetypes")]
+    fn test_relation_properties_on_non_relation_panic() {
+        initialize_kb();
+        ArchetypeBuilder::new(Form::archetype()).meta().build();
+    }
+
+    #[test]
+    fn test_build_in_parent_kb() {
+        initialize_kb();
+        let kb = crate::tao::new_in_memory_kb();
+        let parent = kb.with_default(|| Form::archetype().individuate_as_archetype());
+        let child = ArchetypeBuilder::new(parent).build();
+        assert_eq!(child.kb(), kb);
+        assert!(child.has_ancestor(parent));
+        assert!(child
+            .base_wrapper()
+            .incoming_nodes(Owner::TYPE_ID)
+            .is_empty());
+    }
+}
@@ -2,9 +2,13 @@ use super::Archetype;
 use crate::node_wrappers::{BaseNodeTrait, CommonNodeTrait, FinalNode};
 use crate::tao::archetype::{ArchetypeTrait, AttributeArchetype};
 use crate::tao::form::{Form, FormTrait};
-use crate::tao::relation::attribute::has_property::{HasAttribute, HasFlag};
-use crate::tao::relation::attribute::{Inherits, MetaForm};
+use crate::tao::inheritance::{resolve, try_resolve, Direction};
+use crate::tao::relation::attribute::has_property::{HasAttribute, HasDefault, HasFlag};
+use crate::tao::relation::attribute::{Attribute, AttributeTrait, Inherits, MetaForm};
+use crate::tao::schema::{check_owner, check_value, expect_schema, is_validating_schema};
+use crate::tao::SchemaResult;
 use std::collections::{HashSet, VecDeque};
+use std::iter;
 use std::ops::{Deref, DerefMut};
 
 /// Every concept represents a different way of looking at and manipulating the world. This one
@@ -32,18 +36,24 @@ pub trait ArchetypeFormTrait:
     ///
     /// Here, Self::ArchetypeForm should never be used, Self::Form is the self as the observer, and
     /// Self::SubjectForm is the subject archetype that is currently being observed.
-    type SubjectForm: ArchetypeTrait + FormTrait;
+    type SubjectForm: ArchetypeTrait + FormTrait + 'static;
 
     /// Create a subtype of the archetype represented by this Archetype instance.
     fn individuate_as_archetype(&self) -> Self::Form {
-        Self::Form::from(FinalNode::new_with_inheritance(self.id()))
+        Self::Form::from(
+            self.kb()
+                .with_default(|| FinalNode::new_with_inheritance(self.id())),
+        )
     }
 
     /// Create a new individual of the archetype represented by this Archetype instance.
     ///
     /// Convenience function for the static one.
     fn individuate_as_form(&self) -> Self::SubjectForm {
-        let mut result = Self::SubjectForm::from(FinalNode::new_with_inheritance(self.id()));
+        let mut result = Self::SubjectForm::from(
+            self.kb()
+                .with_default(|| FinalNode::new_with_inheritance(self.id())),
+        );
         result.mark_individual();
         result
     }
@@ -51,31 +61,36 @@ pub trait ArchetypeFormTrait:
     /// Individuals that adhere to this archetype. It is possible that some of these individuals
     /// might not be direct descendants of the archetype in question.
     fn individuals(&self) -> Vec<Self::SubjectForm> {
+        let mut result: Vec<Self::SubjectForm> = self.iter_individuals().collect();
+        result.sort();
+        result
+    }
+
+    /// Lazy version of `individuals`, which finds individuals one at a time by walking down the
+    /// inheritance hierarchy. Unlike with `individuals`, the results are not sorted.
+    fn iter_individuals(&self) -> Box<dyn Iterator<Item = Self::SubjectForm>> {
+        let root = *self.deref();
         let mut visited: HashSet<FinalNode> = HashSet::new();
-        visited.insert(*self.deref());
+        visited.insert(root);
         let mut to_be_visited: VecDeque<FinalNode> = VecDeque::new();
-        to_be_visited.push_back(*self.deref());
-        let mut leaves: Vec<FinalNode> = Vec::new();
-        while let Some(next) = to_be_visited.pop_front() {
-            let children = next.incoming_nodes(Inherits::TYPE_ID);
-            if children.is_empty() {
-                leaves.push(next);
-            } else {
-                for child in next.incoming_nodes(Inherits::TYPE_ID) {
-                    if !visited.contains(&child) {
-                        visited.insert(child);
+        to_be_visited.push_back(root);
+        let leaves = iter::from_fn(move || {
+            while let Some(next) = to_be_visited.pop_front() {
+                let mut is_leaf = true;
+                for child in next.iter_incoming_nodes(Inherits::TYPE_ID) {
+                    is_leaf = false;
+                    if visited.insert(child) {
                         to_be_visited.push_back(child);
                     }
                 }
+                // never return self, even if it's the only leaf
+                if is_leaf && next != root {
+                    return Some(next);
+                }
             }
-        }
-        let mut result: Vec<Self::SubjectForm> = leaves
-            .into_iter()
-            .filter(|l| l != self.deref()) // never return self, even if it's the only leaf
-            .map(Self::SubjectForm::from)
-            .collect();
-        result.sort();
-        result
+            None
+        });
+        Box::new(leaves.map(Self::SubjectForm::from))
     }
 
     /// Retrieve child archetypes.
@@ -92,14 +107,72 @@ pub trait ArchetypeFormTrait:
         self.add_outgoing(HasAttribute::TYPE_ID, attribute_type);
     }
 
+    /// Add an attribute type to this archetype, along with a default value that individuals of
+    /// this archetype get from `attribute_values` until they set their own. Defaults are kept
+    /// apart from the archetype's own attributes, so they aren't values of the archetype itself.
+    /// Like `set_attribute`, this replaces any default already set for exactly this attribute type
+    /// if it's single-valued, and adds to the defaults if it's multi-valued.
+    ///
+    /// If schema validation is on for this archetype's KB, this will panic if the archetype or the
+    /// value don't fit the attribute type's owner and value archetypes.
+    fn set_default_attribute(
+        &mut self,
+        attribute_type: &AttributeArchetype,
+        value: &Form,
+    ) -> Attribute {
+        expect_schema(self.try_set_default_attribute(attribute_type, value))
+    }
+
+    /// Fallible version of `set_default_attribute`, which leaves the archetype untouched instead
+    /// of panicking if schema validation is on and the archetype or the value don't fit the
+    /// attribute type.
+    fn try_set_default_attribute(
+        &mut self,
+        attribute_type: &AttributeArchetype,
+        value: &Form,
+    ) -> SchemaResult<Attribute> {
+        let existing = self
+            .default_attributes()
+            .into_iter()
+            .find(|a| a.parents().contains(&Archetype::from(*attribute_type)));
+        if let Some(mut attribute) = existing {
+            attribute.try_set_value(value)?;
+            return Ok(attribute);
+        }
+
+        if is_validating_schema(self.kb()) {
+            check_owner(attribute_type.deref(), self.deref())?;
+            check_value(attribute_type.deref(), value.deref())?;
+        }
+        Ok(self.kb().graph().transaction(|| {
+            self.add_attribute(attribute_type);
+            let mut attribute = attribute_type.individuate_as_form();
+            attribute.set_value(value);
+            self.add_outgoing(HasDefault::TYPE_ID, &attribute);
+            attribute
+        }))
+    }
+
+    /// The attributes holding the defaults set on this archetype itself, without any that it
+    /// inherits. These attributes have no owner.
+    fn default_attributes(&self) -> Vec<Attribute> {
+        let kb = self.kb();
+        self.base_wrapper()
+            .outgoing_nodes(HasDefault::TYPE_ID)
+            .into_iter()
+            .map(|b| Attribute::from(FinalNode::from_id_in(kb, b.id())))
+            .collect()
+    }
+
     /// Retrieve non-inherited attribute types that are introduced by this archetype to all
     /// descendant archetypes. Attribute types introduced by an ancestor do not count.
     fn added_attributes(&self) -> Vec<AttributeArchetype> {
         self.base_wrapper()
             .outgoing_nodes(HasAttribute::TYPE_ID)
             .into_iter()
-            .filter(|n| !Form::from(n.id()).is_individual())
-            .map(|n| AttributeArchetype::from(n.id()))
+            .map(FinalNode::from)
+            .filter(|n| !Form::from(*n).is_individual())
+            .map(AttributeArchetype::from)
             .collect()
     }
 
@@ -122,17 +195,28 @@ pub trait ArchetypeFormTrait:
     /// Given the lack of a conventional antonym to "meta", this uses
     /// "[mesa](https://www.gwiznlp.com/wp-content/uploads/2014/08/Whats-the-opposite-of-meta.pdf)"
     /// as a proposed antonym.
+    ///
+    /// If this meta-perspective is shared by unrelated archetypes, the conflict is settled by the
+    /// order of this meta's `linearization`; use `try_mesa_archetype` to find out about such
+    /// conflicts instead.
     fn mesa_archetype(&self) -> Archetype {
         // todo: this is an archetype-specific attribute. There should therefore be an archetype
         // for archetypes
         Archetype::from(
-            self.incoming_nodes(MetaForm::TYPE_ID)
-                .last()
-                .unwrap_or(&FinalNode::from(Archetype::TYPE_ID))
-                .id(),
+            resolve(self.deref(), MetaForm::TYPE_ID, Direction::Incoming)
+                .unwrap_or_else(|| FinalNode::from_id_in(self.kb(), Archetype::TYPE_ID)),
         )
     }
 
+    /// Fallible version of `mesa_archetype`, which reports unrelated archetypes that share this
+    /// meta-perspective.
+    fn try_mesa_archetype(&self) -> SchemaResult<Archetype> {
+        Ok(Archetype::from(
+            try_resolve(self.deref(), MetaForm::TYPE_ID, Direction::Incoming)?
+                .unwrap_or_else(|| FinalNode::from_id_in(self.kb(), Archetype::TYPE_ID)),
+        ))
+    }
+
     /// Get all the types of flags that this type of concept is predefined to potentially have.
     fn flags(&self) -> Vec<Archetype> {
         self.outgoing_nodes(HasFlag::TYPE_ID)
@@ -157,7 +241,7 @@ pub trait ArchetypeFormTrait:
         self.base_wrapper()
             .outgoing_nodes(HasFlag::TYPE_ID)
             .into_iter()
-            .map(|n| Archetype::from(n.id()))
+            .map(|n| Archetype::from(FinalNode::from(n)))
             .collect()
     }
 }
@@ -202,6 +286,18 @@ mod tests {
         assert_eq!(childless_type.individuals(), Vec::<Form>::new())
     }
 
+    #[test]
+    fn test_iter_individuals_short_circuit() {
+        initialize_kb();
+        let type1 = Form::archetype().individuate_as_archetype();
+        let type2 = type1.individuate_as_archetype();
+        let type1_instance = type1.individuate_as_form();
+        let type2_instance = type2.individuate_as_form();
+        assert!(type1.iter_individuals().any(|i| i == type2_instance));
+        assert_eq!(type1.iter_individuals().next(), Some(type1_instance));
+        assert_eq!(type2.iter_individuals().count(), 1);
+    }
+
     #[test]
     fn test_child_archetypes() {
         initialize_kb();
@@ -370,4 +466,43 @@ mod tests {
         assert_eq!(form_type.flags(), vec![flag_type]);
         assert_eq!(form_type.added_flags(), vec![flag_type]);
     }
+
+    #[test]
+    fn test_set_default_attribute() {
+        initialize_kb();
+        let language = Attribute::archetype().individuate_as_archetype();
+        let rust = Form::new();
+        let mut program = Form::archetype().individuate_as_archetype();
+        program.set_default_attribute(&language, &rust);
+        assert!(program.has_attribute(&language));
+        assert_eq!(
+            program.individuate_as_form().attribute_values(&language),
+            vec![rust]
+        );
+        // the archetype itself is not a program written in Rust
+        assert_eq!(program.attribute_values(&language), vec![]);
+        assert_eq!(program.owned_attributes(), vec![]);
+        assert_eq!(program.default_attributes()[0].owner(), None);
+
+        // setting the archetype's own value leaves the default alone, and vice versa
+        let english = Form::new();
+        program.set_attribute(&language, &english);
+        let python = Form::new();
+        program.set_default_attribute(&language, &python);
+        assert_eq!(program.attribute_values(&language), vec![english]);
+        assert_eq!(program.default_attributes().len(), 1);
+        assert_eq!(
+            program.individuate_as_form().attribute_values(&language),
+            vec![python]
+        );
+
+        // defaults for more specific attribute types are kept apart as well
+        let dialect = language.individuate_as_archetype();
+        program.set_default_attribute(&dialect, &english);
+        assert_eq!(program.default_attributes().len(), 2);
+        assert_eq!(
+            program.individuate_as_form().attribute_values(&dialect),
+            vec![english]
+        );
+    }
 }
@@ -27,9 +27,16 @@ pub trait ArchetypeTrait:
     const PARENT_TYPE_ID: usize; // todo: deprecate this in favor of manual parent link
                                  // initialization
 
+    /// The ID of this archetype in the current KB. This is just `TYPE_ID` for archetypes with IDs
+    /// fixed at compile time. Archetypes that get their IDs allocated at runtime by
+    /// `register_type` should look them up with `registered_type_id` instead.
+    fn archetype_id() -> usize {
+        Self::TYPE_ID
+    }
+
     /// The incarnation of this archetype as a form.
     fn archetype() -> Self::ArchetypeForm {
-        Self::ArchetypeForm::from(Self::TYPE_ID)
+        Self::ArchetypeForm::from(Self::archetype_id())
     }
 
     /// In the beginning was the Oneness, and the Oneness was nothingness.
@@ -47,7 +54,7 @@ pub trait ArchetypeTrait:
     /// Self and Other. The time has come to stroke the ego, to stand out from the rest of the
     /// world as a unique individual engaging in the act of self-realization.
     fn new() -> Self::Form {
-        let result = Self::Form::from(FinalNode::new_with_inheritance(Self::TYPE_ID));
+        let result = Self::Form::from(FinalNode::new_with_inheritance(Self::archetype_id()));
         // todo: require FormExtension on Self::Form after it's implemented everywhere
         Form::from(result.id()).mark_individual();
         result
@@ -1,10 +1,11 @@
 use super::{Archetype, AttributeArchetype};
-use crate::node_wrappers::{BaseNodeTrait, FinalNode};
+use crate::node_wrappers::{BaseNodeTrait, CommonNodeTrait, FinalNode};
 use crate::tao::archetype::ArchetypeTrait;
 use crate::tao::form::FormTrait;
+use crate::tao::inheritance::{resolve, try_resolve, Direction};
 use crate::tao::relation::attribute::{OwnerArchetype, ValueArchetype};
 use crate::tao::relation::flag::{Meta, MultiValued, Nonhereditary};
-use crate::tao::Tao;
+use crate::tao::{SchemaResult, Tao};
 use std::ops::{Deref, DerefMut};
 
 /// Archetype functionality that is specific to attribute archetypes.
@@ -16,44 +17,55 @@ pub trait AttributeArchetypeFormTrait:
         self.add_outgoing(OwnerArchetype::TYPE_ID, &owner_archetype);
     }
 
-    /// Retrieve the owner type for this type of attribute.
+    /// Retrieve the owner type for this type of attribute. This is the owner type set on the most
+    /// specific ancestor that has one.
+    ///
+    /// If unrelated ancestors disagree on what the owner type should be, the one that comes first
+    /// in `linearization` wins; use `try_owner_archetype` to find out about such conflicts instead.
     fn owner_archetype(&self) -> Archetype {
-        // outgoing nodes are sorted by ID, and more specific nodes are created later, resulting in
-        // higher IDs
-        //
-        // todo: implement this properly, because this assumption forces Tao to be defined before
-        // everything else in Yin.md, because documentation is tied to the creation of an
-        // implementation.
         Archetype::from(
-            *self
-                .outgoing_nodes(OwnerArchetype::TYPE_ID)
-                .last()
-                .unwrap_or(&FinalNode::from(Tao::TYPE_ID)),
+            resolve(self.deref(), OwnerArchetype::TYPE_ID, Direction::Outgoing)
+                .unwrap_or_else(|| FinalNode::from_id_in(self.kb(), Tao::TYPE_ID)),
         )
     }
 
+    /// Fallible version of `owner_archetype`, which reports unrelated ancestors that disagree on
+    /// the owner type.
+    fn try_owner_archetype(&self) -> SchemaResult<Archetype> {
+        Ok(Archetype::from(
+            try_resolve(self.deref(), OwnerArchetype::TYPE_ID, Direction::Outgoing)?
+                .unwrap_or_else(|| FinalNode::from_id_in(self.kb(), Tao::TYPE_ID)),
+        ))
+    }
+
     /// Restrict the values for this type of attribute.
     fn set_value_archetype(&mut self, value_archetype: &Archetype) {
         self.add_outgoing(ValueArchetype::TYPE_ID, &value_archetype);
     }
 
-    /// Retrieve the value type for this type of attribute.
+    /// Retrieve the value type for this type of attribute. This is the value type set on the most
+    /// specific ancestor that has one.
+    ///
+    /// If unrelated ancestors disagree on what the value type should be, the one that comes first
+    /// in `linearization` wins; use `try_value_archetype` to find out about such conflicts instead.
     fn value_archetype(&self) -> Archetype {
-        // outgoing nodes are sorted by ID, and more specific nodes are created later, resulting in
-        // higher IDs
-        //
-        // todo: implement this properly, because this assumption forces Tao to be defined before
-        // everything else in Yin.md, because documentation is tied to the creation of an
-        // implementation.
         Archetype::from(
-            *self
-                .outgoing_nodes(ValueArchetype::TYPE_ID)
-                .last()
-                .unwrap_or(&FinalNode::from(Tao::TYPE_ID)),
+            resolve(self.deref(), ValueArchetype::TYPE_ID, Direction::Outgoing)
+                .unwrap_or_else(|| FinalNode::from_id_in(self.kb(), Tao::TYPE_ID)),
         )
     }
 
-    /// Mark this attribute as non-hereditary.
+    /// Fallible version of `value_archetype`, which reports unrelated ancestors that disagree on
+    /// the value type.
+    fn try_value_archetype(&self) -> SchemaResult<Archetype> {
+        Ok(Archetype::from(
+            try_resolve(self.deref(), ValueArchetype::TYPE_ID, Direction::Outgoing)?
+                .unwrap_or_else(|| FinalNode::from_id_in(self.kb(), Tao::TYPE_ID)),
+        ))
+    }
+
+    /// Mark this attribute as non-hereditary. Attributes of this type that are set on an archetype
+    /// will then only apply to that archetype, and not to any of its descendants.
     fn mark_nonhereditary_attr(&mut self) {
         self.add_flag(Nonhereditary::TYPE_ID);
     }
@@ -91,8 +103,9 @@ mod tests {
     use super::*;
     use crate::node_wrappers::CommonNodeTrait;
     use crate::tao::archetype::{ArchetypeFormTrait, ArchetypeTrait};
-    use crate::tao::initialize_kb;
+    use crate::tao::form::FormTrait;
     use crate::tao::relation::attribute::Attribute;
+    use crate::tao::{initialize_kb, SchemaError};
 
     #[test]
     fn test_overriding_owner_archetype() {
@@ -118,6 +131,64 @@ mod tests {
         assert_eq!(attr_type2.value_archetype(), Attribute::archetype().into());
     }
 
+    #[test]
+    fn test_owner_archetype_regardless_of_id() {
+        initialize_kb();
+        // the child gets created, and restricted, before its parent is
+        let mut child = Attribute::archetype().individuate_as_archetype();
+        child.set_owner_archetype(&Attribute::archetype().into());
+        let mut parent = Attribute::archetype().individuate_as_archetype();
+        parent.set_owner_archetype(&Tao::archetype().individuate_as_archetype());
+        child.add_parent(parent.into());
+        assert_eq!(child.owner_archetype(), Attribute::archetype().into());
+        assert_eq!(
+            child.try_owner_archetype(),
+            Ok(Attribute::archetype().into())
+        );
+    }
+
+    #[test]
+    fn test_conflicting_value_archetypes() {
+        initialize_kb();
+        let mut parent1 = Attribute::archetype().individuate_as_archetype();
+        let mut parent2 = Attribute::archetype().individuate_as_archetype();
+        let value1 = Tao::archetype().individuate_as_archetype();
+        let value2 = Tao::archetype().individuate_as_archetype();
+        parent1.set_value_archetype(&value1);
+        parent2.set_value_archetype(&value2);
+        let mut child = parent1.individuate_as_archetype();
+        child.add_parent(parent2.into());
+        assert_eq!(
+            child.try_value_archetype(),
+            Err(SchemaError::ConflictingInheritance {
+                form: child.as_form(),
+                attribute: ValueArchetype::archetype().into(),
+                first: value1.as_form(),
+                second: value2.as_form(),
+            })
+        );
+        assert_eq!(child.value_archetype(), value1);
+
+        // a more specific restriction settles the conflict
+        child.set_value_archetype(&value1);
+        assert_eq!(child.try_value_archetype(), Ok(value1));
+    }
+
+    #[test]
+    fn test_conflicting_owner_archetypes_resolved() {
+        initialize_kb();
+        let mut parent1 = Attribute::archetype().individuate_as_archetype();
+        let mut parent2 = Attribute::archetype().individuate_as_archetype();
+        let owner1 = Tao::archetype().individuate_as_archetype();
+        parent1.set_owner_archetype(&owner1);
+        parent2.set_owner_archetype(&Tao::archetype().individuate_as_archetype());
+        let mut child = parent1.individuate_as_archetype();
+        child.add_parent(parent2.into());
+        // settled the same way as conflicting meta-perspectives are
+        assert_eq!(child.owner_archetype(), owner1);
+        assert!(child.try_owner_archetype().is_err());
+    }
+
     #[test]
     fn test_default_owner_value_archetypes() {
         initialize_kb();
@@ -139,6 +210,20 @@ mod tests {
         assert!(new_type.is_nonhereditary_attr());
     }
 
+    #[test]
+    fn test_nonhereditary_attr_not_inherited() {
+        initialize_kb();
+        let mut doc_link = Attribute::archetype().individuate_as_archetype();
+        doc_link.mark_nonhereditary_attr();
+        let mut parent = Tao::archetype().individuate_as_archetype();
+        let child = parent.individuate_as_archetype();
+        let docs = Tao::archetype().individuate_as_form();
+        parent.add_outgoing(doc_link.id(), &docs);
+        assert!(parent.has_outgoing(doc_link.id(), &docs));
+        assert!(!child.has_outgoing(doc_link.id(), &docs));
+        assert_eq!(child.outgoing_nodes(doc_link.id()), vec![]);
+    }
+
     #[test]
     fn test_new_meta() {
         initialize_kb();
@@ -1,9 +1,19 @@
 use super::Form;
-use crate::node_wrappers::{BaseNodeTrait, CommonNodeTrait, FinalNode, InheritanceNodeTrait};
-use crate::tao::archetype::{Archetype, ArchetypeFormTrait, ArchetypeTrait};
-use crate::tao::relation::attribute::{Inherits, MetaForm};
+use crate::graph::value_wrappers::{downcast_value, KBRc, StrongValue};
+use crate::graph::{expect_graph, Graph, GraphResult};
+use crate::node_wrappers::{
+    BaseNodeTrait, CommonNodeTrait, Explanation, FinalNode, InheritanceNodeTrait,
+};
+use crate::tao::archetype::{
+    Archetype, ArchetypeFormTrait, ArchetypeTrait, AttributeArchetype, AttributeArchetypeFormTrait,
+};
+use crate::tao::inheritance::{negation_of, resolve, try_resolve, Direction};
+use crate::tao::relation::attribute::{
+    Attribute, AttributeTrait, Description, Inherits, MetaForm, Owner,
+};
 use crate::tao::relation::flag::IsIndividual;
-use crate::tao::Tao;
+use crate::tao::schema::{check_owner, check_value, expect_schema, is_validating_schema};
+use crate::tao::{SchemaError, SchemaResult, Tao};
 use std::collections::{HashMap, VecDeque};
 use std::ops::{Deref, DerefMut};
 
@@ -15,18 +25,49 @@ pub trait FormTrait: Deref<Target = FinalNode> + DerefMut + std::fmt::Debug + Ar
     /// non-existence, there is no form or abstraction. Forget all preconceptions, blur all
     /// boundaries, be at peace with the universe again.
     fn ego_death(&self) -> Tao {
-        Tao::from(self.id())
+        Tao::from(*self.deref())
     }
 
     /// A less severe form of ego-death, where you still remember that you exist.
     fn as_form(&self) -> Form {
-        Form::from(self.id())
+        Form::from(*self.deref())
     }
 
     /// Set a parent archetype. The current archetype will inherit all attributes of the parent
     /// archetype.
+    ///
+    /// This will panic if the parent already inherits from the current archetype, because the two
+    /// would then end up inheriting from each other.
     fn add_parent(&mut self, parent: Archetype) {
+        expect_schema(self.try_add_parent(parent))
+    }
+
+    /// Fallible version of `add_parent`, which refuses to add a parent that already inherits from
+    /// the current archetype. Making an archetype its own parent is still allowed, because
+    /// `parents` ignores such links anyway.
+    fn try_add_parent(&mut self, parent: Archetype) -> SchemaResult<()> {
+        if parent.id() != self.id() && parent.has_ancestor(Archetype::from(**self)) {
+            return Err(SchemaError::InheritanceCycle {
+                form: self.as_form(),
+                parent,
+            });
+        }
         self.add_outgoing(Inherits::TYPE_ID, &parent);
+        Ok(())
+    }
+
+    /// Cancel a flag that this form would otherwise inherit from its ancestors. Descendants of this
+    /// form won't inherit the flag either, unless one of them sets it again.
+    fn cancel_flag(&mut self, flag_type: usize) {
+        let negation = negation_of(self.kb(), flag_type);
+        self.add_flag(negation.id());
+    }
+
+    /// Cancel a link to another node that this form would otherwise inherit from its ancestors.
+    /// Descendants of this form won't inherit the link either, unless one of them adds it again.
+    fn cancel_outgoing(&mut self, edge_type: usize, to: &FinalNode) {
+        let negation = negation_of(self.kb(), edge_type);
+        self.add_outgoing(negation.id(), to);
     }
 
     /// Whether this represents an individual.
@@ -34,6 +75,11 @@ pub trait FormTrait: Deref<Target = FinalNode> + DerefMut + std::fmt::Debug + Ar
         self.has_flag(IsIndividual::TYPE_ID)
     }
 
+    /// Fallible version of `is_individual`.
+    fn try_is_individual(&self) -> GraphResult<bool> {
+        self.try_has_flag(IsIndividual::TYPE_ID)
+    }
+
     /// Get all direct parent archetypes of this concept.
     fn parents(&self) -> Vec<Archetype> {
         let direct_parents: Vec<Archetype> = self
@@ -43,15 +89,44 @@ pub trait FormTrait: Deref<Target = FinalNode> + DerefMut + std::fmt::Debug + Ar
             .map(Archetype::from)
             .filter(|a| !a.is_individual())
             .collect();
-        let mut specific_parents = Vec::<Archetype>::new();
+        // look up each parent's ancestors only once, instead of once per comparison
+        let mut specific_parents = Vec::<(Archetype, Vec<FinalNode>)>::new();
         for parent in direct_parents {
-            if specific_parents.iter().any(|sp| sp.has_ancestor(parent)) {
+            if specific_parents
+                .iter()
+                .any(|(_, ancestors)| ancestors.contains(&parent))
+            {
                 continue; // this is not the most specific parent
             }
-            specific_parents.retain(|sp| !parent.has_ancestor(*sp));
-            specific_parents.push(parent);
+            let ancestors = parent.inheritance_nodes();
+            specific_parents.retain(|(sp, _)| !ancestors.contains(sp));
+            specific_parents.push((parent, ancestors));
+        }
+        specific_parents.into_iter().map(|(sp, _)| sp).collect()
+    }
+
+    /// This form followed by all of its ancestors, in the order in which they take precedence when
+    /// they set conflicting flags or attributes. This is the C3 method resolution order: every
+    /// form comes before its own ancestors, and the parents of every form come in the order they
+    /// were added in.
+    ///
+    /// This will panic if there is no order that satisfies both constraints.
+    fn linearization(&self) -> Vec<Form> {
+        expect_schema(self.try_linearization())
+    }
+
+    /// Fallible version of `linearization`, which returns an error if there is no order that
+    /// satisfies both constraints.
+    fn try_linearization(&self) -> SchemaResult<Vec<Form>> {
+        match expect_graph(self.inheritance_wrapper().try_linearization()) {
+            Some(order) => Ok(order
+                .into_iter()
+                .map(|iw| Form::from(FinalNode::from(iw)))
+                .collect()),
+            None => Err(SchemaError::InconsistentLinearization {
+                form: self.as_form(),
+            }),
         }
-        specific_parents
     }
 
     /// Get the shortest chain of ancestors that leads back to Tao, starting with Tao itself.
@@ -75,10 +150,10 @@ pub trait FormTrait: Deref<Target = FinalNode> + DerefMut + std::fmt::Debug + Ar
         }
 
         let mut ancestry = Vec::new();
-        let mut next_node = Tao::archetype().as_form();
+        let mut next_node = Form::from(FinalNode::from_id_in(self.kb(), Tao::TYPE_ID));
         let selfless_ego = self.as_form();
         while next_node != selfless_ego {
-            ancestry.push(Archetype::from(next_node.id()));
+            ancestry.push(Archetype::from(*next_node));
             next_node = *backpointers.get(&next_node).unwrap();
         }
         ancestry
@@ -96,33 +171,75 @@ pub trait FormTrait: Deref<Target = FinalNode> + DerefMut + std::fmt::Debug + Ar
         self.inheritance_nodes().contains(&possible_ancestor)
     }
 
+    /// Fallible version of `has_ancestor`.
+    fn try_has_ancestor(&self, possible_ancestor: Archetype) -> GraphResult<bool> {
+        Ok(self.try_inheritance_nodes()?.contains(&possible_ancestor))
+    }
+
+    /// Why this form has the given ancestor, or `None` if it doesn't. The explanation traces the
+    /// chain of parents leading up to the ancestor.
+    fn explain_ancestor(&self, possible_ancestor: Archetype) -> Option<Explanation<Form>> {
+        expect_graph(self.try_explain_ancestor(possible_ancestor))
+    }
+
+    /// Fallible version of `explain_ancestor`.
+    fn try_explain_ancestor(
+        &self,
+        possible_ancestor: Archetype,
+    ) -> GraphResult<Option<Explanation<Form>>> {
+        let chain = self
+            .inheritance_wrapper()
+            .try_inheritance_chain(possible_ancestor.inheritance_wrapper())?;
+        Ok(chain.map(|chain| {
+            let chain: Vec<Form> = chain
+                .into_iter()
+                .map(|n| Form::from(FinalNode::from(n)))
+                .collect();
+            // the ancestor is either this form itself, or the parent of the one before it
+            let asserted_by = chain[chain.len().saturating_sub(2)];
+            Explanation { chain, asserted_by }
+        }))
+    }
+
     /// View the current node from its meta perspective.
     fn meta(&self) -> Self::ArchetypeForm {
-        Self::ArchetypeForm::from(self.id())
+        Self::ArchetypeForm::from(*self.deref())
     }
 
     /// Get the node representing the current node's meta-perspective.
     ///
     /// This is in contrast to `self.meta()`, which views the current node *from* the
     /// meta-perspective.
+    ///
+    /// If unrelated ancestors have different meta-perspectives, the one that comes first in
+    /// `linearization` wins; use `try_meta_archetype` to find out about such conflicts instead.
     fn meta_archetype(&self) -> Archetype {
-        // same assumption as in attribute archetype form trait about ID and specificity
         Archetype::from(
-            self.outgoing_nodes(MetaForm::TYPE_ID)
-                .last()
-                .unwrap_or(&FinalNode::from(Archetype::TYPE_ID))
-                .id(),
+            resolve(self.deref(), MetaForm::TYPE_ID, Direction::Outgoing)
+                .unwrap_or_else(|| FinalNode::from_id_in(self.kb(), Archetype::TYPE_ID)),
         )
     }
 
+    /// Fallible version of `meta_archetype`, which reports unrelated ancestors that disagree on
+    /// the meta-perspective.
+    fn try_meta_archetype(&self) -> SchemaResult<Archetype> {
+        Ok(Archetype::from(
+            try_resolve(self.deref(), MetaForm::TYPE_ID, Direction::Outgoing)?
+                .unwrap_or_else(|| FinalNode::from_id_in(self.kb(), Archetype::TYPE_ID)),
+        ))
+    }
+
     /// Grab the meta-perspective that's specific to the current type. If it doesn't exist yet,
     /// then it will be created.
+    ///
+    /// Creating the meta-perspective takes several steps, which are done within a single
+    /// transaction so that the KB is never left with a half-defined meta.
     fn specific_meta(&mut self) -> Archetype {
         // there should only be one of these
         let uninherited_metas = self.base_wrapper().outgoing_nodes(MetaForm::TYPE_ID);
         match uninherited_metas.last() {
-            Some(specific_meta) => Archetype::from(specific_meta.id()),
-            None => {
+            Some(specific_meta) => Archetype::from(FinalNode::from(*specific_meta)),
+            None => self.kb().graph().transaction(|| {
                 // grabbing parent metas first so that they get created first and the
                 // greater-ID-greater-specificity assumption still holds
                 let mut parent_metas = Vec::<Archetype>::new();
@@ -132,13 +249,15 @@ pub trait FormTrait: Deref<Target = FinalNode> + DerefMut + std::fmt::Debug + Ar
                     // still inherit from the parent
                     parent_metas.push(parent.specific_meta());
                 }
-                let mut new_meta = Archetype::archetype().individuate_as_archetype();
+                let mut new_meta =
+                    Archetype::from(FinalNode::from_id_in(self.kb(), Archetype::TYPE_ID))
+                        .individuate_as_archetype();
                 for parent_meta in parent_metas {
                     new_meta.add_parent(parent_meta);
                 }
                 self.set_meta_archetype(&new_meta);
                 new_meta
-            }
+            }),
         }
     }
 
@@ -160,12 +279,144 @@ pub trait FormTrait: Deref<Target = FinalNode> + DerefMut + std::fmt::Debug + Ar
     fn mark_individual(&mut self) {
         self.add_flag(IsIndividual::TYPE_ID);
     }
+
+    /// Give this form an attribute of the given type, with the given value. The attribute is
+    /// stored as an attribute node owned by this form, which gets created the first time an
+    /// attribute of this type is set. After that, single-valued attributes have their value
+    /// replaced, while multi-valued attributes have the new value added to the existing ones. Only
+    /// attributes of exactly the given type get updated, so setting a general attribute type
+    /// leaves attributes of its more specific subtypes alone.
+    ///
+    /// If schema validation is on for this form's KB, this will panic if the form or the value
+    /// don't fit the attribute type's owner and value archetypes.
+    fn set_attribute(&mut self, attribute_type: &AttributeArchetype, value: &Form) -> Attribute {
+        expect_schema(self.try_set_attribute(attribute_type, value))
+    }
+
+    /// Fallible version of `set_attribute`, which leaves the form untouched instead of panicking
+    /// if schema validation is on and the form or the value don't fit the attribute type.
+    fn try_set_attribute(
+        &mut self,
+        attribute_type: &AttributeArchetype,
+        value: &Form,
+    ) -> SchemaResult<Attribute> {
+        let existing = self
+            .owned_attributes()
+            .into_iter()
+            .find(|a| a.parents().contains(&Archetype::from(*attribute_type)));
+        if let Some(mut attribute) = existing {
+            attribute.try_set_value(value)?;
+            return Ok(attribute);
+        }
+
+        if is_validating_schema(self.kb()) {
+            check_owner(attribute_type.deref(), self.deref())?;
+            check_value(attribute_type.deref(), value.deref())?;
+        }
+        let owner = self.as_form();
+        Ok(self.kb().graph().transaction(|| {
+            let mut attribute = attribute_type.individuate_as_form();
+            attribute.set_owner(&owner);
+            attribute.set_value(value);
+            attribute
+        }))
+    }
+
+    /// All values of the attributes of the given type that this form owns, in the order the
+    /// attributes were created in. Attributes whose type descends from the given one count too.
+    ///
+    /// If this form doesn't own any such attributes, it falls back on the defaults set with
+    /// `set_default_attribute` on the nearest ancestor that has any, going by `linearization`
+    /// order. Defaults set on the form itself don't count, and nonhereditary attribute types have
+    /// no defaults at all.
+    ///
+    /// This will panic if defaults need to be looked up but there is no `linearization` to look
+    /// them up by.
+    fn attribute_values(&self, attribute_type: &AttributeArchetype) -> Vec<Form> {
+        expect_schema(self.try_attribute_values(attribute_type))
+    }
+
+    /// Fallible version of `attribute_values`, which returns an error if defaults need to be
+    /// looked up but there is no `linearization` to look them up by.
+    fn try_attribute_values(&self, attribute_type: &AttributeArchetype) -> SchemaResult<Vec<Form>> {
+        let own_values = owned_values(&self.as_form(), attribute_type);
+        if !own_values.is_empty() || attribute_type.is_nonhereditary_attr() {
+            return Ok(own_values);
+        }
+        Ok(self
+            .try_linearization()?
+            .into_iter()
+            .skip(1)
+            .map(|ancestor| default_values(&ancestor, attribute_type))
+            .find(|values| !values.is_empty())
+            .unwrap_or_default())
+    }
+
+    /// Describe this form for the benefit of humans, replacing any description it had before.
+    /// The node holding the old text is deleted along with it.
+    fn set_description(&mut self, description: &str) {
+        let kb = self.kb();
+        let description_type =
+            AttributeArchetype::from(FinalNode::from_id_in(kb, Description::TYPE_ID));
+        let old_texts = owned_values(&self.as_form(), &description_type);
+        kb.graph().transaction(|| {
+            let mut text = FinalNode::new_in(kb);
+            text.set_value(KBRc::new(StrongValue::new(description.to_owned())));
+            self.set_attribute(&description_type, &Form::from(text));
+            let mut graph = kb.graph();
+            for old_text in old_texts {
+                graph.remove_node(old_text.id());
+            }
+        });
+    }
+
+    /// The human-readable description of this form, if it has one. Descriptions are
+    /// nonhereditary, because what describes an ancestor does not describe its descendants.
+    fn description(&self) -> Option<String> {
+        let description_type =
+            AttributeArchetype::from(FinalNode::from_id_in(self.kb(), Description::TYPE_ID));
+        let text = self.attribute_values(&description_type).into_iter().next()?;
+        text.value()
+            .and_then(|v| downcast_value::<String>(&*v))
+            .map(|s| (*s).clone())
+    }
+
+    /// All attribute nodes that have been set on this form itself as their owner.
+    fn owned_attributes(&self) -> Vec<Attribute> {
+        let kb = self.kb();
+        self.base_wrapper()
+            .incoming_nodes(Owner::TYPE_ID)
+            .into_iter()
+            .map(|b| Attribute::from(FinalNode::from_id_in(kb, b.id())))
+            .collect()
+    }
+}
+
+/// Values of the attributes of the given type that have been set on the form itself.
+fn owned_values(form: &Form, attribute_type: &AttributeArchetype) -> Vec<Form> {
+    form.owned_attributes()
+        .into_iter()
+        .filter(|a| a.has_ancestor(Archetype::from(*attribute_type)))
+        .flat_map(|a| a.values())
+        .collect()
+}
+
+/// Default values of the given attribute type that have been set on the form itself, as an
+/// archetype for its descendants.
+fn default_values(form: &Form, attribute_type: &AttributeArchetype) -> Vec<Form> {
+    Archetype::from(*form.deref())
+        .default_attributes()
+        .into_iter()
+        .filter(|a| a.has_ancestor(Archetype::from(*attribute_type)))
+        .flat_map(|a| a.values())
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::tao::archetype::{Archetype, ArchetypeFormTrait};
+    use crate::graph::{GraphError, InjectionGraph};
+    use crate::tao::archetype::{Archetype, ArchetypeFormTrait, AttributeArchetypeFormTrait};
     use crate::tao::initialize_kb;
     use crate::tao::relation::attribute::{Attribute, Owner, Value};
 
@@ -295,6 +546,22 @@ mod tests {
         assert!(!owner.has_ancestor(Value::archetype().into()));
     }
 
+    #[test]
+    fn test_try_has_ancestor_stale() {
+        initialize_kb();
+        let mut g = InjectionGraph::new();
+        let stale = Form::new();
+        g.remove_node(stale.id());
+        assert_eq!(
+            stale.try_has_ancestor(Form::archetype()),
+            Err(GraphError::UnknownNode(stale.id()))
+        );
+        assert_eq!(
+            stale.try_is_individual(),
+            Err(GraphError::UnknownNode(stale.id()))
+        );
+    }
+
     #[test]
     fn test_form_meta_set() {
         initialize_kb();
@@ -308,6 +575,19 @@ mod tests {
         assert_eq!(form_indv.meta_archetype(), meta_type);
     }
 
+    #[test]
+    fn test_specific_meta_rollback() {
+        initialize_kb();
+        let mut g = InjectionGraph::new();
+        let mut form = Form::new();
+        let size = g.size();
+        g.begin_transaction();
+        form.specific_meta();
+        g.rollback();
+        assert!(!form.has_specific_meta());
+        assert_eq!(g.size(), size);
+    }
+
     #[test]
     fn test_form_meta_inheritance() {
         initialize_kb();
@@ -365,4 +645,332 @@ mod tests {
         // the meta-perspective for Owner (aka the AttributeArchetype perspective) does.
         assert!(new_attr.meta().attributes().contains(&Owner::archetype()));
     }
+
+    #[test]
+    fn test_cancel_flag() {
+        initialize_kb();
+        let flies = Form::archetype().individuate_as_archetype();
+        let mut bird = Form::archetype().individuate_as_archetype();
+        bird.deref_mut().add_flag(flies.id());
+        let mut penguin = bird.individuate_as_archetype();
+        let pingu = penguin.individuate_as_form();
+        let mut jetpack_penguin = penguin.individuate_as_archetype();
+        penguin.cancel_flag(flies.id());
+        jetpack_penguin.deref_mut().add_flag(flies.id());
+        let rocketeer = jetpack_penguin.individuate_as_form();
+
+        assert!(bird.deref().has_flag(flies.id()));
+        assert!(!penguin.deref().has_flag(flies.id()));
+        assert!(!pingu.has_flag(flies.id()));
+        assert!(jetpack_penguin.deref().has_flag(flies.id()));
+        assert!(rocketeer.has_flag(flies.id()));
+    }
+
+    #[test]
+    fn test_cancel_outgoing() {
+        initialize_kb();
+        let habitat = Attribute::archetype().individuate_as_archetype();
+        let sky = Form::new();
+        let sea = Form::new();
+        let mut bird = Form::archetype().individuate_as_archetype();
+        bird.add_outgoing(habitat.id(), &sky);
+        let mut penguin = bird.individuate_as_archetype();
+        penguin.add_outgoing(habitat.id(), &sea);
+        penguin.cancel_outgoing(habitat.id(), &sky);
+        let pingu = penguin.individuate_as_form();
+
+        assert_eq!(bird.outgoing_nodes(habitat.id()), vec![*sky]);
+        assert_eq!(pingu.outgoing_nodes(habitat.id()), vec![*sea]);
+        assert!(!pingu.has_outgoing(habitat.id(), &sky));
+        assert!(pingu.has_outgoing(habitat.id(), &sea));
+        assert_eq!(
+            pingu.iter_outgoing_nodes(habitat.id()).collect::<Vec<_>>(),
+            vec![*sea]
+        );
+    }
+
+    #[test]
+    fn test_cancel_flag_from_unrelated_ancestor() {
+        initialize_kb();
+        let flies = Form::archetype().individuate_as_archetype();
+        let mut bird = Form::archetype().individuate_as_archetype();
+        bird.deref_mut().add_flag(flies.id());
+        let mut flightless = Form::archetype().individuate_as_archetype();
+        flightless.cancel_flag(flies.id());
+        let mut ostrich = bird.individuate_as_archetype();
+        ostrich.add_parent(flightless);
+        // the negation does not apply to birds, which are not flightless
+        assert!(ostrich.deref().has_flag(flies.id()));
+    }
+
+    #[test]
+    fn test_linearization() {
+        initialize_kb();
+        let animal = Form::archetype().individuate_as_archetype();
+        let swimmer = animal.individuate_as_archetype();
+        let flier = animal.individuate_as_archetype();
+        let mut duck = flier.individuate_as_archetype();
+        duck.add_parent(swimmer);
+        // flier was added as a parent first, even though swimmer was created first
+        assert_eq!(
+            duck.linearization(),
+            vec![
+                duck.as_form(),
+                flier.as_form(),
+                swimmer.as_form(),
+                animal.as_form(),
+                Form::archetype().as_form(),
+                Tao::archetype().as_form(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inconsistent_linearization() {
+        initialize_kb();
+        let b = Form::archetype().individuate_as_archetype();
+        let a = Form::archetype().individuate_as_archetype();
+        let mut c = Form::archetype().individuate_as_archetype();
+        c.add_parent(b);
+        let mut p = a.individuate_as_archetype();
+        p.add_parent(c);
+        let mut q = b.individuate_as_archetype();
+        q.add_parent(a);
+        let mut z = p.individuate_as_archetype();
+        z.add_parent(q);
+        assert_eq!(
+            z.try_linearization(),
+            Err(SchemaError::InconsistentLinearization { form: z.as_form() })
+        );
+    }
+
+    #[test]
+    fn test_add_parent_cycle_refused() {
+        initialize_kb();
+        let grandparent = Form::archetype().individuate_as_archetype();
+        let parent = grandparent.individuate_as_archetype();
+        let mut child = parent.individuate_as_archetype();
+        let mut grandparent_form = Form::from(grandparent.id());
+        assert_eq!(
+            grandparent_form.try_add_parent(child),
+            Err(SchemaError::InheritanceCycle {
+                form: grandparent_form,
+                parent: child,
+            })
+        );
+        assert!(!grandparent.has_ancestor(child));
+        assert_eq!(child.try_add_parent(grandparent), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "which already inherits from it")]
+    fn test_add_parent_cycle_panics() {
+        initialize_kb();
+        let mut parent = Form::archetype().individuate_as_archetype();
+        let child = parent.individuate_as_archetype();
+        parent.add_parent(child);
+    }
+
+    #[test]
+    fn test_explain_ancestor() {
+        initialize_kb();
+        let animal = Form::archetype().individuate_as_archetype();
+        let bird = animal.individuate_as_archetype();
+        let penguin = bird.individuate_as_archetype();
+        let pingu = penguin.individuate_as_form();
+        assert_eq!(
+            pingu.explain_ancestor(animal),
+            Some(Explanation {
+                chain: vec![pingu, penguin.as_form(), bird.as_form(), animal.as_form()],
+                asserted_by: bird.as_form(),
+            })
+        );
+        assert_eq!(
+            pingu.explain_ancestor(Archetype::from(pingu.id())),
+            Some(Explanation {
+                chain: vec![pingu],
+                asserted_by: pingu,
+            })
+        );
+        assert_eq!(animal.explain_ancestor(bird), None);
+    }
+
+    #[test]
+    fn test_explain_inherited_flag() {
+        initialize_kb();
+        let flies = Form::archetype().individuate_as_archetype();
+        let mut bird = Form::archetype().individuate_as_archetype();
+        bird.deref_mut().add_flag(flies.id());
+        let tweety = bird.individuate_as_form();
+        assert_eq!(
+            tweety.explain_flag(flies.id()),
+            Some(Explanation {
+                chain: vec![*tweety, *bird.deref()],
+                asserted_by: *bird.deref(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_set_attribute() {
+        initialize_kb();
+        let language = Attribute::archetype().individuate_as_archetype();
+        let rust = Form::new();
+        let python = Form::new();
+        let mut program = Form::new();
+        let attribute = program.set_attribute(&language, &python);
+        assert_eq!(attribute.owner(), Some(program));
+        assert_eq!(program.set_attribute(&language, &rust), attribute);
+        assert_eq!(program.attribute_values(&language), vec![rust]);
+        assert_eq!(program.owned_attributes(), vec![attribute]);
+    }
+
+    #[test]
+    fn test_set_multi_valued_attribute() {
+        initialize_kb();
+        let mut author = Attribute::archetype().individuate_as_archetype();
+        author.mark_multi_valued_attr();
+        let alice = Form::new();
+        let bob = Form::new();
+        let mut book = Form::new();
+        book.set_attribute(&author, &alice);
+        book.set_attribute(&author, &bob);
+        assert_eq!(book.attribute_values(&author), vec![alice, bob]);
+        assert_eq!(book.owned_attributes().len(), 1);
+    }
+
+    #[test]
+    fn test_attribute_values_by_type() {
+        initialize_kb();
+        let name = Attribute::archetype().individuate_as_archetype();
+        let nickname = name.individuate_as_archetype();
+        let age = Attribute::archetype().individuate_as_archetype();
+        let bobby = Form::new();
+        let forty = Form::new();
+        let mut bob = Form::new();
+        bob.set_attribute(&nickname, &bobby);
+        bob.set_attribute(&age, &forty);
+        assert_eq!(bob.attribute_values(&name), vec![bobby]);
+        assert_eq!(bob.attribute_values(&nickname), vec![bobby]);
+        assert_eq!(bob.attribute_values(&age), vec![forty]);
+        assert_eq!(Form::new().attribute_values(&age), vec![]);
+
+        // setting the more general type doesn't overwrite the more specific one
+        let robert = Form::new();
+        bob.set_attribute(&name, &robert);
+        assert_eq!(bob.owned_attributes().len(), 3);
+        assert_eq!(bob.attribute_values(&name), vec![bobby, robert]);
+        assert_eq!(bob.attribute_values(&nickname), vec![bobby]);
+
+        // but it does update the attribute of exactly that type
+        let rob = Form::new();
+        bob.set_attribute(&name, &rob);
+        assert_eq!(bob.owned_attributes().len(), 3);
+        assert_eq!(bob.attribute_values(&name), vec![bobby, rob]);
+        assert_eq!(bob.attribute_values(&nickname), vec![bobby]);
+    }
+
+    #[test]
+    fn test_set_attribute_invalid_owner() {
+        initialize_kb();
+        let program_type = Form::archetype().individuate_as_archetype();
+        let mut language = Attribute::archetype().individuate_as_archetype();
+        language.set_owner_archetype(&program_type);
+        let rust = Form::new();
+        let mut essay = Form::new();
+        crate::tao::set_schema_validation(essay.kb(), true);
+        assert_eq!(
+            essay.try_set_attribute(&language, &rust),
+            Err(SchemaError::InvalidOwner {
+                relation: language.as_form(),
+                owner: essay,
+                expected: program_type,
+            })
+        );
+        assert_eq!(essay.owned_attributes(), vec![]);
+        crate::tao::set_schema_validation(essay.kb(), false);
+    }
+
+    #[test]
+    fn test_inherited_attribute_values() {
+        initialize_kb();
+        let language = Attribute::archetype().individuate_as_archetype();
+        let rust = Form::new();
+        let python = Form::new();
+        let mut program = Form::archetype().individuate_as_archetype();
+        program.set_default_attribute(&language, &rust);
+        let mut script = program.individuate_as_archetype();
+        let compiled = program.individuate_as_form();
+        let mut overridden = program.individuate_as_form();
+        overridden.set_attribute(&language, &python);
+
+        assert_eq!(compiled.attribute_values(&language), vec![rust]);
+        assert_eq!(overridden.attribute_values(&language), vec![python]);
+        assert_eq!(compiled.owned_attributes(), vec![]);
+
+        // the nearest ancestor wins
+        script.set_default_attribute(&language, &python);
+        let interpreted = script.individuate_as_form();
+        assert_eq!(interpreted.attribute_values(&language), vec![python]);
+    }
+
+    #[test]
+    fn test_attribute_defaults_without_linearization() {
+        initialize_kb();
+        let language = Attribute::archetype().individuate_as_archetype();
+        let rust = Form::new();
+        let mut b = Form::archetype().individuate_as_archetype();
+        b.set_default_attribute(&language, &rust);
+        let a = Form::archetype().individuate_as_archetype();
+        let mut c = Form::archetype().individuate_as_archetype();
+        c.add_parent(b);
+        let mut p = a.individuate_as_archetype();
+        p.add_parent(c);
+        let mut q = b.individuate_as_archetype();
+        q.add_parent(a);
+        let mut z = p.individuate_as_archetype();
+        z.add_parent(q);
+        assert_eq!(
+            z.try_attribute_values(&language),
+            Err(SchemaError::InconsistentLinearization { form: z.as_form() })
+        );
+        // own values don't need to be looked up anywhere else
+        z.set_attribute(&language, &rust);
+        assert_eq!(z.try_attribute_values(&language), Ok(vec![rust]));
+    }
+
+    #[test]
+    fn test_nonhereditary_attribute_has_no_default() {
+        initialize_kb();
+        let mut version = Attribute::archetype().individuate_as_archetype();
+        version.mark_nonhereditary_attr();
+        let one = Form::new();
+        let mut program = Form::archetype().individuate_as_archetype();
+        program.set_default_attribute(&version, &one);
+        assert_eq!(
+            program.individuate_as_form().attribute_values(&version),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_description() {
+        initialize_kb();
+        let mut bird = Form::archetype().individuate_as_archetype();
+        assert_eq!(bird.description(), None);
+        bird.set_description("Has feathers.");
+        let description_type = AttributeArchetype::from(Description::TYPE_ID);
+        let old_text = owned_values(&bird.as_form(), &description_type)[0];
+        bird.set_description("Has feathers and a beak.");
+        assert_eq!(
+            old_text.try_has_ancestor(Form::archetype()),
+            Err(GraphError::UnknownNode(old_text.id()))
+        );
+        assert_eq!(
+            bird.description().as_deref(),
+            Some("Has feathers and a beak.")
+        );
+        // descriptions of ancestors don't describe their descendants
+        assert_eq!(bird.individuate_as_archetype().description(), None);
+    }
 }
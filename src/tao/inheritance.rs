@@ -0,0 +1,292 @@
+use crate::graph::KnowledgeBase;
+use crate::node_wrappers::{BaseNodeTrait, CommonNodeTrait, FinalNode, InheritanceNodeTrait};
+use crate::tao::archetype::{Archetype, ArchetypeTrait};
+use crate::tao::form::{Form, FormTrait};
+use crate::tao::relation::attribute::Negation;
+use crate::tao::schema::expect_schema;
+use crate::tao::{SchemaError, SchemaResult};
+use std::collections::{BTreeSet, HashSet};
+
+/// Which way an attribute edge points, relative to the node whose attribute is being resolved.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Direction {
+    /// The node is the one the edge starts from.
+    Outgoing,
+    /// The node is the one the edge leads to.
+    Incoming,
+}
+
+/// The nodes on the other end of the edges of this type that `node` has set on itself, without
+/// inheriting any from its ancestors.
+fn own_links(node: &FinalNode, edge_type: usize, direction: Direction) -> Vec<FinalNode> {
+    let base = node.base_wrapper();
+    let links = match direction {
+        Direction::Outgoing => base.outgoing_nodes(edge_type),
+        Direction::Incoming => base.incoming_nodes(edge_type),
+    };
+    links
+        .into_iter()
+        .map(|b| FinalNode::from_id_in(node.kb(), b.id()))
+        .collect()
+}
+
+/// All values that `node` inherits for an attribute from its most specific ancestors, in order of
+/// ID. An ancestor that sets the attribute is overridden by any of its descendants that also set
+/// it, so there will only be more than one value here if unrelated ancestors disagree, or if the
+/// same ancestor was given several values.
+fn most_specific(node: &FinalNode, edge_type: usize, direction: Direction) -> Vec<FinalNode> {
+    // respects nonhereditary attributes, which only count when set on the node itself
+    let visible: HashSet<FinalNode> = match direction {
+        Direction::Outgoing => node.outgoing_nodes(edge_type),
+        Direction::Incoming => node.incoming_nodes(edge_type),
+    }
+    .into_iter()
+    .collect();
+    if visible.is_empty() {
+        return Vec::new();
+    }
+
+    let asserters: Vec<(FinalNode, Vec<FinalNode>)> = node
+        .inheritance_nodes()
+        .into_iter()
+        .map(|ancestor| {
+            let values = own_links(&ancestor, edge_type, direction)
+                .into_iter()
+                .filter(|v| visible.contains(v))
+                .collect::<Vec<_>>();
+            (ancestor, values)
+        })
+        .filter(|(_, values)| !values.is_empty())
+        .collect();
+    let asserter_ancestries: Vec<HashSet<FinalNode>> = asserters
+        .iter()
+        .map(|(asserter, _)| asserter.inheritance_nodes().into_iter().collect())
+        .collect();
+
+    let mut result = BTreeSet::new();
+    for (asserter, values) in &asserters {
+        let overridden = asserters
+            .iter()
+            .zip(&asserter_ancestries)
+            .any(|((other, _), ancestry)| other != asserter && ancestry.contains(asserter));
+        if !overridden {
+            result.extend(values.iter().copied());
+        }
+    }
+    result.into_iter().collect()
+}
+
+/// Resolve the value of a single-valued attribute for `node`, taking the value set on the most
+/// specific ancestor. Fails if unrelated ancestors disagree on what the value should be.
+pub(crate) fn try_resolve(
+    node: &FinalNode,
+    edge_type: usize,
+    direction: Direction,
+) -> SchemaResult<Option<FinalNode>> {
+    let candidates = most_specific(node, edge_type, direction);
+    match candidates.as_slice() {
+        [] => Ok(None),
+        [value] => Ok(Some(*value)),
+        [first, second, ..] => Err(SchemaError::ConflictingInheritance {
+            form: Form::from(*node),
+            attribute: Archetype::from(FinalNode::from_id_in(node.kb(), edge_type)),
+            first: Form::from(*first),
+            second: Form::from(*second),
+        }),
+    }
+}
+
+/// Like `try_resolve`, except that conflicts get resolved in favor of the value set on whichever
+/// ancestor comes first in the linearization of `node`. This will panic if there is a conflict
+/// but no consistent linearization to settle it with.
+pub(crate) fn resolve(
+    node: &FinalNode,
+    edge_type: usize,
+    direction: Direction,
+) -> Option<FinalNode> {
+    let candidates = most_specific(node, edge_type, direction);
+    if candidates.len() <= 1 {
+        return candidates.first().copied();
+    }
+    expect_schema(Form::from(*node).try_linearization())
+        .into_iter()
+        .find_map(|ancestor| {
+            own_links(&ancestor, edge_type, direction)
+                .into_iter()
+                .filter(|v| candidates.contains(v))
+                .max()
+        })
+}
+
+/// The type that negates a flag or attribute type, which gets created the first time it's needed.
+pub(crate) fn negation_of(kb: KnowledgeBase, type_id: usize) -> FinalNode {
+    let mut type_node = FinalNode::from_id_in(kb, type_id);
+    let existing = type_node.base_wrapper().outgoing_nodes(Negation::TYPE_ID);
+    match existing.first() {
+        Some(negation) => FinalNode::from_id_in(kb, negation.id()),
+        None => kb.graph().transaction(|| {
+            let mut negation = FinalNode::new_in(kb);
+            if let Some(name) = type_node.internal_name() {
+                negation.set_internal_name(&format!("not {}", name));
+            }
+            type_node.add_outgoing(Negation::TYPE_ID, &negation);
+            negation
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tao::archetype::{ArchetypeFormTrait, ArchetypeTrait};
+    use crate::tao::form::FormTrait;
+    use crate::tao::initialize_kb;
+    use crate::tao::relation::attribute::{Attribute, MetaForm};
+
+    #[test]
+    fn test_most_specific_regardless_of_id() {
+        initialize_kb();
+        let mut parent = Form::archetype().individuate_as_archetype();
+        let mut child = parent.individuate_as_archetype();
+        let child_value = Form::new();
+        let parent_value = Form::new();
+        child.add_outgoing(MetaForm::TYPE_ID, &child_value);
+        parent.add_outgoing(MetaForm::TYPE_ID, &parent_value);
+        assert_eq!(
+            try_resolve(&child, MetaForm::TYPE_ID, Direction::Outgoing),
+            Ok(Some(*child_value))
+        );
+        assert_eq!(
+            resolve(&parent, MetaForm::TYPE_ID, Direction::Outgoing),
+            Some(*parent_value)
+        );
+    }
+
+    #[test]
+    fn test_inherited_from_distant_ancestor() {
+        initialize_kb();
+        let mut grandparent = Form::archetype().individuate_as_archetype();
+        let child = grandparent
+            .individuate_as_archetype()
+            .individuate_as_archetype();
+        let value = Form::new();
+        grandparent.add_outgoing(MetaForm::TYPE_ID, &value);
+        assert_eq!(
+            try_resolve(&child, MetaForm::TYPE_ID, Direction::Outgoing),
+            Ok(Some(*value))
+        );
+        assert_eq!(
+            try_resolve(&value, MetaForm::TYPE_ID, Direction::Incoming),
+            Ok(Some(*grandparent))
+        );
+    }
+
+    #[test]
+    fn test_conflicting_ancestors() {
+        initialize_kb();
+        let mut parent1 = Form::archetype().individuate_as_archetype();
+        let mut parent2 = Form::archetype().individuate_as_archetype();
+        let mut child = parent1.individuate_as_archetype();
+        child.add_parent(parent2);
+        let value1 = Form::new();
+        let value2 = Form::new();
+        parent1.add_outgoing(MetaForm::TYPE_ID, &value1);
+        parent2.add_outgoing(MetaForm::TYPE_ID, &value2);
+        assert_eq!(
+            try_resolve(&child, MetaForm::TYPE_ID, Direction::Outgoing),
+            Err(SchemaError::ConflictingInheritance {
+                form: child.as_form(),
+                attribute: Archetype::from(MetaForm::TYPE_ID),
+                first: value1,
+                second: value2,
+            })
+        );
+        // parent1 comes first in the linearization, because it was added first
+        assert_eq!(
+            resolve(&child, MetaForm::TYPE_ID, Direction::Outgoing),
+            Some(*value1)
+        );
+    }
+
+    #[test]
+    fn test_newer_parent_added_first() {
+        initialize_kb();
+        let mut parent1 = Form::archetype().individuate_as_archetype();
+        let mut parent2 = Form::archetype().individuate_as_archetype();
+        let mut child = parent2.individuate_as_archetype();
+        child.add_parent(parent1);
+        let value1 = Form::new();
+        let value2 = Form::new();
+        parent1.add_outgoing(MetaForm::TYPE_ID, &value1);
+        parent2.add_outgoing(MetaForm::TYPE_ID, &value2);
+        assert!(parent2.id() > parent1.id());
+        assert_eq!(
+            resolve(&child, MetaForm::TYPE_ID, Direction::Outgoing),
+            Some(*value2)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "have no consistent order of precedence")]
+    fn test_conflict_without_linearization() {
+        initialize_kb();
+        let mut b = Form::archetype().individuate_as_archetype();
+        let mut a = Form::archetype().individuate_as_archetype();
+        let mut c = Form::archetype().individuate_as_archetype();
+        c.add_parent(b);
+        let mut p = a.individuate_as_archetype();
+        p.add_parent(c);
+        let mut q = b.individuate_as_archetype();
+        q.add_parent(a);
+        let mut z = p.individuate_as_archetype();
+        z.add_parent(q);
+        let value1 = Form::new();
+        let value2 = Form::new();
+        a.add_outgoing(MetaForm::TYPE_ID, &value1);
+        b.add_outgoing(MetaForm::TYPE_ID, &value2);
+        resolve(&z, MetaForm::TYPE_ID, Direction::Outgoing);
+    }
+
+    #[test]
+    fn test_agreeing_ancestors() {
+        initialize_kb();
+        let mut parent1 = Attribute::archetype().individuate_as_archetype();
+        let mut parent2 = Attribute::archetype().individuate_as_archetype();
+        let mut child = parent1.individuate_as_archetype();
+        child.add_parent(parent2.into());
+        let value = Form::new();
+        parent1.add_outgoing(MetaForm::TYPE_ID, &value);
+        parent2.add_outgoing(MetaForm::TYPE_ID, &value);
+        assert_eq!(
+            try_resolve(&child, MetaForm::TYPE_ID, Direction::Outgoing),
+            Ok(Some(*value))
+        );
+    }
+
+    #[test]
+    fn test_negation_created_once() {
+        initialize_kb();
+        let flag_type = Form::archetype().individuate_as_archetype();
+        let kb = KnowledgeBase::current();
+        let negation = negation_of(kb, flag_type.id());
+        assert_eq!(negation_of(kb, flag_type.id()), negation);
+        assert_eq!(
+            flag_type
+                .base_wrapper()
+                .outgoing_nodes(Negation::TYPE_ID)
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_unset() {
+        initialize_kb();
+        let attr_type = Attribute::archetype().individuate_as_archetype();
+        let form = Form::new();
+        assert_eq!(
+            try_resolve(&form, attr_type.id(), Direction::Outgoing),
+            Ok(None)
+        );
+    }
+}
@@ -1,33 +1,60 @@
 use super::auto_init::initialize_types;
-use crate::graph::{bind_cypher_graph, bind_in_memory_graph, Graph, InjectionGraph};
+#[cfg(feature = "sqlite")]
+use crate::graph::bind_sqlite_graph;
+use crate::graph::{bind_cypher_graph, bind_in_memory_graph, Graph, InjectionGraph, KnowledgeBase};
+#[cfg(feature = "snapshot")]
+use crate::graph::{bind_in_memory_snapshot, GraphResult, GraphSnapshot, SnapshotFormat};
+#[cfg(feature = "thread-safe")]
+use crate::graph::{bind_shared_in_memory_graph, SharedGraph};
 use crate::tao::archetype::{Archetype, ArchetypeTrait, AttributeArchetype};
-use crate::tao::relation::attribute::{Attribute, MetaForm};
+use crate::tao::relation::attribute::{Attribute, Description, MetaForm};
+use crate::tao::relation::flag::{Nonhereditary, SchemaValidated};
 use crate::tao::Tao;
+#[cfg(feature = "snapshot")]
+use std::path::Path;
 
 /// Add the given Concept type to the KB.
 ///
+/// Each type's `TYPE_ID` must be the next ID that the KB hands out, so types should be added in
+/// order of ID, and before anything else gets added to the KB. This will panic if that ID has
+/// already been taken, for example by another crate extending Yin with IDs of its own. Crates
+/// that can't guarantee this should use `register_type` to have IDs allocated for them instead.
+///
 /// # Examples
 ///
 /// Note: do not actually run this on existing types, since they are automatically added when the
-/// KB is initialized.
+/// KB is initialized. This example starts out with an empty KB instead.
 ///
 /// ```rust
-/// # use zamm_yin::tao::initialize_kb;
-/// # initialize_kb();
+/// # use zamm_yin::graph::bind_in_memory_graph;
+/// # bind_in_memory_graph();
 /// use zamm_yin::initialize_type;
-/// use zamm_yin::tao::archetype::{Archetype, ArchetypeTrait};
-/// use zamm_yin::tao::relation::attribute::Inherits;
+/// use zamm_yin::tao::archetype::ArchetypeTrait;
+/// use zamm_yin::tao::form::Form;
+/// use zamm_yin::tao::relation::attribute::{Attribute, Inherits, Owner, Value};
+/// use zamm_yin::tao::relation::flag::Flag;
+/// use zamm_yin::tao::relation::Relation;
 /// use zamm_yin::tao::Tao; // import your own types instead
 /// use zamm_yin::graph::{Graph, InjectionGraph};
 ///
 /// let mut ig = InjectionGraph::new();
-/// initialize_type!(ig, (Archetype, Tao));
+/// initialize_type!(
+///     ig,
+///     (Tao, Form, Relation, Flag, Attribute, Owner, Value, Inherits)
+/// );
 /// ```
 #[macro_export]
 macro_rules! initialize_type {
     ($g:expr, ($($t:ty),*)) => {
         $(
-            $g.add_node();
+            let id = $g.add_node();
+            assert_eq!(
+                id,
+                <$t>::TYPE_ID,
+                "Type {} was meant to have ID {}, but that ID has already been taken",
+                <$t>::TYPE_NAME,
+                <$t>::TYPE_ID
+            );
             $g.set_node_name(<$t>::TYPE_ID, <$t>::TYPE_NAME);
         )*
         // set edges later, since edges contain references to node names, and that will be
@@ -45,6 +72,19 @@ fn custom_relations_init() {
         MetaForm::TYPE_ID,
         AttributeArchetype::TYPE_ID,
     );
+    ig.add_flag(Description::TYPE_ID, Nonhereditary::TYPE_ID);
+    ig.add_flag(SchemaValidated::TYPE_ID, Nonhereditary::TYPE_ID);
+}
+
+/// Seed the given KB with initial concepts and relationships.
+///
+/// This only needs to be called on KBs opened directly with `KnowledgeBase::new`. All the
+/// `initialize_*` functions seed their KBs already.
+pub fn seed_kb(kb: KnowledgeBase) {
+    kb.with_default(|| {
+        initialize_types();
+        custom_relations_init();
+    });
 }
 
 /// Initialize Yin with an in-memory graph database.
@@ -53,8 +93,28 @@ fn custom_relations_init() {
 /// concepts and relationships.
 pub fn initialize_kb() {
     bind_in_memory_graph();
-    initialize_types();
-    custom_relations_init();
+    seed_kb(KnowledgeBase::GLOBAL);
+}
+
+/// Open a new in-memory KB alongside the global one, seeded with initial concepts and
+/// relationships. Use the returned handle's `with_default` to create forms in it.
+pub fn new_in_memory_kb() -> KnowledgeBase {
+    let kb = KnowledgeBase::new_in_memory();
+    seed_kb(kb);
+    kb
+}
+
+/// Initialize Yin with an in-memory graph database that can be shared between threads.
+///
+/// This not only creates the graph for Yin to act on, but also seeds the graph with initial
+/// concepts and relationships. Threads spawned afterwards automatically use this KB too. The
+/// returned handle can be passed to `bind_shared_graph` to bind threads that were already using
+/// some other graph.
+#[cfg(feature = "thread-safe")]
+pub fn initialize_shared_kb() -> SharedGraph {
+    let shared = bind_shared_in_memory_graph();
+    seed_kb(KnowledgeBase::GLOBAL);
+    shared
 }
 
 /// Initialize Yin with a Neo4j-backed graph database.
@@ -63,6 +123,152 @@ pub fn initialize_kb() {
 /// concepts and relationships.
 pub fn initialize_cypher_kb(uri: &str) {
     bind_cypher_graph(uri);
-    initialize_types();
-    custom_relations_init();
+    seed_kb(KnowledgeBase::GLOBAL);
+}
+
+/// Initialize Yin with a graph database persisted to an embedded SQLite file.
+///
+/// This not only creates the graph for Yin to act on, but also seeds the graph with initial
+/// concepts and relationships. If the file already contains a KB from a previous run, that KB is
+/// used as-is instead.
+#[cfg(feature = "sqlite")]
+pub fn initialize_sqlite_kb(path: &str) {
+    bind_sqlite_graph(path);
+    if KnowledgeBase::GLOBAL.graph().size() == 0 {
+        seed_kb(KnowledgeBase::GLOBAL);
+    }
+}
+
+/// Save the current KB to disk, so that it can be restored later with `load_kb` instead of being
+/// rebuilt from scratch.
+///
+/// Files ending in `.json` are written as JSON, and all others are written in a compact binary
+/// encoding. Only node values of basic types such as strings and numbers are saved.
+#[cfg(feature = "snapshot")]
+pub fn save_kb<P: AsRef<Path>>(path: P) -> GraphResult<()> {
+    let path = path.as_ref();
+    InjectionGraph::new()
+        .try_snapshot()?
+        .save(path, SnapshotFormat::from_path(path))
+}
+
+/// Initialize Yin with an in-memory graph database that is restored from a file previously
+/// written by `save_kb`. Either format is accepted regardless of the file extension.
+///
+/// Unlike `initialize_kb`, this does not seed the graph with the initial concepts, because they
+/// are already part of the saved KB.
+#[cfg(feature = "snapshot")]
+pub fn load_kb<P: AsRef<Path>>(path: P) -> GraphResult<()> {
+    bind_in_memory_snapshot(&GraphSnapshot::load(path.as_ref())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node_wrappers::CommonNodeTrait;
+    use crate::tao::archetype::ArchetypeFormTrait;
+    use crate::tao::form::{Form, FormTrait};
+    use std::rc::Rc;
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_initialize_sqlite_kb_reopen() {
+        let path = std::env::temp_dir().join("yin_sqlite_kb_test.db");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        initialize_sqlite_kb(path);
+        let mut concept = Form::new();
+        concept.set_internal_name("persisted");
+        let size = InjectionGraph::new().size();
+
+        initialize_kb(); // release the file before reopening it
+        initialize_sqlite_kb(path);
+        assert_eq!(InjectionGraph::new().size(), size);
+        assert_eq!(concept.internal_name(), Some(Rc::from("persisted")));
+        assert!(concept.has_ancestor(Form::archetype()));
+        initialize_kb();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_side_by_side_kbs() {
+        initialize_kb();
+        let reference = new_in_memory_kb();
+        let scratch = new_in_memory_kb();
+        let size = scratch.graph().size();
+        let mut concept = reference.with_default(Form::new);
+        concept.set_internal_name("reference");
+        let mut draft = scratch.with_default(Form::new);
+        draft.set_internal_name("draft");
+
+        assert_eq!(concept.kb(), reference);
+        assert_eq!(draft.kb(), scratch);
+        assert_eq!(concept.internal_name(), Some(Rc::from("reference")));
+        assert_eq!(draft.internal_name(), Some(Rc::from("draft")));
+        assert_eq!(InjectionGraph::new().lookup("draft"), Vec::<usize>::new());
+        assert!(draft.has_ancestor(Form::archetype()));
+        assert!(draft.parents().iter().all(|p| p.kb() == scratch));
+
+        // nodes created outside of with_default go to the form's own KB
+        let subtype = scratch
+            .with_default(Form::archetype)
+            .individuate_as_archetype();
+        let meta = draft.as_form().specific_meta();
+        assert_eq!(subtype.kb(), scratch);
+        assert_eq!(meta.kb(), scratch);
+        assert!(scratch.graph().size() >= size + 3);
+        assert_eq!(reference.graph().size(), size + 1);
+    }
+
+    #[cfg(feature = "thread-safe")]
+    #[test]
+    fn test_initialize_shared_kb() {
+        initialize_shared_kb();
+        let concept_id = std::thread::spawn(|| {
+            // no binding needed, because new threads default to the shared KB
+            let mut concept = Form::new();
+            concept.set_internal_name("shared");
+            concept.id()
+        })
+        .join()
+        .unwrap();
+        let concept = Form::from(concept_id);
+        assert_eq!(concept.internal_name(), Some(Rc::from("shared")));
+        assert!(concept.has_ancestor(Form::archetype()));
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn test_save_and_load_kb() {
+        let dir = std::env::temp_dir();
+        for file in &["yin_kb_test.json", "yin_kb_test.yin"] {
+            initialize_kb();
+            let mut concept = Form::new();
+            concept.set_internal_name("persisted");
+            let path = dir.join(file);
+            save_kb(&path).unwrap();
+
+            initialize_kb();
+            assert_eq!(Form::from(concept.id()).internal_name(), None);
+
+            load_kb(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(concept.internal_name(), Some(Rc::from("persisted")));
+            assert!(concept.has_ancestor(Form::archetype()));
+            assert_eq!(
+                Form::archetype().internal_name(),
+                Some(Rc::from(Form::TYPE_NAME))
+            );
+        }
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn test_load_kb_missing_file() {
+        initialize_kb();
+        let path = std::env::temp_dir().join("yin_kb_test_nonexistent.yin");
+        assert!(load_kb(&path).is_err());
+        // the existing KB stays usable
+        assert!(Form::new().has_ancestor(Form::archetype()));
+    }
 }
@@ -46,15 +46,56 @@
 //! concept.set_internal_name("A");
 //! assert_eq!(concept.internal_name(), Some(Rc::from("A")));
 //! ```
+//!
+//! Other KBs can be opened alongside the global one. Forms created inside `with_default` stay
+//! bound to that KB afterwards:
+//!
+//! ```rust
+//! # use zamm_yin::tao::initialize_kb;
+//! # use zamm_yin::tao::archetype::ArchetypeTrait;
+//! # use zamm_yin::tao::form::{Form, FormTrait};
+//! # initialize_kb();
+//! use zamm_yin::node_wrappers::CommonNodeTrait;
+//! use zamm_yin::tao::new_in_memory_kb;
+//! use std::convert::TryFrom;
+//!
+//! let scratch = new_in_memory_kb();
+//! let mut draft = scratch.with_default(|| Form::new());
+//! draft.set_internal_name("draft");
+//!
+//! assert_eq!(draft.kb(), scratch);
+//! assert!(draft.has_ancestor(Form::archetype()));
+//! assert!(Form::try_from("draft").is_err()); // not in the global KB
+//! ```
 
 pub mod archetype;
 pub mod form;
 pub mod relation;
 
 mod auto_init;
+mod inheritance;
 mod init;
+mod queries;
+mod registry;
+mod schema;
 mod tao_form;
 
 pub use auto_init::YIN_MAX_ID;
-pub use init::{initialize_cypher_kb, initialize_kb};
+#[cfg(feature = "thread-safe")]
+pub use init::initialize_shared_kb;
+#[cfg(feature = "sqlite")]
+pub use init::initialize_sqlite_kb;
+pub use init::{initialize_cypher_kb, initialize_kb, new_in_memory_kb, seed_kb};
+#[cfg(feature = "snapshot")]
+pub use init::{load_kb, save_kb};
+pub use queries::{
+    all_individuals, inheritance_cycles, meta_attributes, multi_valued_attributes,
+    nonhereditary_attributes, nonhereditary_flags,
+};
+pub use registry::{
+    register_type, registered_type, registered_type_id, try_register_type, DYNAMIC_TYPE_ID,
+};
+pub use schema::{
+    is_validating_schema, schema_violations, set_schema_validation, SchemaError, SchemaResult,
+};
 pub use tao_form::Tao;
@@ -0,0 +1,207 @@
+use crate::graph::{Graph, KnowledgeBase};
+use crate::node_wrappers::{BaseNodeTrait, FinalNode, InheritanceNodeTrait};
+use crate::tao::archetype::{Archetype, ArchetypeTrait, AttributeArchetype};
+use crate::tao::form::{Form, FormTrait};
+use crate::tao::relation::attribute::{Attribute, Inherits};
+use crate::tao::relation::flag::{Flag, IsIndividual, Meta, MultiValued, Nonhereditary};
+use crate::tao::Tao;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+/// Nodes in the current KB that have the flag set on themselves, as opposed to inheriting it.
+fn directly_flagged(flag: usize) -> Vec<FinalNode> {
+    let kb = KnowledgeBase::current();
+    kb.graph()
+        .nodes_with_flag(flag)
+        .into_iter()
+        .map(|id| FinalNode::from_id_in(kb, id))
+        .collect()
+}
+
+/// Archetypes in the current KB that descend from `ancestor` and have the flag, whether set on
+/// themselves or inherited from a parent archetype.
+fn flagged_archetypes(flag: usize, ancestor: usize) -> Vec<Archetype> {
+    let kb = KnowledgeBase::current();
+    let ancestor = Archetype::from(FinalNode::from_id_in(kb, ancestor));
+    let mut found = BTreeSet::new();
+    let mut to_be_visited: VecDeque<FinalNode> = directly_flagged(flag).into_iter().collect();
+    while let Some(next) = to_be_visited.pop_front() {
+        if Form::from(next).is_individual() || !found.insert(next) {
+            continue;
+        }
+        to_be_visited.extend(next.iter_incoming_nodes(Inherits::TYPE_ID));
+    }
+    found
+        .into_iter()
+        .map(Archetype::from)
+        .filter(|a| a.has_ancestor(ancestor))
+        .collect()
+}
+
+/// Attribute archetypes in the current KB that have the flag, whether set on themselves or
+/// inherited from a parent archetype.
+fn flagged_attributes(flag: usize) -> Vec<AttributeArchetype> {
+    flagged_archetypes(flag, Attribute::TYPE_ID)
+        .into_iter()
+        .map(|a| AttributeArchetype::from(*a))
+        .collect()
+}
+
+/// All individuals in the current KB, as opposed to archetypes.
+pub fn all_individuals() -> Vec<Form> {
+    directly_flagged(IsIndividual::TYPE_ID)
+        .into_iter()
+        .map(Form::from)
+        .collect()
+}
+
+/// All attribute archetypes in the current KB that are nonhereditary, including ones that inherit
+/// from a nonhereditary attribute archetype.
+pub fn nonhereditary_attributes() -> Vec<AttributeArchetype> {
+    flagged_attributes(Nonhereditary::TYPE_ID)
+}
+
+/// All flag archetypes in the current KB that are nonhereditary, including ones that inherit from
+/// a nonhereditary flag archetype.
+pub fn nonhereditary_flags() -> Vec<Archetype> {
+    flagged_archetypes(Nonhereditary::TYPE_ID, Flag::TYPE_ID)
+}
+
+/// All attribute archetypes in the current KB that are meta, including ones that inherit from a
+/// meta attribute archetype.
+pub fn meta_attributes() -> Vec<AttributeArchetype> {
+    flagged_attributes(Meta::TYPE_ID)
+}
+
+/// All attribute archetypes in the current KB that accept multiple values, including ones that
+/// inherit from a multi-valued attribute archetype.
+pub fn multi_valued_attributes() -> Vec<AttributeArchetype> {
+    flagged_attributes(MultiValued::TYPE_ID)
+}
+
+/// All groups of forms in the current KB that inherit from each other in a loop, with the forms in
+/// each group sorted by ID. Forms that are their own parents, such as Tao, don't count.
+///
+/// Only forms that descend from Tao get checked, which includes every form that was created as an
+/// individual or child archetype of another form.
+pub fn inheritance_cycles() -> Vec<Vec<Form>> {
+    let kb = KnowledgeBase::current();
+    let tao = FinalNode::from_id_in(kb, Tao::TYPE_ID);
+    let mut descendants = vec![tao];
+    let mut visited: HashSet<FinalNode> = descendants.iter().copied().collect();
+    let mut to_be_visited = VecDeque::from(vec![tao]);
+    while let Some(next) = to_be_visited.pop_front() {
+        for child in next.iter_incoming_nodes(Inherits::TYPE_ID) {
+            if visited.insert(child) {
+                descendants.push(child);
+                to_be_visited.push_back(child);
+            }
+        }
+    }
+
+    let ancestries: HashMap<FinalNode, HashSet<FinalNode>> = descendants
+        .iter()
+        .map(|node| (*node, node.inheritance_nodes().into_iter().collect()))
+        .collect();
+    let mut cycles = BTreeSet::new();
+    for (node, ancestry) in &ancestries {
+        let mut cycle: Vec<Form> = ancestry
+            .iter()
+            .filter(|ancestor| {
+                ancestries
+                    .get(ancestor)
+                    .map(|a| a.contains(node))
+                    .unwrap_or(false)
+            })
+            .map(|ancestor| Form::from(*ancestor))
+            .collect();
+        if cycle.len() > 1 {
+            cycle.sort();
+            cycles.insert(cycle);
+        }
+    }
+    cycles.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tao::archetype::{ArchetypeFormTrait, AttributeArchetypeFormTrait};
+    use crate::tao::initialize_kb;
+    use crate::tao::new_in_memory_kb;
+    use crate::tao::relation::attribute::{Attribute, Description};
+    use crate::tao::relation::flag::SchemaValidated;
+    use std::ops::Deref;
+
+    #[test]
+    fn test_all_individuals() {
+        initialize_kb();
+        assert_eq!(all_individuals(), Vec::<Form>::new());
+        let form1 = Form::new();
+        let subtype = Form::archetype().individuate_as_archetype();
+        let form2 = subtype.individuate_as_form();
+        assert_eq!(all_individuals(), vec![form1, form2]);
+    }
+
+    #[test]
+    fn test_all_individuals_other_kb() {
+        initialize_kb();
+        Form::new();
+        let kb = new_in_memory_kb();
+        assert_eq!(kb.with_default(all_individuals), Vec::<Form>::new());
+    }
+
+    #[test]
+    fn test_nonhereditary_attributes() {
+        initialize_kb();
+        let mut attr_type = Attribute::archetype().individuate_as_archetype();
+        let attr_subtype = attr_type.individuate_as_archetype();
+        let other_attr_type = Attribute::archetype().individuate_as_archetype();
+        attr_type.mark_nonhereditary_attr();
+        attr_subtype.individuate_as_form();
+        // descriptions describe only the concept they're set on
+        let description = AttributeArchetype::from(Description::TYPE_ID);
+        assert_eq!(
+            nonhereditary_attributes(),
+            vec![description, attr_type, attr_subtype]
+        );
+        assert!(!nonhereditary_attributes().contains(&other_attr_type));
+    }
+
+    #[test]
+    fn test_nonhereditary_flags() {
+        initialize_kb();
+        let mut flag_type = AttributeArchetype::from(*Flag::archetype().individuate_as_archetype());
+        flag_type.mark_nonhereditary_attr();
+        // schema validation is only ever meant to be set on Tao itself
+        assert_eq!(
+            nonhereditary_flags(),
+            vec![SchemaValidated::archetype(), Archetype::from(*flag_type)]
+        );
+        assert!(!nonhereditary_flags().contains(&Description::archetype().into()));
+    }
+
+    #[test]
+    fn test_inheritance_cycles() {
+        initialize_kb();
+        assert_eq!(inheritance_cycles(), Vec::<Vec<Form>>::new());
+        let a = Form::archetype().individuate_as_archetype();
+        let b = a.individuate_as_archetype();
+        let mut c = b.individuate_as_archetype();
+        let mut loner = Form::archetype().individuate_as_archetype();
+        // add_parent would refuse to do this
+        c.add_outgoing(Inherits::TYPE_ID, &b);
+        let mut b_node = *b.deref();
+        b_node.add_outgoing(Inherits::TYPE_ID, &c);
+        loner.add_parent(loner);
+        assert_eq!(inheritance_cycles(), vec![vec![b.as_form(), c.as_form()]]);
+    }
+
+    #[test]
+    fn test_multi_valued_and_meta_attributes() {
+        initialize_kb();
+        let mut attr_type = Attribute::archetype().individuate_as_archetype();
+        attr_type.mark_multi_valued_attr();
+        assert_eq!(multi_valued_attributes(), vec![attr_type]);
+        assert!(!meta_attributes().contains(&attr_type));
+    }
+}
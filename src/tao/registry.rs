@@ -0,0 +1,264 @@
+use crate::graph::{Graph, KnowledgeBase};
+use crate::node_wrappers::{CommonNodeTrait, FinalNode};
+use crate::tao::archetype::{Archetype, ArchetypeFormTrait, ArchetypeTrait};
+use crate::tao::relation::flag::IsIndividual;
+use crate::tao::schema::expect_schema;
+use crate::tao::{SchemaError, SchemaResult};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+thread_local! {
+    /// IDs of the types looked up so far with `registered_type_id`, by KB and name.
+    static TYPE_IDS: RefCell<HashMap<(KnowledgeBase, &'static str), usize>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Placeholder `TYPE_ID` for archetypes that get their actual ID allocated at runtime by
+/// `register_type`. Such archetypes should override `ArchetypeTrait::archetype_id` to look their
+/// ID up with `registered_type`.
+pub const DYNAMIC_TYPE_ID: usize = usize::MAX;
+
+/// Whether the node with this ID still exists in the KB as an archetype going by this name.
+fn is_archetype_named(kb: KnowledgeBase, id: usize, name: &str) -> bool {
+    let graph = kb.graph();
+    match graph.try_node_name(id) {
+        Ok(Some(node_name)) => &*node_name == name && !graph.has_flag(id, IsIndividual::TYPE_ID),
+        _ => false,
+    }
+}
+
+/// IDs of the archetypes in this KB that go by this name, as opposed to individuals that happen
+/// to share it.
+fn archetype_ids(kb: KnowledgeBase, name: &str) -> Vec<usize> {
+    let graph = kb.graph();
+    graph
+        .lookup(name)
+        .into_iter()
+        .filter(|id| !graph.has_flag(*id, IsIndividual::TYPE_ID))
+        .collect()
+}
+
+/// The archetype registered under this name in the current KB, if there is one. Types defined by
+/// Yin itself, or added with `initialize_type!`, count as registered too.
+///
+/// # Examples
+///
+/// ```rust
+/// # use zamm_yin::tao::initialize_kb;
+/// # initialize_kb();
+/// use zamm_yin::tao::archetype::ArchetypeTrait;
+/// use zamm_yin::tao::form::Form;
+/// use zamm_yin::tao::registered_type;
+///
+/// assert_eq!(registered_type("form"), Some(Form::archetype()));
+/// assert_eq!(registered_type("unicorn"), None);
+/// ```
+pub fn registered_type(name: &str) -> Option<Archetype> {
+    let kb = KnowledgeBase::current();
+    archetype_ids(kb, name)
+        .first()
+        .map(|id| Archetype::from(FinalNode::from_id_in(kb, *id)))
+}
+
+/// The ID of the archetype registered under this name in the given KB, if there is one. Unlike
+/// `registered_type`, this remembers what it found in each KB, so that archetypes with runtime IDs
+/// can cheaply look themselves up in `ArchetypeTrait::archetype_id` every time they're used.
+///
+/// # Examples
+///
+/// ```rust
+/// # use zamm_yin::tao::initialize_kb;
+/// # initialize_kb();
+/// use zamm_yin::graph::KnowledgeBase;
+/// use zamm_yin::node_wrappers::CommonNodeTrait;
+/// use zamm_yin::tao::archetype::ArchetypeTrait;
+/// use zamm_yin::tao::form::Form;
+/// use zamm_yin::tao::{register_type, registered_type_id};
+///
+/// let dog = register_type("dog", Form::archetype());
+/// assert_eq!(registered_type_id(KnowledgeBase::current(), "dog"), Some(dog.id()));
+/// ```
+pub fn registered_type_id(kb: KnowledgeBase, name: &'static str) -> Option<usize> {
+    let key = (kb, name);
+    let cached = TYPE_IDS.with(|ids| ids.borrow().get(&key).copied());
+    // the KB may have been rebound or rolled back since then
+    if let Some(id) = cached.filter(|id| is_archetype_named(kb, *id, name)) {
+        return Some(id);
+    }
+
+    let id = archetype_ids(kb, name).first().copied();
+    TYPE_IDS.with(|ids| {
+        let mut ids = ids.borrow_mut();
+        match id {
+            Some(id) => ids.insert(key, id),
+            None => ids.remove(&key),
+        }
+    });
+    id
+}
+
+/// Register a new type with the parent's KB, allocating the next free ID for it. Unlike IDs
+/// fixed at compile time, this lets independent crates extend Yin without coordinating which IDs
+/// each of them gets to use.
+///
+/// This will panic if another archetype in the KB already goes by the same name.
+///
+/// # Examples
+///
+/// ```rust
+/// # use zamm_yin::tao::initialize_kb;
+/// # initialize_kb();
+/// use zamm_yin::node_wrappers::CommonNodeTrait;
+/// use zamm_yin::tao::archetype::ArchetypeTrait;
+/// use zamm_yin::tao::form::{Form, FormTrait};
+/// use zamm_yin::tao::{register_type, registered_type, YIN_MAX_ID};
+///
+/// let dog = register_type("dog", Form::archetype());
+/// assert!(dog.id() > YIN_MAX_ID);
+/// assert!(dog.has_ancestor(Form::archetype()));
+/// assert_eq!(registered_type("dog"), Some(dog));
+/// ```
+pub fn register_type(name: &str, parent: Archetype) -> Archetype {
+    expect_schema(try_register_type(name, parent))
+}
+
+/// Fallible version of `register_type`, which rejects the type instead of panicking if its name
+/// is already taken.
+pub fn try_register_type(name: &str, parent: Archetype) -> SchemaResult<Archetype> {
+    let kb = parent.kb();
+    kb.graph().transaction(|| {
+        if let Some(existing) = kb.with_default(|| registered_type(name)) {
+            return Err(SchemaError::TypeNameTaken { existing });
+        }
+        let mut archetype = parent.individuate_as_archetype();
+        archetype.set_internal_name(name);
+        Ok(archetype)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::InjectionGraph;
+    use crate::initialize_type;
+    use crate::tao::form::{Form, FormTrait};
+    use crate::tao::relation::attribute::Inherits;
+    use crate::tao::{initialize_kb, new_in_memory_kb, YIN_MAX_ID};
+
+    #[test]
+    fn test_builtin_types_registered() {
+        initialize_kb();
+        assert_eq!(registered_type("form"), Some(Form::archetype()));
+        assert_eq!(registered_type("archetype"), Some(Archetype::archetype()));
+    }
+
+    #[test]
+    fn test_individuals_not_registered() {
+        initialize_kb();
+        let mut form = Form::new();
+        form.set_internal_name("rover");
+        assert_eq!(registered_type("rover"), None);
+    }
+
+    #[test]
+    fn test_ids_allocated_in_order() {
+        initialize_kb();
+        let dog = register_type("dog", Form::archetype());
+        let cat = register_type("cat", Form::archetype());
+        assert_eq!(dog.id(), YIN_MAX_ID + 1);
+        assert_eq!(cat.id(), YIN_MAX_ID + 2);
+        assert_eq!(registered_type("cat"), Some(cat));
+    }
+
+    #[test]
+    fn test_registered_under_parent() {
+        initialize_kb();
+        let animal = register_type("animal", Form::archetype());
+        let dog = register_type("dog", animal);
+        assert_eq!(dog.parents(), vec![animal]);
+        assert!(dog.individuate_as_form().has_ancestor(animal));
+    }
+
+    #[test]
+    fn test_name_conflict() {
+        initialize_kb();
+        let dog = register_type("dog", Form::archetype());
+        assert_eq!(
+            try_register_type("dog", Form::archetype()),
+            Err(SchemaError::TypeNameTaken { existing: dog })
+        );
+        assert_eq!(
+            try_register_type("form", Form::archetype()),
+            Err(SchemaError::TypeNameTaken {
+                existing: Form::archetype()
+            })
+        );
+        // nothing else got added to the KB
+        assert_eq!(KnowledgeBase::current().graph().size(), YIN_MAX_ID + 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot register another type with the same name as dog")]
+    fn test_name_conflict_panics() {
+        initialize_kb();
+        register_type("dog", Form::archetype());
+        register_type("dog", Form::archetype());
+    }
+
+    #[test]
+    fn test_registered_type_id_per_kb() {
+        initialize_kb();
+        let kb = new_in_memory_kb();
+        let cat = kb.with_default(|| register_type("cat", Form::archetype()));
+        let kb_dog = kb.with_default(|| register_type("dog", Form::archetype()));
+        let global_dog = register_type("dog", Form::archetype());
+        assert_ne!(kb_dog.id(), global_dog.id());
+        assert_eq!(
+            registered_type_id(KnowledgeBase::GLOBAL, "dog"),
+            Some(global_dog.id())
+        );
+        assert_eq!(registered_type_id(kb, "dog"), Some(kb_dog.id()));
+        assert_eq!(registered_type_id(kb, "cat"), Some(cat.id()));
+        assert_eq!(registered_type_id(KnowledgeBase::GLOBAL, "cat"), None);
+    }
+
+    #[test]
+    fn test_registered_type_id_after_rebind() {
+        initialize_kb();
+        let dog = register_type("dog", Form::archetype());
+        assert_eq!(
+            registered_type_id(KnowledgeBase::GLOBAL, "dog"),
+            Some(dog.id())
+        );
+        initialize_kb();
+        let cat = register_type("cat", Form::archetype());
+        // the ID that dog used to have now belongs to a different type
+        assert_eq!(cat.id(), dog.id());
+        assert_eq!(registered_type_id(KnowledgeBase::GLOBAL, "dog"), None);
+        assert_eq!(
+            registered_type_id(KnowledgeBase::GLOBAL, "cat"),
+            Some(cat.id())
+        );
+    }
+
+    #[test]
+    fn test_registered_in_parent_kb() {
+        initialize_kb();
+        let kb = new_in_memory_kb();
+        let parent = kb.with_default(Form::archetype);
+        let dog = register_type("dog", parent);
+        assert_eq!(dog.kb(), kb);
+        assert_eq!(registered_type("dog"), None);
+        assert_eq!(kb.with_default(|| registered_type("dog")), Some(dog));
+    }
+
+    #[test]
+    #[should_panic(expected = "was meant to have ID")]
+    fn test_static_id_conflict() {
+        initialize_kb();
+        register_type("dog", Form::archetype());
+        // Form's ID is long gone by now
+        let mut ig = InjectionGraph::new();
+        initialize_type!(ig, (Form));
+    }
+}
@@ -1,7 +1,9 @@
-use crate::node_wrappers::{BaseNodeTrait, FinalNode};
-use crate::tao::archetype::ArchetypeTrait;
+use crate::node_wrappers::{BaseNodeTrait, CommonNodeTrait, FinalNode};
+use crate::tao::archetype::{ArchetypeTrait, AttributeArchetype, AttributeArchetypeFormTrait};
 use crate::tao::form::FormTrait;
 use crate::tao::relation::attribute::{Owner, Value};
+use crate::tao::schema::{check_owner, check_value, expect_schema, is_validating_schema};
+use crate::tao::SchemaResult;
 use std::ops::{Deref, DerefMut};
 
 /// Interface for all attributes.
@@ -11,27 +13,175 @@ pub trait AttributeTrait: FormTrait + Deref<Target = FinalNode> + DerefMut {
     /// The Form representing the value.
     type ValueForm: FormTrait + From<FinalNode>;
 
-    /// Set the owner for this attribute.
+    /// Set the owner for this attribute, replacing any owner it had before.
+    ///
+    /// If schema validation is on for this attribute's KB, this will panic if the owner does not
+    /// descend from the attribute's owner archetype.
     fn set_owner(&mut self, owner: &Self::OwnerForm) {
-        self.add_outgoing(Owner::TYPE_ID, &owner);
+        expect_schema(self.try_set_owner(owner))
     }
 
-    /// The owner of an attribute, if it exists.
+    /// Fallible version of `set_owner`, which rejects the owner instead of panicking if schema
+    /// validation is on and the owner does not descend from the attribute's owner archetype.
+    fn try_set_owner(&mut self, owner: &Self::OwnerForm) -> SchemaResult<()> {
+        if is_validating_schema(self.kb()) {
+            check_owner(self.deref(), owner.deref())?;
+        }
+        replace_outgoing(**self, Owner::TYPE_ID, owner.deref());
+        Ok(())
+    }
+
+    /// The owner of an attribute, if it exists. An owner set on the attribute itself takes
+    /// precedence over any it inherits.
     fn owner(&self) -> Option<Self::OwnerForm> {
-        self.outgoing_nodes(Owner::TYPE_ID)
-            .get(0)
+        own_or_inherited(self.deref(), Owner::TYPE_ID)
+            .first()
             .map(|n| Self::OwnerForm::from(*n))
     }
 
-    /// Set the value for this attribute.
+    /// Set the value for this attribute. If the attribute is single-valued, this replaces any
+    /// value it had before. If it is multi-valued, the new value gets added to the existing ones.
+    ///
+    /// If schema validation is on for this attribute's KB, this will panic if the value does not
+    /// descend from the attribute's value archetype.
     fn set_value(&mut self, value: &Self::ValueForm) {
-        self.add_outgoing(Value::TYPE_ID, &value);
+        expect_schema(self.try_set_value(value))
+    }
+
+    /// Fallible version of `set_value`, which rejects the value instead of panicking if schema
+    /// validation is on and the value does not descend from the attribute's value archetype.
+    fn try_set_value(&mut self, value: &Self::ValueForm) -> SchemaResult<()> {
+        if is_validating_schema(self.kb()) {
+            check_value(self.deref(), value.deref())?;
+        }
+        if self.is_multi_valued() {
+            self.add_outgoing(Value::TYPE_ID, value);
+        } else {
+            replace_outgoing(**self, Value::TYPE_ID, value.deref());
+        }
+        Ok(())
     }
 
-    /// The value of an attribute, if it exists.
+    /// The value of an attribute, if it exists. For multi-valued attributes, use `values` instead
+    /// to get all of them. A value set on the attribute itself takes precedence over any it
+    /// inherits.
     fn value(&self) -> Option<Self::ValueForm> {
-        self.outgoing_nodes(Value::TYPE_ID)
-            .get(0)
+        own_or_inherited(self.deref(), Value::TYPE_ID)
+            .first()
             .map(|n| Self::ValueForm::from(*n))
     }
+
+    /// All values of an attribute. Single-valued attributes will have at most one. Values set on
+    /// the attribute itself replace any it inherits, rather than adding to them.
+    fn values(&self) -> Vec<Self::ValueForm> {
+        own_or_inherited(self.deref(), Value::TYPE_ID)
+            .into_iter()
+            .map(Self::ValueForm::from)
+            .collect()
+    }
+
+    /// Whether this attribute accepts multiple values, as opposed to each new value replacing the
+    /// old one.
+    fn is_multi_valued(&self) -> bool {
+        AttributeArchetype::from(*self.deref()).is_multi_valued_attr()
+    }
+}
+
+/// The nodes that `node` itself links to via edges of this type, or the ones it inherits if it
+/// has none of its own.
+fn own_or_inherited(node: &FinalNode, edge_type: usize) -> Vec<FinalNode> {
+    let kb = node.kb();
+    let own: Vec<FinalNode> = node
+        .base_wrapper()
+        .outgoing_nodes(edge_type)
+        .into_iter()
+        .map(|n| FinalNode::from_id_in(kb, n.id()))
+        .collect();
+    if own.is_empty() {
+        node.outgoing_nodes(edge_type)
+    } else {
+        own
+    }
+}
+
+/// Make `to` the only node that `node` links to via edges of this type, all within one transaction.
+/// Only links set on `node` itself get replaced, since inherited ones belong to its ancestors.
+fn replace_outgoing(mut node: FinalNode, edge_type: usize, to: &FinalNode) {
+    let kb = node.kb();
+    kb.graph().transaction(|| {
+        for old in node.base_wrapper().outgoing_nodes(edge_type) {
+            node.remove_outgoing(edge_type, &FinalNode::from_id_in(kb, old.id()));
+        }
+        node.add_outgoing(edge_type, to);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tao::archetype::ArchetypeFormTrait;
+    use crate::tao::form::Form;
+    use crate::tao::initialize_kb;
+    use crate::tao::relation::attribute::Attribute;
+
+    #[test]
+    fn test_single_value_replaced() {
+        initialize_kb();
+        let mut attr = Attribute::new();
+        let old = Form::new();
+        let new = Form::new();
+        attr.set_value(&old);
+        attr.set_value(&new);
+        assert_eq!(attr.value(), Some(new));
+        assert_eq!(attr.values(), vec![new]);
+    }
+
+    #[test]
+    fn test_multiple_values_kept() {
+        initialize_kb();
+        let mut attr_type = Attribute::archetype().individuate_as_archetype();
+        attr_type.mark_multi_valued_attr();
+        let mut attr = attr_type.individuate_as_form();
+        assert!(attr.is_multi_valued());
+        let first = Form::new();
+        let second = Form::new();
+        attr.set_value(&first);
+        attr.set_value(&second);
+        assert_eq!(attr.values(), vec![first, second]);
+    }
+
+    #[test]
+    fn test_owner_replaced() {
+        initialize_kb();
+        let mut attr = Attribute::new();
+        let old = Form::new();
+        let new = Form::new();
+        attr.set_owner(&old);
+        attr.set_owner(&new);
+        assert_eq!(attr.owner(), Some(new));
+        assert_eq!(attr.outgoing_nodes(Owner::TYPE_ID).len(), 1);
+    }
+
+    #[test]
+    fn test_inherited_value_left_alone() {
+        initialize_kb();
+        let mut attr_type = Attribute::archetype().individuate_as_archetype();
+        let default = Form::new();
+        attr_type.add_outgoing(Value::TYPE_ID, &default);
+        let mut attr = attr_type.individuate_as_form();
+        assert_eq!(attr.value(), Some(default));
+        let new = Form::new();
+        attr.set_value(&new);
+        assert_eq!(attr.value(), Some(new));
+        assert_eq!(attr.values(), vec![new]);
+        // replacing the attribute's own value doesn't touch what its archetype links to
+        assert_eq!(
+            attr_type.base_wrapper().outgoing_nodes(Value::TYPE_ID),
+            vec![*default.base_wrapper()]
+        );
+        assert_eq!(
+            attr.base_wrapper().outgoing_nodes(Value::TYPE_ID),
+            vec![*new.base_wrapper()]
+        );
+    }
 }
@@ -0,0 +1,498 @@
+use crate::graph::{Graph, KnowledgeBase};
+use crate::node_wrappers::{BaseNodeTrait, CommonNodeTrait, FinalNode};
+use crate::tao::archetype::{
+    Archetype, ArchetypeFormTrait, ArchetypeTrait, AttributeArchetype, AttributeArchetypeFormTrait,
+};
+use crate::tao::form::{Form, FormTrait};
+use crate::tao::relation::attribute::{Owner, Value};
+use crate::tao::relation::flag::SchemaValidated;
+use crate::tao::relation::Relation;
+use crate::tao::Tao;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::Deref;
+
+/// Result of an operation that may violate the schema of a KB.
+pub type SchemaResult<T> = std::result::Result<T, SchemaError>;
+
+/// Ways in which the KB can break the restrictions set by its archetypes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaError {
+    /// The owner of a relation does not descend from the relation's owner archetype.
+    InvalidOwner {
+        /// The relation with the invalid owner.
+        relation: Form,
+        /// The invalid owner.
+        owner: Form,
+        /// The archetype that the owner should have descended from.
+        expected: Archetype,
+    },
+    /// The value of an attribute does not descend from the attribute's value archetype.
+    InvalidValue {
+        /// The attribute with the invalid value.
+        attribute: Form,
+        /// The invalid value.
+        value: Form,
+        /// The archetype that the value should have descended from.
+        expected: Archetype,
+    },
+    /// An attribute that only accepts a single value ended up with several of them anyway.
+    MultipleValues {
+        /// The attribute with too many values.
+        attribute: Form,
+        /// How many values the attribute has.
+        count: usize,
+    },
+    /// Unrelated ancestors of a form disagree on what a single-valued attribute should be, so
+    /// there is no one most specific value for the form to inherit.
+    ConflictingInheritance {
+        /// The form inheriting the attribute.
+        form: Form,
+        /// The type of attribute being inherited.
+        attribute: Archetype,
+        /// One of the values being inherited.
+        first: Form,
+        /// Another one of the values being inherited.
+        second: Form,
+    },
+    /// A form was about to inherit from one of its own descendants.
+    InheritanceCycle {
+        /// The form that would have gotten a new parent.
+        form: Form,
+        /// The parent that already inherits from the form.
+        parent: Archetype,
+    },
+    /// The ancestors of a form cannot be put in an order where every form comes before its
+    /// ancestors and the parents of every form keep their own order.
+    InconsistentLinearization {
+        /// The form whose ancestors cannot be ordered.
+        form: Form,
+    },
+    /// A type was about to be registered under a name that another archetype already goes by.
+    TypeNameTaken {
+        /// The archetype that already has the name.
+        existing: Archetype,
+    },
+    /// A new archetype was given the properties of a relation, such as an owner or value
+    /// archetype, without descending from `Relation`.
+    NotARelation {
+        /// The parent that the new archetype would have inherited from instead.
+        parent: Archetype,
+    },
+}
+
+/// Human-readable reference to a node, using its name if it has one.
+fn describe(node: &FinalNode) -> String {
+    match node.internal_name() {
+        Some(name) => format!("{} (#{})", name, node.id()),
+        None => format!("#{}", node.id()),
+    }
+}
+
+impl Display for SchemaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaError::InvalidOwner {
+                relation,
+                owner,
+                expected,
+            } => write!(
+                f,
+                "Relation {} cannot be owned by {}, which does not descend from {}",
+                describe(relation),
+                describe(owner),
+                describe(expected)
+            ),
+            SchemaError::InvalidValue {
+                attribute,
+                value,
+                expected,
+            } => write!(
+                f,
+                "Attribute {} cannot have the value {}, which does not descend from {}",
+                describe(attribute),
+                describe(value),
+                describe(expected)
+            ),
+            SchemaError::MultipleValues { attribute, count } => write!(
+                f,
+                "Single-valued attribute {} has {} values",
+                describe(attribute),
+                count
+            ),
+            SchemaError::ConflictingInheritance {
+                form,
+                attribute,
+                first,
+                second,
+            } => write!(
+                f,
+                "{} inherits conflicting values for {}: {} and {}",
+                describe(form),
+                describe(attribute),
+                describe(first),
+                describe(second)
+            ),
+            SchemaError::InheritanceCycle { form, parent } => write!(
+                f,
+                "{} cannot inherit from {}, which already inherits from it",
+                describe(form),
+                describe(parent)
+            ),
+            SchemaError::InconsistentLinearization { form } => write!(
+                f,
+                "The ancestors of {} have no consistent order of precedence",
+                describe(form)
+            ),
+            SchemaError::TypeNameTaken { existing } => write!(
+                f,
+                "Cannot register another type with the same name as {}",
+                describe(existing)
+            ),
+            SchemaError::NotARelation { parent } => write!(
+                f,
+                "Only relations have owner or value archetypes, but children of {} are not relations",
+                describe(parent)
+            ),
+        }
+    }
+}
+
+impl Error for SchemaError {}
+
+/// Unwrap the result of a schema-checked operation for the infallible versions of the API,
+/// panicking with a readable message if the operation was rejected.
+pub(crate) fn expect_schema<T>(result: SchemaResult<T>) -> T {
+    result.unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Turn schema validation on or off for a KB. While it is on, setting the owner or value of an
+/// attribute to a form that does not descend from the attribute's owner or value archetype will be
+/// rejected. Validation is off by default, and stays on for the KB until it is turned off again.
+///
+/// The setting is stored in the KB's graph itself, so it applies to every thread sharing the
+/// graph, and persists along with graphs stored in a database.
+pub fn set_schema_validation(kb: KnowledgeBase, enabled: bool) {
+    let mut graph = kb.graph();
+    if enabled {
+        graph.add_flag(Tao::TYPE_ID, SchemaValidated::TYPE_ID);
+    } else {
+        graph.remove_flag(Tao::TYPE_ID, SchemaValidated::TYPE_ID);
+    }
+}
+
+/// Whether or not schema validation is turned on for a KB.
+pub fn is_validating_schema(kb: KnowledgeBase) -> bool {
+    kb.graph().has_flag(Tao::TYPE_ID, SchemaValidated::TYPE_ID)
+}
+
+/// Whether `form` descends from `expected`. Everything descends from Tao.
+fn conforms(form: &FinalNode, expected: Archetype) -> bool {
+    expected.id() == Tao::TYPE_ID || Form::from(*form).has_ancestor(expected)
+}
+
+/// Check that `owner` is an acceptable owner for `relation`.
+pub(crate) fn check_owner(relation: &FinalNode, owner: &FinalNode) -> SchemaResult<()> {
+    let expected = AttributeArchetype::from(*relation).try_owner_archetype()?;
+    if conforms(owner, expected) {
+        Ok(())
+    } else {
+        Err(SchemaError::InvalidOwner {
+            relation: Form::from(*relation),
+            owner: Form::from(*owner),
+            expected,
+        })
+    }
+}
+
+/// Check that `value` is an acceptable value for `attribute`.
+pub(crate) fn check_value(attribute: &FinalNode, value: &FinalNode) -> SchemaResult<()> {
+    let expected = AttributeArchetype::from(*attribute).try_value_archetype()?;
+    if conforms(value, expected) {
+        Ok(())
+    } else {
+        Err(SchemaError::InvalidValue {
+            attribute: Form::from(*attribute),
+            value: Form::from(*value),
+            expected,
+        })
+    }
+}
+
+/// All owners and values in the KB that break the restrictions of their attribute archetypes,
+/// regardless of whether or not validation was on when they were set. This includes
+/// single-valued attributes that were given several values.
+pub fn schema_violations(kb: KnowledgeBase) -> Vec<SchemaError> {
+    let mut violations = Vec::new();
+    for relation in kb.with_default(|| Relation::archetype().individuals()) {
+        if !relation.is_individual() {
+            continue;
+        }
+        for owner in relation.outgoing_nodes(Owner::TYPE_ID) {
+            violations.extend(check_owner(relation.deref(), &owner).err());
+        }
+        let values = relation.outgoing_nodes(Value::TYPE_ID);
+        if values.len() > 1 && !AttributeArchetype::from(*relation.deref()).is_multi_valued_attr() {
+            violations.push(SchemaError::MultipleValues {
+                attribute: relation,
+                count: values.len(),
+            });
+        }
+        for value in values {
+            violations.extend(check_value(relation.deref(), &value).err());
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tao::initialize_kb;
+    use crate::tao::new_in_memory_kb;
+    use crate::tao::relation::attribute::{
+        Attribute, AttributeTrait, OwnerArchetype, ValueArchetype,
+    };
+
+    /// Create an attribute type that only accepts owners and values of the given types.
+    fn restricted_attr(owner_type: Archetype, value_type: Archetype) -> AttributeArchetype {
+        let mut attr_type = Attribute::archetype().individuate_as_archetype();
+        attr_type.set_internal_name("restricted");
+        attr_type.set_owner_archetype(&owner_type);
+        attr_type.set_value_archetype(&value_type);
+        attr_type
+    }
+
+    #[test]
+    fn test_validation_off_by_default() {
+        initialize_kb();
+        let kb = KnowledgeBase::current();
+        assert!(!is_validating_schema(kb));
+        set_schema_validation(kb, true);
+        assert!(is_validating_schema(kb));
+        assert!(!is_validating_schema(new_in_memory_kb()));
+        set_schema_validation(kb, false);
+        assert!(!is_validating_schema(kb));
+    }
+
+    #[test]
+    fn test_validation_not_inherited() {
+        initialize_kb();
+        set_schema_validation(KnowledgeBase::current(), true);
+        assert!(Tao::archetype().deref().has_flag(SchemaValidated::TYPE_ID));
+        assert!(!Form::archetype().deref().has_flag(SchemaValidated::TYPE_ID));
+        assert!(!Form::new().has_flag(SchemaValidated::TYPE_ID));
+    }
+
+    #[cfg(feature = "thread-safe")]
+    #[test]
+    fn test_validation_shared_between_threads() {
+        crate::tao::initialize_shared_kb();
+        set_schema_validation(KnowledgeBase::GLOBAL, true);
+        let validating = std::thread::spawn(|| is_validating_schema(KnowledgeBase::GLOBAL))
+            .join()
+            .unwrap();
+        assert!(validating);
+    }
+
+    #[test]
+    fn test_check_owner_and_value() {
+        initialize_kb();
+        let owner_type = Form::archetype().individuate_as_archetype();
+        let value_type = Form::archetype().individuate_as_archetype();
+        let attr_type = restricted_attr(owner_type, value_type);
+        let attr = attr_type.individuate_as_form();
+        let good_owner = owner_type.individuate_as_form();
+        let good_value = value_type.individuate_as_form();
+        assert_eq!(check_owner(&attr, &good_owner), Ok(()));
+        assert_eq!(check_value(&attr, &good_value), Ok(()));
+        assert_eq!(
+            check_owner(&attr, &good_value),
+            Err(SchemaError::InvalidOwner {
+                relation: attr.as_form(),
+                owner: good_value,
+                expected: owner_type,
+            })
+        );
+        assert_eq!(
+            check_value(&attr, &good_owner),
+            Err(SchemaError::InvalidValue {
+                attribute: attr.as_form(),
+                value: good_owner,
+                expected: value_type,
+            })
+        );
+    }
+
+    #[test]
+    fn test_unrestricted_accepts_anything() {
+        initialize_kb();
+        let attr = Attribute::new();
+        let owner = Form::new();
+        assert_eq!(check_owner(&attr, &owner), Ok(()));
+        assert_eq!(check_value(&attr, &owner), Ok(()));
+    }
+
+    #[test]
+    fn test_error_display() {
+        initialize_kb();
+        let owner_type = Form::archetype().individuate_as_archetype();
+        let attr = restricted_attr(owner_type, Tao::archetype()).individuate_as_form();
+        let owner = Form::new();
+        assert_eq!(
+            format!("{}", check_owner(&attr, &owner).unwrap_err()),
+            format!(
+                "Relation #{} cannot be owned by #{}, which does not descend from #{}",
+                attr.id(),
+                owner.id(),
+                owner_type.id()
+            )
+        );
+    }
+
+    #[test]
+    fn test_validated_setters() {
+        initialize_kb();
+        set_schema_validation(KnowledgeBase::current(), true);
+        let owner_type = Form::archetype().individuate_as_archetype();
+        let value_type = Form::archetype().individuate_as_archetype();
+        let mut attr = restricted_attr(owner_type, value_type).individuate_as_form();
+        let stranger = Form::new();
+        assert!(attr.try_set_owner(&stranger).is_err());
+        assert!(attr.try_set_value(&stranger).is_err());
+        assert_eq!(attr.owner(), None);
+        assert_eq!(attr.value(), None);
+
+        let owner = owner_type.individuate_as_form();
+        assert_eq!(attr.try_set_owner(&owner), Ok(()));
+        assert_eq!(attr.owner(), Some(owner));
+        assert_eq!(schema_violations(KnowledgeBase::current()), vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot have the value")]
+    fn test_validated_set_value_panics() {
+        initialize_kb();
+        set_schema_validation(KnowledgeBase::current(), true);
+        let value_type = Form::archetype().individuate_as_archetype();
+        let mut attr = restricted_attr(Tao::archetype(), value_type).individuate_as_form();
+        attr.set_value(&Form::new());
+    }
+
+    #[test]
+    fn test_validated_setters_with_conflicting_restrictions() {
+        initialize_kb();
+        set_schema_validation(KnowledgeBase::current(), true);
+        let owner_type1 = Form::archetype().individuate_as_archetype();
+        let owner_type2 = Form::archetype().individuate_as_archetype();
+        let value_type1 = Form::archetype().individuate_as_archetype();
+        let value_type2 = Form::archetype().individuate_as_archetype();
+        let mut attr_type = restricted_attr(owner_type1, value_type1).individuate_as_archetype();
+        attr_type.add_parent(restricted_attr(owner_type2, value_type2).into());
+        let owner_conflict = |form: Form| SchemaError::ConflictingInheritance {
+            form,
+            attribute: OwnerArchetype::archetype().into(),
+            first: owner_type1.as_form(),
+            second: owner_type2.as_form(),
+        };
+        let value_conflict = |form: Form| SchemaError::ConflictingInheritance {
+            form,
+            attribute: ValueArchetype::archetype().into(),
+            first: value_type1.as_form(),
+            second: value_type2.as_form(),
+        };
+
+        let mut attr = attr_type.individuate_as_form();
+        let owner = owner_type1.individuate_as_form();
+        let value = value_type1.individuate_as_form();
+        assert_eq!(
+            attr.try_set_owner(&owner),
+            Err(owner_conflict(attr.as_form()))
+        );
+        assert_eq!(
+            attr.try_set_value(&value),
+            Err(value_conflict(attr.as_form()))
+        );
+
+        let mut owner_archetype = owner_type1.individuate_as_archetype();
+        assert_eq!(
+            owner_archetype.try_set_attribute(&attr_type, &value),
+            Err(owner_conflict(attr_type.as_form()))
+        );
+        assert_eq!(
+            owner_archetype.try_set_default_attribute(&attr_type, &value),
+            Err(owner_conflict(attr_type.as_form()))
+        );
+    }
+
+    #[test]
+    fn test_unvalidated_setters() {
+        initialize_kb();
+        let owner_type = Form::archetype().individuate_as_archetype();
+        let mut attr = restricted_attr(owner_type, Tao::archetype()).individuate_as_form();
+        let stranger = Form::new();
+        assert_eq!(attr.try_set_owner(&stranger), Ok(()));
+        assert_eq!(attr.owner(), Some(stranger));
+    }
+
+    #[test]
+    fn test_schema_violations() {
+        initialize_kb();
+        let owner_type = Form::archetype().individuate_as_archetype();
+        let value_type = Form::archetype().individuate_as_archetype();
+        let attr_type = restricted_attr(owner_type, value_type);
+        let mut good_attr = attr_type.individuate_as_form();
+        good_attr.set_owner(&owner_type.individuate_as_form());
+        good_attr.set_value(&value_type.individuate_as_form());
+        assert_eq!(schema_violations(KnowledgeBase::current()), vec![]);
+
+        let mut bad_attr = attr_type.individuate_as_form();
+        let stranger = Form::new();
+        bad_attr.set_owner(&stranger);
+        bad_attr.set_value(&stranger);
+        assert_eq!(
+            schema_violations(KnowledgeBase::current()),
+            vec![
+                SchemaError::InvalidOwner {
+                    relation: bad_attr.as_form(),
+                    owner: stranger,
+                    expected: owner_type,
+                },
+                SchemaError::InvalidValue {
+                    attribute: bad_attr.as_form(),
+                    value: stranger,
+                    expected: value_type,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multiple_values_violation() {
+        initialize_kb();
+        let attr_type = Attribute::archetype().individuate_as_archetype();
+        let attr = attr_type.individuate_as_form();
+        let mut node = *attr.deref();
+        // bypass set_value, which would have replaced the first value
+        node.add_outgoing(Value::TYPE_ID, &Form::new());
+        node.add_outgoing(Value::TYPE_ID, &Form::new());
+        assert_eq!(
+            schema_violations(KnowledgeBase::current()),
+            vec![SchemaError::MultipleValues {
+                attribute: attr.as_form(),
+                count: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_schema_violations_other_kb() {
+        initialize_kb();
+        let owner_type = Form::archetype().individuate_as_archetype();
+        let mut bad_attr = restricted_attr(owner_type, Tao::archetype()).individuate_as_form();
+        bad_attr.set_owner(&Form::new());
+        assert_eq!(schema_violations(KnowledgeBase::current()).len(), 1);
+        let kb = new_in_memory_kb();
+        assert_eq!(schema_violations(kb), vec![]);
+    }
+}
@@ -0,0 +1,517 @@
+//! Derive macros for defining new [Yin](https://crates.io/crates/zamm_yin) archetypes in
+//! downstream crates, without having to run [Yang](https://crates.io/crates/zamm_yang) over a
+//! whole schema.
+//!
+//! Deriving `Archetype` on a struct that wraps a single `FinalNode` implements everything Yin
+//! expects of a Form: `ArchetypeTrait`, `FormTrait`, `Debug`, `Deref` and `DerefMut` to the
+//! wrapped node, conversions from node IDs, nodes and internal names, and a conversion into the
+//! parent type. Because `ArchetypeTrait` requires `Ord`, the struct should also derive `Copy`,
+//! `Clone`, `Hash`, `PartialEq`, `Eq`, `PartialOrd` and `Ord`, just like Yin's own Forms do.
+//!
+//! The struct gets configured with an `#[archetype(...)]` attribute:
+//!
+//!  * `parent = "Form"` (required) is the type this archetype inherits from.
+//!  * `offset = 1` places this archetype's ID at `YIN_MAX_ID + offset`. Offsets should start at 1
+//!    and be contiguous, because `initialize_type!` expects each type's ID to be the next one
+//!    added to the KB. The parent must then have a fixed ID as well. Without an offset, the
+//!    archetype instead gets its ID allocated at runtime by a generated `register` function,
+//!    which keeps it from colliding with types from other crates.
+//!  * `name = "good-boy"` overrides the internal name, which otherwise defaults to the struct
+//!    name in kebab case.
+//!  * `attribute` implements `AttributeTrait` as well, for archetypes that descend from
+//!    `Attribute`. `owner_form = "Dog"` and `value_form = "Form"` set the Rust types of the owner
+//!    and value, both of which default to `Form` and imply `attribute`.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use zamm_yin::graph::{Graph, InjectionGraph};
+//! use zamm_yin::initialize_type;
+//! use zamm_yin::node_wrappers::{CommonNodeTrait, FinalNode};
+//! use zamm_yin::tao::archetype::ArchetypeTrait;
+//! use zamm_yin::tao::form::{Form, FormTrait};
+//! use zamm_yin::tao::relation::attribute::{Attribute, AttributeTrait, Inherits};
+//! use zamm_yin::tao::{initialize_kb, YIN_MAX_ID};
+//! use zamm_yin_derive::Archetype;
+//!
+//! #[derive(Archetype, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+//! #[archetype(parent = "Form", offset = 1)]
+//! pub struct Animal {
+//!     base: FinalNode,
+//! }
+//!
+//! #[derive(Archetype, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+//! #[archetype(parent = "Animal", offset = 2, name = "good-boy")]
+//! pub struct Dog(FinalNode);
+//!
+//! #[derive(Archetype, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+//! #[archetype(parent = "Attribute", offset = 3, owner_form = "Dog")]
+//! pub struct Nickname(FinalNode);
+//!
+//! initialize_kb();
+//! let mut ig = InjectionGraph::new();
+//! initialize_type!(ig, (Animal, Dog, Nickname));
+//!
+//! assert_eq!(Dog::TYPE_ID, YIN_MAX_ID + 2);
+//! assert_eq!(Animal::archetype().internal_name().as_deref(), Some("animal"));
+//! assert_eq!(Dog::archetype().internal_name().as_deref(), Some("good-boy"));
+//!
+//! let dog = Dog::new();
+//! assert!(dog.has_ancestor(Animal::archetype()));
+//! assert_eq!(Animal::from(dog).id(), dog.id());
+//!
+//! let mut nickname = Nickname::new();
+//! nickname.set_owner(&dog);
+//! assert_eq!(nickname.owner(), Some(dog));
+//! ```
+//!
+//! Archetypes without an offset have to be registered in each KB before they can be used there,
+//! after their parent has been. Their IDs get looked up in whichever KB is current:
+//!
+//! ```rust
+//! use zamm_yin::node_wrappers::{CommonNodeTrait, FinalNode};
+//! use zamm_yin::tao::archetype::ArchetypeTrait;
+//! use zamm_yin::tao::form::{Form, FormTrait};
+//! use zamm_yin::tao::{initialize_kb, new_in_memory_kb};
+//! use zamm_yin_derive::Archetype;
+//!
+//! #[derive(Archetype, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+//! #[archetype(parent = "Form")]
+//! pub struct Pet(FinalNode);
+//!
+//! #[derive(Archetype, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+//! #[archetype(parent = "Pet")]
+//! pub struct Cat(FinalNode);
+//!
+//! initialize_kb();
+//! let pet_type = Pet::register();
+//! let cat_type = Cat::register();
+//! assert_eq!(Cat::archetype(), cat_type);
+//! assert_eq!(cat_type.parents(), vec![pet_type]);
+//! assert!(Cat::new().has_ancestor(pet_type));
+//!
+//! let kb = new_in_memory_kb();
+//! let other_cat_type = kb.with_default(|| {
+//!     Pet::register();
+//!     Cat::register()
+//! });
+//! assert_eq!(kb.with_default(Cat::archetype), other_cat_type);
+//! assert_eq!(Cat::archetype(), cat_type);
+//! ```
+//!
+//! Archetypes with an offset can't descend from archetypes without one, because the parent's ID
+//! isn't known until runtime:
+//!
+//! ```compile_fail
+//! use zamm_yin::node_wrappers::FinalNode;
+//! use zamm_yin::tao::form::Form;
+//! use zamm_yin_derive::Archetype;
+//!
+//! #[derive(Archetype, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+//! #[archetype(parent = "Form")]
+//! pub struct Pet(FinalNode);
+//!
+//! #[derive(Archetype, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+//! #[archetype(parent = "Pet", offset = 1)]
+//! pub struct Cat(FinalNode);
+//! ```
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Fields, Lit, LitStr, Member, Meta, NestedMeta,
+    Path, Result,
+};
+
+/// Implement `ArchetypeTrait`, `FormTrait` and friends for a struct wrapping a `FinalNode`. See
+/// the crate documentation for the attributes this takes.
+#[proc_macro_derive(Archetype, attributes(archetype))]
+pub fn derive_archetype(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// Settings read from the `#[archetype(...)]` attribute.
+struct ArchetypeOptions {
+    parent: Path,
+    /// Where this archetype's ID lies after `YIN_MAX_ID`, if it gets fixed at compile time.
+    offset: Option<usize>,
+    name: String,
+    /// The owner and value forms, if this archetype is an attribute.
+    attribute: Option<(Path, Path)>,
+}
+
+/// Read the `#[archetype(...)]` attribute off of the derived struct.
+fn parse_options(input: &DeriveInput) -> Result<ArchetypeOptions> {
+    let mut parent = None;
+    let mut offset = None;
+    let mut name = None;
+    let mut is_attribute = false;
+    let mut owner_form = None;
+    let mut value_form = None;
+
+    for attr in input.attrs.iter().filter(|a| a.path.is_ident("archetype")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            other => return Err(Error::new_spanned(other, "expected #[archetype(...)]")),
+        };
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("attribute") => {
+                    is_attribute = true;
+                }
+                NestedMeta::Meta(Meta::NameValue(pair)) => {
+                    let key = pair.path.get_ident().map(|i| i.to_string());
+                    match (key.as_deref(), &pair.lit) {
+                        (Some("parent"), Lit::Str(s)) => parent = Some(s.parse::<Path>()?),
+                        (Some("offset"), Lit::Int(i)) => {
+                            let value = i.base10_parse::<usize>()?;
+                            if value == 0 {
+                                return Err(Error::new_spanned(
+                                    i,
+                                    "offset must be at least 1, because YIN_MAX_ID is taken by Yin",
+                                ));
+                            }
+                            offset = Some(value);
+                        }
+                        (Some("name"), Lit::Str(s)) => name = Some(s.value()),
+                        (Some("owner_form"), Lit::Str(s)) => owner_form = Some(s.parse::<Path>()?),
+                        (Some("value_form"), Lit::Str(s)) => value_form = Some(s.parse::<Path>()?),
+                        _ => {
+                            return Err(Error::new_spanned(pair, "unrecognized archetype setting"))
+                        }
+                    }
+                }
+                other => return Err(Error::new_spanned(other, "unrecognized archetype setting")),
+            }
+        }
+    }
+
+    let missing = |setting: &str| {
+        Error::new(
+            Span::call_site(),
+            format!("missing `{}` in #[archetype(...)]", setting),
+        )
+    };
+    let attribute = if is_attribute || owner_form.is_some() || value_form.is_some() {
+        let form: Path = syn::parse_quote!(::zamm_yin::tao::form::Form);
+        Some((
+            owner_form.unwrap_or_else(|| form.clone()),
+            value_form.unwrap_or(form),
+        ))
+    } else {
+        None
+    };
+    Ok(ArchetypeOptions {
+        parent: parent.ok_or_else(|| missing("parent"))?,
+        offset,
+        name: name.unwrap_or_else(|| kebab_case(&input.ident.to_string())),
+        attribute,
+    })
+}
+
+/// The field holding the struct's `FinalNode`, which must be the only field it has.
+fn base_field(input: &DeriveInput) -> Result<Member> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "Archetype can only be derived for structs",
+            ))
+        }
+    };
+    match fields {
+        Fields::Named(named) if named.named.len() == 1 => {
+            Ok(Member::Named(named.named[0].ident.clone().unwrap()))
+        }
+        Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => Ok(Member::Unnamed(0.into())),
+        _ => Err(Error::new_spanned(
+            fields,
+            "Archetype structs must have exactly one field, holding a FinalNode",
+        )),
+    }
+}
+
+/// Turn a type name such as `OwnerArchetype` into a node name such as `owner-archetype`. A run of
+/// capitals counts as a single word, so that `HTTPServer` becomes `http-server`.
+fn kebab_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut result = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let after_lower = !chars[i - 1].is_uppercase();
+            let ends_acronym = chars[i - 1].is_uppercase()
+                && matches!(chars.get(i + 1), Some(next) if next.is_lowercase());
+            if after_lower || ends_acronym {
+                result.push('-');
+            }
+        }
+        result.extend(c.to_lowercase());
+    }
+    result
+}
+
+/// Generate the impls for the derived struct.
+fn expand(input: &DeriveInput) -> Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "Archetype cannot be derived for generic structs",
+        ));
+    }
+    let options = parse_options(input)?;
+    let base = base_field(input)?;
+    let ident = &input.ident;
+    let debug_name = LitStr::new(&ident.to_string(), ident.span());
+    let parent = &options.parent;
+    let type_name = &options.name;
+    let construct = match &base {
+        Member::Named(field) => quote!(Self { #field: f }),
+        Member::Unnamed(_) => quote!(Self(f)),
+    };
+
+    let (type_id, registration) = match options.offset {
+        Some(offset) => {
+            let message = format!("{} has a fixed ID, so its parent must have one too", ident);
+            let registration = quote! {
+                const _: () = ::std::assert!(
+                    <#parent as ::zamm_yin::tao::archetype::ArchetypeTrait>::TYPE_ID
+                        != ::zamm_yin::tao::DYNAMIC_TYPE_ID,
+                    #message
+                );
+            };
+            let type_id = quote! {
+                const TYPE_ID: usize = ::zamm_yin::tao::YIN_MAX_ID + #offset;
+            };
+            (type_id, registration)
+        }
+        None => {
+            let registration = quote! {
+                impl #ident {
+                    /// Register this type with the current KB, under the archetype that its
+                    /// parent type has in that KB.
+                    pub fn register() -> ::zamm_yin::tao::archetype::Archetype {
+                        let parent = *<#parent as ::zamm_yin::tao::archetype::ArchetypeTrait>::archetype();
+                        ::zamm_yin::tao::register_type(
+                            <Self as ::zamm_yin::tao::archetype::ArchetypeTrait>::TYPE_NAME,
+                            ::zamm_yin::tao::archetype::Archetype::from(parent),
+                        )
+                    }
+                }
+            };
+            let type_id = quote! {
+                const TYPE_ID: usize = ::zamm_yin::tao::DYNAMIC_TYPE_ID;
+
+                fn archetype_id() -> usize {
+                    let kb = ::zamm_yin::graph::KnowledgeBase::current();
+                    match ::zamm_yin::tao::registered_type_id(kb, Self::TYPE_NAME) {
+                        Some(id) => id,
+                        None => panic!("Type {} has not been registered with {:?} yet", Self::TYPE_NAME, kb),
+                    }
+                }
+            };
+            (type_id, registration)
+        }
+    };
+
+    let attribute_impl = options.attribute.as_ref().map(|(owner_form, value_form)| {
+        quote! {
+            impl ::zamm_yin::tao::relation::attribute::AttributeTrait for #ident {
+                type OwnerForm = #owner_form;
+                type ValueForm = #value_form;
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl ::std::fmt::Debug for #ident {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                ::zamm_yin::node_wrappers::debug_wrapper(#debug_name, &self.#base, f)
+            }
+        }
+
+        impl ::std::convert::From<usize> for #ident {
+            fn from(id: usize) -> Self {
+                let f = ::zamm_yin::node_wrappers::FinalNode::from(id);
+                #construct
+            }
+        }
+
+        impl ::std::convert::From<::zamm_yin::node_wrappers::FinalNode> for #ident {
+            fn from(f: ::zamm_yin::node_wrappers::FinalNode) -> Self {
+                #construct
+            }
+        }
+
+        impl<'a> ::std::convert::TryFrom<&'a str> for #ident {
+            type Error = String;
+
+            fn try_from(name: &'a str) -> ::std::result::Result<Self, Self::Error> {
+                <::zamm_yin::node_wrappers::FinalNode as ::std::convert::TryFrom<&'a str>>::try_from(name)
+                    .map(|f| #construct)
+            }
+        }
+
+        impl ::zamm_yin::tao::archetype::ArchetypeTrait for #ident {
+            type ArchetypeForm =
+                <#parent as ::zamm_yin::tao::archetype::ArchetypeTrait>::ArchetypeForm;
+            type Form = #ident;
+
+            const TYPE_NAME: &'static str = #type_name;
+            const PARENT_TYPE_ID: usize =
+                <#parent as ::zamm_yin::tao::archetype::ArchetypeTrait>::TYPE_ID;
+
+            #type_id
+        }
+
+        impl ::std::ops::Deref for #ident {
+            type Target = ::zamm_yin::node_wrappers::FinalNode;
+
+            fn deref(&self) -> &Self::Target {
+                &self.#base
+            }
+        }
+
+        impl ::std::ops::DerefMut for #ident {
+            fn deref_mut(&mut self) -> &mut Self::Target {
+                &mut self.#base
+            }
+        }
+
+        impl ::zamm_yin::tao::form::FormTrait for #ident {}
+
+        impl ::std::convert::From<#ident> for #parent {
+            fn from(this: #ident) -> #parent {
+                #parent::from(this.#base)
+            }
+        }
+
+        #registration
+
+        #attribute_impl
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn error_of(input: DeriveInput) -> String {
+        match expand(&input) {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_kebab_case() {
+        assert_eq!(kebab_case("Dog"), "dog");
+        assert_eq!(kebab_case("OwnerArchetype"), "owner-archetype");
+        assert_eq!(kebab_case("HTTPServer"), "http-server");
+        assert_eq!(kebab_case("ServeHTTP"), "serve-http");
+        assert_eq!(kebab_case("UTF8Text"), "utf8-text");
+    }
+
+    #[test]
+    fn test_acronym_name() {
+        let input: DeriveInput = parse_quote! {
+            #[archetype(parent = "Form")]
+            struct HTTPServer(FinalNode);
+        };
+        assert_eq!(parse_options(&input).unwrap().name, "http-server");
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let input: DeriveInput = parse_quote! {
+            #[archetype(parent = "Form", offset = 2)]
+            struct GoodBoy(FinalNode);
+        };
+        let options = parse_options(&input).unwrap();
+        let form: Path = parse_quote!(Form);
+        assert_eq!(options.parent, form);
+        assert_eq!(options.offset, Some(2));
+        assert_eq!(options.name, "good-boy");
+        assert!(options.attribute.is_none());
+    }
+
+    #[test]
+    fn test_parse_attribute_options() {
+        let input: DeriveInput = parse_quote! {
+            #[archetype(parent = "Attribute", offset = 1, name = "nick", owner_form = "Dog")]
+            struct Nickname(FinalNode);
+        };
+        let options = parse_options(&input).unwrap();
+        let dog: Path = parse_quote!(Dog);
+        let form: Path = parse_quote!(::zamm_yin::tao::form::Form);
+        assert_eq!(options.offset, Some(1));
+        assert_eq!(options.name, "nick");
+        assert_eq!(options.attribute, Some((dog, form)));
+    }
+
+    #[test]
+    fn test_base_field() {
+        let named: DeriveInput = parse_quote!(
+            struct Dog {
+                node: FinalNode,
+            }
+        );
+        let unnamed: DeriveInput = parse_quote!(
+            struct Dog(FinalNode);
+        );
+        assert_eq!(base_field(&named).unwrap(), parse_quote!(node));
+        assert_eq!(base_field(&unnamed).unwrap(), Member::Unnamed(0.into()));
+    }
+
+    #[test]
+    fn test_parse_dynamic_options() {
+        let input: DeriveInput = parse_quote! {
+            #[archetype(parent = "Form")]
+            struct Dog(FinalNode);
+        };
+        assert_eq!(parse_options(&input).unwrap().offset, None);
+    }
+
+    #[test]
+    fn test_missing_settings() {
+        assert_eq!(
+            error_of(parse_quote! {
+                #[archetype(offset = 1)]
+                struct Dog(FinalNode);
+            }),
+            "missing `parent` in #[archetype(...)]"
+        );
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        assert_eq!(
+            error_of(parse_quote! {
+                #[archetype(parent = "Form", offset = 0)]
+                struct Dog(FinalNode);
+            }),
+            "offset must be at least 1, because YIN_MAX_ID is taken by Yin"
+        );
+        assert_eq!(
+            error_of(parse_quote! {
+                #[archetype(parent = "Form", offset = 1, colour = "brown")]
+                struct Dog(FinalNode);
+            }),
+            "unrecognized archetype setting"
+        );
+        assert_eq!(
+            error_of(parse_quote! {
+                #[archetype(parent = "Form", offset = 1)]
+                struct Dog(FinalNode, usize);
+            }),
+            "Archetype structs must have exactly one field, holding a FinalNode"
+        );
+    }
+}